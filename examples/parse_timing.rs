@@ -0,0 +1,31 @@
+extern crate loxrust;
+
+use std::time::{Duration, Instant};
+use loxrust::scanner::Scanner;
+use loxrust::parser::Parser;
+
+// Times `Parser::parse` on a large generated file. Run with `cargo run --release --example parse_timing`.
+
+const STATEMENTS: usize = 20000;
+const RUNS: usize = 10;
+
+fn main() {
+    let mut source = String::new();
+    for i in 0..STATEMENTS {
+        source.push_str(format!("var v{} = (1 + {} * 3) / 5 == 6 and ! (7 > 8) or -9 <= {};\n", i, i, i).as_str());
+    }
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens();
+
+    let mut fastest = Duration::from_secs(u64::MAX);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let stmts = Parser::new(tokens).parse().expect("generated source should parse");
+        let elapsed = start.elapsed();
+        assert_eq!(stmts.len(), STATEMENTS);
+        if elapsed < fastest {
+            fastest = elapsed;
+        }
+    }
+    println!("Parsed {} tokens, fastest of {} runs: {:?}", tokens.len(), RUNS, fastest);
+}
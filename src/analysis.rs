@@ -0,0 +1,26 @@
+use ast::Stmt;
+use resolver::Resolver;
+use typecheck::TypeChecker;
+
+/// What the static passes found in a program, before it runs. A program with errors shouldn't be
+/// run, but one with only warnings can be.
+pub struct Analysis {
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Runs the static passes over a script or module: the resolver, and then the type checker if
+/// the resolver didn't find any errors.
+pub fn analyze(stmts: &[Stmt]) -> Analysis {
+    let mut resolver = Resolver::new();
+    resolver.resolve(stmts);
+    if !resolver.errors.is_empty() {
+        return Analysis { warnings: resolver.warnings, errors: resolver.errors }
+    }
+
+    let mut checker = TypeChecker::new();
+    checker.check(stmts);
+    let mut warnings = resolver.warnings;
+    warnings.extend(checker.warnings);
+    Analysis { warnings, errors: checker.errors }
+}
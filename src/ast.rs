@@ -1,13 +1,131 @@
-use scanner::Token;
 use scanner::Literal;
 use core::borrow::{Borrow};
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::fmt;
+use std::rc::Rc;
+
+/// Identifies a single node in a parsed tree. Ids are handed out by the `Parser` and are unique
+/// within one parse, so passes can record facts about a node without relying on the structural
+/// equality of its subtree.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Hash)]
+pub struct NodeId(pub usize);
+
+/// A side table keyed by `NodeId`, used by analyses to attach data to `Exp` and `Stmt` nodes.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct NodeMap<T> {
+    map: HashMap<NodeId, T>
+}
+
+impl<T> NodeMap<T> {
+    pub fn new() -> NodeMap<T> {
+        NodeMap {
+            map: HashMap::new()
+        }
+    }
+
+    pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+        self.map.insert(id, value)
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.map.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.map.get_mut(&id)
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.map.contains_key(&id)
+    }
+
+    pub fn remove(&mut self, id: NodeId) -> Option<T> {
+        self.map.remove(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, NodeId, T> {
+        self.map.iter()
+    }
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> NodeMap<T> {
+        NodeMap::new()
+    }
+}
+
+/// The location in the source that a node was parsed from.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub struct Span {
+    pub line: usize
+}
 
 #[derive(Eq, PartialEq)]
 #[derive(Debug)]
 #[derive(Clone)]
 pub struct VarDecl {
-    pub identifier : Identifier,
+    pub id: NodeId,
+    /// A single name, or a list or map pattern like `[a, b]` destructuring the value into names.
+    pub pattern : Pattern,
     pub exp : Option<Exp>,
+    /// Declared with `const` rather than `var`, so it can't be assigned to.
+    pub constant: bool,
+    pub annotation: Option<TypeAnnotation>,
+    pub span: Span,
+}
+
+/// A type written after a `:` in a declaration, like the `Number` in `var x: Number = 1;`. Only
+/// the `typecheck` module looks at them; they don't change how a program runs.
+#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum TypeAnnotation {
+    /// Any value at all, the same as leaving the annotation out.
+    Any,
+    Nil,
+    Bool,
+    Number,
+    String,
+    List,
+    Map,
+    Function,
+}
+
+impl TypeAnnotation {
+    pub fn from_name(name: &str) -> Option<TypeAnnotation> {
+        match name {
+            "Any" => Some(TypeAnnotation::Any),
+            "Nil" => Some(TypeAnnotation::Nil),
+            "Bool" => Some(TypeAnnotation::Bool),
+            "Number" => Some(TypeAnnotation::Number),
+            "String" => Some(TypeAnnotation::String),
+            "List" => Some(TypeAnnotation::List),
+            "Map" => Some(TypeAnnotation::Map),
+            "Function" => Some(TypeAnnotation::Function),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
 }
 
 #[derive(Eq, PartialEq)]
@@ -21,8 +139,285 @@ pub struct Identifier (pub String);
 #[derive(Clone)]
 pub enum Stmt {
     VarDecl(VarDecl),
-    Statement(Exp),
-    PrintStmt(Exp),
+    Statement(ExpStmt),
+    PrintStmt(PrintStmt),
+    Block(Block),
+    FunDecl(FunDecl),
+    Return(ReturnStmt),
+    If(IfStmt),
+    While(WhileStmt),
+    ForIn(ForInStmt),
+    Break(BreakStmt),
+    Continue(ContinueStmt),
+    Match(MatchStmt),
+    Import(ImportStmt),
+    Export(ExportStmt),
+    Throw(ThrowStmt),
+    Try(TryStmt),
+    Yield(YieldStmt),
+    Enum(EnumDecl),
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ThrowStmt {
+    pub id: NodeId,
+    pub exp: Exp,
+    pub span: Span,
+}
+
+/// `yield exp;`, which suspends the generator whose body it's in, producing `exp` (or nil) from
+/// the call to `next()` that resumed it.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct YieldStmt {
+    pub id: NodeId,
+    pub exp: Option<Exp>,
+    pub span: Span,
+}
+
+/// `enum Name { A, B }`, which binds `Name` to a namespace of distinct variants, `Name.A` and
+/// `Name.B`, each equal only to itself.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct EnumDecl {
+    pub id: NodeId,
+    pub name: Identifier,
+    pub variants: Vec<Identifier>,
+    pub span: Span,
+}
+
+/// `try { } catch (name) { } finally { }`, where at least one of the `catch` and `finally` clauses
+/// is present. `finally` runs however the rest of the statement finishes.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct TryStmt {
+    pub id: NodeId,
+    pub body: Vec<Stmt>,
+    pub catch: Option<CatchClause>,
+    pub finally: Option<Vec<Stmt>>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct CatchClause {
+    pub name: Identifier,
+    pub body: Vec<Stmt>,
+}
+
+/// `import "path" as name;`, which runs the module at `path` the first time it's imported, and
+/// binds `name` to an object exposing its exports.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ImportStmt {
+    pub id: NodeId,
+    pub path: String,
+    pub name: Identifier,
+    pub span: Span,
+}
+
+/// `export` before a top-level `var`, `fun` or `enum` declaration, making it visible to importers.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ExportStmt {
+    pub id: NodeId,
+    pub decl: Box<Stmt>,
+}
+
+impl ExportStmt {
+    /// The names the exported declaration binds.
+    pub fn names(&self) -> Vec<&Identifier> {
+        match self.decl.as_ref() {
+            Stmt::VarDecl(decl) => decl.pattern.bindings(),
+            Stmt::FunDecl(decl) => vec![&decl.name],
+            Stmt::Enum(decl) => vec![&decl.name],
+            _ => unreachable!("the parser only exports declarations"),
+        }
+    }
+}
+
+/// `match (subject) { pattern => stmt ... }`, which runs the first arm whose pattern matches.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct MatchStmt {
+    pub id: NodeId,
+    pub subject: Exp,
+    pub arms: Vec<MatchArm<Stmt>>,
+    pub span: Span,
+}
+
+/// `pattern if guard => body`. Variables bound by the pattern are in scope in the guard and body.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct MatchArm<T> {
+    pub pattern: Pattern,
+    pub guard: Option<Exp>,
+    pub body: T,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Pattern {
+    /// `_`, which matches anything without binding it.
+    Wildcard,
+    /// A number, string, boolean or nil, matching values equal to it.
+    Literal(Literal),
+    /// A name, which matches anything and binds it to the name.
+    Binding(Identifier),
+    /// `[p, q]`, matching lists of exactly that length whose elements match.
+    List(Vec<Pattern>),
+    /// `{"key": p}`, matching maps which have all of the keys, with values that match. Other keys
+    /// are ignored.
+    Map(Vec<(Literal, Pattern)>),
+    /// `Name.A`, matching only that variant of the enum `Name`.
+    Variant(Identifier, Identifier),
+}
+
+impl Pattern {
+    /// The names the pattern binds, in the order they're written.
+    pub fn bindings(&self) -> Vec<&Identifier> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Variant(..) => vec![],
+            Pattern::Binding(name) => vec![name],
+            Pattern::List(elements) => elements.iter().flat_map(Pattern::bindings).collect(),
+            Pattern::Map(entries) => entries.iter().flat_map(|(_, value)| value.bindings()).collect(),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct IfStmt {
+    pub id: NodeId,
+    pub condition: Exp,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+}
+
+/// A `while` loop, which is also what `for` loops are desugared into. `increment` is only set for
+/// `for` loops, and runs after every iteration of the body, including ones ended by `continue`.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct WhileStmt {
+    pub id: NodeId,
+    pub condition: Exp,
+    pub body: Box<Stmt>,
+    pub increment: Option<Exp>,
+}
+
+/// `for (name in iterable) body`, which runs `body` for each value produced by the iterator that
+/// `iterable.iterator()` returns, binding `name` to the value in a new scope each time.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ForInStmt {
+    pub id: NodeId,
+    pub name: Identifier,
+    pub iterable: Exp,
+    pub body: Box<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct BreakStmt {
+    pub id: NodeId,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ContinueStmt {
+    pub id: NodeId,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Block {
+    pub id: NodeId,
+    pub stmts: Vec<Stmt>,
+}
+
+/// The parameters and body shared by `fun` declarations and lambda expressions. It's reference
+/// counted so that each closure created from it at runtime doesn't need its own copy of the body.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+pub struct Function {
+    /// The parameter names. When the function has a rest parameter, it's the last of them.
+    pub params: Vec<Identifier>,
+    /// The annotation on each parameter, in the same order as `params`.
+    pub param_types: Vec<Option<TypeAnnotation>>,
+    /// The default value of each parameter, in the same order as `params`. Parameters with
+    /// defaults all come after those without, and defaults are evaluated at call time, in a scope
+    /// holding the parameters before them.
+    pub defaults: Vec<Option<Exp>>,
+    pub return_type: Option<TypeAnnotation>,
+    pub body: Vec<Stmt>,
+    /// Whether the body contains a `yield`, making calls produce a generator rather than run it.
+    pub generator: bool,
+    /// Whether the last parameter is written `...name`, collecting any arguments after the others
+    /// into a list. Its annotation applies to each of those arguments.
+    pub rest: bool,
+    pub span: Span,
+}
+
+impl Function {
+    /// How many parameters have no default and aren't the rest parameter, so must be passed.
+    pub fn required(&self) -> usize {
+        let positional = self.params.len() - self.rest as usize;
+        self.defaults[..positional].iter().take_while(|default| default.is_none()).count()
+    }
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct FunDecl {
+    pub id: NodeId,
+    pub name: Identifier,
+    pub function: Rc<Function>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ReturnStmt {
+    pub id: NodeId,
+    pub exp: Option<Exp>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ExpStmt {
+    pub id: NodeId,
+    pub exp: Exp,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct PrintStmt {
+    pub id: NodeId,
+    pub exp: Exp,
 }
 
 #[derive(Eq, PartialEq)]
@@ -33,21 +428,334 @@ pub enum Exp {
     GroupingExp(GroupingExp),
     UnaryExp(UnaryExp),
     LiteralExp(LiteralExp),
+    LogicalExp(LogicalExp),
+    ConditionalExp(ConditionalExp),
+    CommaExp(CommaExp),
+    CallExp(CallExp),
+    LambdaExp(LambdaExp),
+    AssignExp(AssignExp),
+    ListExp(ListExp),
+    IndexExp(IndexExp),
+    SetIndexExp(SetIndexExp),
+    GetExp(GetExp),
+    MapExp(MapExp),
+    CompoundAssignExp(CompoundAssignExp),
+    IncrementExp(IncrementExp),
+    MatchExp(MatchExp),
+    DestructureExp(DestructureExp),
+}
+
+/// `[a, b] = value`, which assigns the parts of `value` to existing variables. Evaluates to
+/// `value`.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct DestructureExp {
+    pub id: NodeId,
+    pub pattern: Pattern,
+    pub value: Box<Exp>,
+    pub span: Span
+}
+
+/// The expression form of `match`, whose arms are expressions separated by commas.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct MatchExp {
+    pub id: NodeId,
+    pub subject: Box<Exp>,
+    pub arms: Vec<MatchArm<Exp>>,
+    pub span: Span
+}
+
+/// Somewhere a value can be both read from and assigned to.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum AssignTarget {
+    Variable(Identifier),
+    Index { object: Box<Exp>, index: Box<Exp> },
+}
+
+/// `target op= value`, which evaluates the parts of `target` only once.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct CompoundAssignExp {
+    pub id: NodeId,
+    pub target: AssignTarget,
+    pub operator: BinaryOp,
+    pub value: Box<Exp>,
+    pub span: Span
+}
+
+/// `++target`, `target--` and so on, where `operator` is `Plus` or `Minus`. The prefix forms
+/// produce the updated value, and the postfix forms the value from before the update.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct IncrementExp {
+    pub id: NodeId,
+    pub target: AssignTarget,
+    pub operator: BinaryOp,
+    pub postfix: bool,
+    pub span: Span
+}
+
+/// `{key: value, ...}`, which evaluates to a new map.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct MapExp {
+    pub id: NodeId,
+    pub entries: Vec<(Exp, Exp)>,
+    pub span: Span
+}
+
+/// `[a, b, c]`, which evaluates to a new list.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct ListExp {
+    pub id: NodeId,
+    pub elements: Vec<Element>,
+    pub span: Span
+}
+
+/// One of the comma-separated expressions in a list literal or a call's arguments.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum Element {
+    Single(Exp),
+    /// `...xs`, which stands for each of the values iterating over `xs` produces.
+    Spread(Exp),
+}
+
+impl Element {
+    pub fn exp(&self) -> &Exp {
+        match self {
+            Element::Single(exp) | Element::Spread(exp) => exp,
+        }
+    }
+}
+
+/// `object[index]`
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct IndexExp {
+    pub id: NodeId,
+    pub object: Box<Exp>,
+    pub index: Box<Exp>,
+    pub span: Span
+}
+
+/// `object[index] = value`
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct SetIndexExp {
+    pub id: NodeId,
+    pub object: Box<Exp>,
+    pub index: Box<Exp>,
+    pub value: Box<Exp>,
+    pub span: Span
+}
+
+/// `object.name`, which looks up one of the object's methods. When `optional`, it's written
+/// `object?.name` and a nil object makes it, along with any calls, indexes and property accesses
+/// chained after it, produce nil.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct GetExp {
+    pub id: NodeId,
+    pub object: Box<Exp>,
+    pub name: Identifier,
+    pub optional: bool,
+    pub span: Span
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct AssignExp {
+    pub id: NodeId,
+    pub name: Identifier,
+    pub value: Box<Exp>,
+    pub span: Span
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum BinaryOp {
+    Minus,
+    Plus,
+    Slash,
+    Star,
+    /// The remainder of floor division, so it takes the sign of the divisor: `-7 % 3` is `2`.
+    Percent,
+    StarStar,
+    /// Division rounding towards negative infinity, where `/` rounds towards zero.
+    TildeSlash,
+    Ampersand,
+    Pipe,
+    Caret,
+    LessLess,
+    /// An arithmetic shift, so the sign is kept.
+    GreaterGreater,
+    BangEqual,
+    EqualEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    /// A range excluding its end.
+    DotDot,
+    /// A range including its end.
+    DotDotEqual,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum UnaryOp {
+    Bang,
+    Minus,
+    Tilde,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum LogicalOp {
+    And,
+    Or,
+    /// `??`, which only evaluates its right operand when the left one is nil.
+    QuestionQuestion,
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lexeme = match self {
+            BinaryOp::Minus => "-",
+            BinaryOp::Plus => "+",
+            BinaryOp::Slash => "/",
+            BinaryOp::Star => "*",
+            BinaryOp::Percent => "%",
+            BinaryOp::StarStar => "**",
+            BinaryOp::TildeSlash => "~/",
+            BinaryOp::Ampersand => "&",
+            BinaryOp::Pipe => "|",
+            BinaryOp::Caret => "^",
+            BinaryOp::LessLess => "<<",
+            BinaryOp::GreaterGreater => ">>",
+            BinaryOp::BangEqual => "!=",
+            BinaryOp::EqualEqual => "==",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::DotDot => "..",
+            BinaryOp::DotDotEqual => "..=",
+        };
+        write!(f, "{}", lexeme)
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOp::Bang => write!(f, "!"),
+            UnaryOp::Minus => write!(f, "-"),
+            UnaryOp::Tilde => write!(f, "~"),
+        }
+    }
+}
+
+impl fmt::Display for LogicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogicalOp::And => write!(f, "and"),
+            LogicalOp::Or => write!(f, "or"),
+            LogicalOp::QuestionQuestion => write!(f, "??"),
+        }
+    }
 }
 
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
 #[derive(Debug)]
 pub struct BinaryExp {
+    pub id: NodeId,
+    pub left: Box<Exp>,
+    pub operator: BinaryOp,
+    pub span: Span,
+    pub right: Box<Exp>
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct LogicalExp {
+    pub id: NodeId,
+    pub left: Box<Exp>,
+    pub operator: LogicalOp,
+    pub span: Span,
+    pub right: Box<Exp>
+}
+
+/// `condition ? then_branch : else_branch`, where only the selected branch is evaluated.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct ConditionalExp {
+    pub id: NodeId,
+    pub condition: Box<Exp>,
+    pub then_branch: Box<Exp>,
+    pub else_branch: Box<Exp>,
+    pub span: Span
+}
+
+/// `left, right`, which evaluates `left` for its side effects and produces `right`.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct CommaExp {
+    pub id: NodeId,
     pub left: Box<Exp>,
-    pub operator: Token,
+    pub span: Span,
     pub right: Box<Exp>
 }
 
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct CallExp {
+    pub id: NodeId,
+    pub callee: Box<Exp>,
+    pub arguments: Vec<Element>,
+    /// The `name: value` arguments, which follow the positional ones.
+    pub named: Vec<(Identifier, Exp)>,
+    pub span: Span
+}
+
+/// An anonymous function, `fun (a, b) { ... }`, which evaluates to a closure.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct LambdaExp {
+    pub id: NodeId,
+    pub function: Rc<Function>
+}
+
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
 #[derive(Debug)]
 pub struct GroupingExp {
+    pub id: NodeId,
     pub exp: Box<Exp>,
 }
 
@@ -55,15 +763,70 @@ pub struct GroupingExp {
 #[derive(Clone)]
 #[derive(Debug)]
 pub struct UnaryExp {
+    pub id: NodeId,
     pub right: Box<Exp>,
-    pub operator: Token
+    pub operator: UnaryOp,
+    pub span: Span
 }
 
 #[derive(Eq, PartialEq)]
 #[derive(Clone)]
 #[derive(Debug)]
 pub struct LiteralExp {
-    pub value: Literal
+    pub id: NodeId,
+    pub value: Literal,
+    pub span: Span
+}
+
+impl Exp {
+    pub fn id(&self) -> NodeId {
+        match self {
+            Exp::BinaryExp(x) => x.id,
+            Exp::GroupingExp(x) => x.id,
+            Exp::UnaryExp(x) => x.id,
+            Exp::LiteralExp(x) => x.id,
+            Exp::LogicalExp(x) => x.id,
+            Exp::ConditionalExp(x) => x.id,
+            Exp::CommaExp(x) => x.id,
+            Exp::CallExp(x) => x.id,
+            Exp::LambdaExp(x) => x.id,
+            Exp::AssignExp(x) => x.id,
+            Exp::ListExp(x) => x.id,
+            Exp::IndexExp(x) => x.id,
+            Exp::SetIndexExp(x) => x.id,
+            Exp::GetExp(x) => x.id,
+            Exp::MapExp(x) => x.id,
+            Exp::CompoundAssignExp(x) => x.id,
+            Exp::IncrementExp(x) => x.id,
+            Exp::MatchExp(x) => x.id,
+            Exp::DestructureExp(x) => x.id,
+        }
+    }
+}
+
+impl Stmt {
+    pub fn id(&self) -> NodeId {
+        match self {
+            Stmt::VarDecl(x) => x.id,
+            Stmt::Statement(x) => x.id,
+            Stmt::PrintStmt(x) => x.id,
+            Stmt::Block(x) => x.id,
+            Stmt::FunDecl(x) => x.id,
+            Stmt::Return(x) => x.id,
+            Stmt::If(x) => x.id,
+            Stmt::While(x) => x.id,
+            Stmt::ForIn(x) => x.id,
+            Stmt::Break(x) => x.id,
+            Stmt::Continue(x) => x.id,
+            Stmt::Match(x) => x.id,
+            Stmt::Import(x) => x.id,
+            Stmt::Export(x) => x.id,
+            Stmt::Throw(x) => x.id,
+            Stmt::Yield(x) => x.id,
+            Stmt::Try(x) => x.id,
+            Stmt::Enum(x) => x.id,
+        }
+    }
 }
 
 // TODO: This should probably allocate the string as part of the function, and implement the ToString trait
@@ -80,47 +843,339 @@ fn exp_printer<'a>(builder: &'a mut String, exp: &'a Exp) -> &'a String {
     }
     match exp {
         Exp::BinaryExp(x) => {
-            add_parens(builder,x.operator.lexeme.clone(), vec![x.left.borrow(), x.right.borrow()])
+            add_parens(builder,x.operator.to_string(), vec![x.left.borrow(), x.right.borrow()])
+        },
+        Exp::LogicalExp(x) => {
+            add_parens(builder,x.operator.to_string(), vec![x.left.borrow(), x.right.borrow()])
+        },
+        Exp::ConditionalExp(x) => {
+            add_parens(builder,"?:".to_string(), vec![x.condition.borrow(), x.then_branch.borrow(), x.else_branch.borrow()])
+        },
+        Exp::CommaExp(x) => {
+            add_parens(builder,",".to_string(), vec![x.left.borrow(), x.right.borrow()])
+        },
+        Exp::CallExp(x) => {
+            builder.push_str("(call ");
+            exp_printer(builder, &x.callee);
+            elements_printer(builder, &x.arguments);
+            for (name, value) in &x.named {
+                builder.push_str(format!(" {}: ", name.0).as_str());
+                exp_printer(builder, value);
+            }
+            builder.push(')');
+            builder
+        },
+        Exp::LambdaExp(x) => {
+            builder.push_str("(fun ");
+            function_printer(builder, &x.function);
+            builder.push(')');
+            builder
+        },
+        Exp::AssignExp(x) => {
+            add_parens(builder,format!("= {}", x.name.0), vec![x.value.borrow()])
+        },
+        Exp::ListExp(x) => {
+            builder.push_str("(list");
+            elements_printer(builder, &x.elements);
+            builder.push(')');
+            builder
+        },
+        Exp::CompoundAssignExp(x) => {
+            builder.push_str(format!("({}= ", x.operator).as_str());
+            target_printer(builder, &x.target);
+            builder.push(' ');
+            exp_printer(builder, &x.value);
+            builder.push(')');
+            builder
+        },
+        Exp::IncrementExp(x) => {
+            let fixity = if x.postfix { "post" } else { "pre" };
+            builder.push_str(format!("({}{}{} ", fixity, x.operator, x.operator).as_str());
+            target_printer(builder, &x.target);
+            builder.push(')');
+            builder
+        },
+        Exp::MatchExp(x) => {
+            builder.push_str("(match ");
+            exp_printer(builder, &x.subject);
+            for arm in &x.arms {
+                builder.push_str(" (");
+                arm_printer(builder, arm);
+                exp_printer(builder, &arm.body);
+                builder.push(')');
+            }
+            builder.push(')');
+            builder
+        },
+        Exp::DestructureExp(x) => {
+            builder.push_str("(= ");
+            pattern_printer(builder, &x.pattern);
+            builder.push(' ');
+            exp_printer(builder, &x.value);
+            builder.push(')');
+            builder
+        },
+        Exp::MapExp(x) => {
+            add_parens(builder,"map".to_string(), x.entries.iter().flat_map(|(key, value)| vec![key, value]).collect())
+        },
+        Exp::IndexExp(x) => {
+            add_parens(builder,"index".to_string(), vec![x.object.borrow(), x.index.borrow()])
+        },
+        Exp::SetIndexExp(x) => {
+            add_parens(builder,"index=".to_string(), vec![x.object.borrow(), x.index.borrow(), x.value.borrow()])
+        },
+        Exp::GetExp(x) => {
+            let operator = if x.optional { "?." } else { "." };
+            add_parens(builder,format!("{} {}", operator, x.name.0), vec![x.object.borrow()])
         },
         Exp::GroupingExp(x) => {
             add_parens(builder,"group".to_string(), vec![x.exp.borrow()])
         },
         Exp::UnaryExp(x) => {
-            add_parens(builder,x.operator.lexeme.clone(), vec![x.right.borrow()])
+            add_parens(builder,x.operator.to_string(), vec![x.right.borrow()])
         },
         Exp::LiteralExp(x) => {
             builder.push_str((match x.value.clone() {
                 Literal::IDENTIFIER(x) => {x},
                 Literal::STRING(x) => {x},
                 Literal::NUMBER(x) => {x.to_string()},
+                Literal::BOOLEAN(x) => {x.to_string()},
+                Literal::NIL => {"nil".to_string()},
             }).as_str());
             builder
         },
     }
 }
 
+fn target_printer<'a>(builder: &'a mut String, target: &'a AssignTarget) -> &'a String {
+    match target {
+        AssignTarget::Variable(name) => {
+            builder.push_str(name.0.as_str());
+            builder
+        },
+        AssignTarget::Index { object, index } => {
+            builder.push_str("(index ");
+            exp_printer(builder, object);
+            builder.push(' ');
+            exp_printer(builder, index);
+            builder.push(')');
+            builder
+        },
+    }
+}
+
+/// Prints the pattern and guard of an arm, leaving its body to the caller.
+fn arm_printer<'a, T>(builder: &'a mut String, arm: &'a MatchArm<T>) -> &'a String {
+    pattern_printer(builder, &arm.pattern);
+    if let Some(guard) = &arm.guard {
+        builder.push_str(" if ");
+        exp_printer(builder, guard);
+    }
+    builder.push_str(" => ");
+    builder
+}
+
+pub fn pattern_printer<'a>(builder: &'a mut String, pattern: &'a Pattern) -> &'a String {
+    match pattern {
+        Pattern::Wildcard => builder.push('_'),
+        Pattern::Literal(Literal::STRING(x)) => builder.push_str(format!("{:?}", x).as_str()),
+        Pattern::Literal(Literal::NUMBER(x)) => builder.push_str(x.to_string().as_str()),
+        Pattern::Literal(Literal::BOOLEAN(x)) => builder.push_str(x.to_string().as_str()),
+        Pattern::Literal(Literal::NIL) => builder.push_str("nil"),
+        Pattern::Literal(Literal::IDENTIFIER(x)) => builder.push_str(x.as_str()),
+        Pattern::Binding(name) => builder.push_str(name.0.as_str()),
+        Pattern::Variant(name, variant) => builder.push_str(format!("{}.{}", name.0, variant.0).as_str()),
+        Pattern::List(elements) => {
+            builder.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    builder.push_str(", ");
+                }
+                pattern_printer(builder, element);
+            }
+            builder.push(']');
+        },
+        Pattern::Map(entries) => {
+            builder.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    builder.push_str(", ");
+                }
+                pattern_printer(builder, &Pattern::Literal(key.clone()));
+                builder.push_str(": ");
+                pattern_printer(builder, value);
+            }
+            builder.push('}');
+        },
+    }
+    builder
+}
+
+/// Prints each element preceded by a space.
+fn elements_printer<'a>(builder: &'a mut String, elements: &'a [Element]) -> &'a String {
+    for element in elements {
+        builder.push(' ');
+        match element {
+            Element::Single(exp) => exp_printer(builder, exp),
+            Element::Spread(exp) => {
+                builder.push_str("...");
+                exp_printer(builder, exp)
+            },
+        };
+    }
+    builder
+}
+
+fn function_printer<'a>(builder: &'a mut String, function: &'a Function) -> &'a String {
+    let mut params: Vec<String> = function.params.iter().zip(&function.param_types).zip(&function.defaults)
+        .map(|((param, annotation), default)| match default {
+            None => format!("{}{}", param.0, annotation_printer(annotation)),
+            Some(default) => format!("{}{} = {}", param.0, annotation_printer(annotation), exp_printer(&mut String::new(), default)),
+        })
+        .collect();
+    if function.rest {
+        if let Some(last) = params.last_mut() {
+            last.insert_str(0, "...");
+        }
+    }
+    builder.push_str(format!("({}){} ", params.join(" "), annotation_printer(&function.return_type)).as_str());
+    block_printer(builder, &function.body)
+}
+
+fn annotation_printer(annotation: &Option<TypeAnnotation>) -> String {
+    match annotation {
+        None => String::new(),
+        Some(annotation) => format!(": {}", annotation),
+    }
+}
+
+fn block_printer<'a>(builder: &'a mut String, stmts: &'a [Stmt]) -> &'a String {
+    builder.push('{');
+    for stmt in stmts {
+        builder.push(' ');
+        stmt_printer(builder, stmt);
+    }
+    builder.push_str(" }");
+    builder
+}
+
 pub fn stmt_printer<'a>(builder: &'a mut String, stmt: &'a Stmt) -> &'a String {
     match stmt {
         Stmt::VarDecl(decl) => {
-            builder.push_str("var ");
-            builder.push_str(decl.identifier.0.as_str());
+            builder.push_str(if decl.constant { "const " } else { "var " });
+            pattern_printer(builder, &decl.pattern);
+            builder.push_str(annotation_printer(&decl.annotation).as_str());
             match &decl.exp {
                 None => {},
                 Some(exp) => {
                     builder.push_str(" = ");
-                    exp_printer(builder, &exp);
-                    builder.push_str(";");
+                    exp_printer(builder, exp);
+                    builder.push(';');
                 },
             }
         },
-        Stmt::Statement(exp) => {
-            exp_printer(builder, exp);
-            builder.push_str(";");
+        Stmt::Statement(stmt) => {
+            exp_printer(builder, &stmt.exp);
+            builder.push(';');
         },
-        Stmt::PrintStmt(exp) => {
+        Stmt::PrintStmt(stmt) => {
             builder.push_str("print ");
-            exp_printer(builder, exp);
-            builder.push_str(";");
+            exp_printer(builder, &stmt.exp);
+            builder.push(';');
+        },
+        Stmt::Block(block) => {
+            block_printer(builder, &block.stmts);
+        },
+        Stmt::FunDecl(decl) => {
+            builder.push_str(format!("fun {}", decl.name.0).as_str());
+            function_printer(builder, &decl.function);
+        },
+        Stmt::Return(stmt) => {
+            builder.push_str("return");
+            if let Some(exp) = &stmt.exp {
+                builder.push(' ');
+                exp_printer(builder, exp);
+            }
+            builder.push(';');
+        },
+        Stmt::If(stmt) => {
+            builder.push_str("if (");
+            exp_printer(builder, &stmt.condition);
+            builder.push_str(") ");
+            stmt_printer(builder, &stmt.then_branch);
+            if let Some(else_branch) = &stmt.else_branch {
+                builder.push_str(" else ");
+                stmt_printer(builder, else_branch);
+            }
+        },
+        Stmt::While(stmt) => {
+            builder.push_str("while (");
+            exp_printer(builder, &stmt.condition);
+            if let Some(increment) = &stmt.increment {
+                builder.push_str("; ");
+                exp_printer(builder, increment);
+            }
+            builder.push_str(") ");
+            stmt_printer(builder, &stmt.body);
+        },
+        Stmt::ForIn(stmt) => {
+            builder.push_str(format!("for ({} in ", stmt.name.0).as_str());
+            exp_printer(builder, &stmt.iterable);
+            builder.push_str(") ");
+            stmt_printer(builder, &stmt.body);
+        },
+        Stmt::Break(_) => {
+            builder.push_str("break;");
+        },
+        Stmt::Continue(_) => {
+            builder.push_str("continue;");
+        },
+        Stmt::Match(stmt) => {
+            builder.push_str("match (");
+            exp_printer(builder, &stmt.subject);
+            builder.push_str(") {");
+            for arm in &stmt.arms {
+                builder.push(' ');
+                arm_printer(builder, arm);
+                stmt_printer(builder, &arm.body);
+            }
+            builder.push_str(" }");
+        },
+        Stmt::Import(stmt) => {
+            builder.push_str(format!("import {:?} as {};", stmt.path, stmt.name.0).as_str());
+        },
+        Stmt::Export(stmt) => {
+            builder.push_str("export ");
+            stmt_printer(builder, &stmt.decl);
+        },
+        Stmt::Throw(stmt) => {
+            builder.push_str("throw ");
+            exp_printer(builder, &stmt.exp);
+            builder.push(';');
+        },
+        Stmt::Try(stmt) => {
+            builder.push_str("try ");
+            block_printer(builder, &stmt.body);
+            if let Some(catch) = &stmt.catch {
+                builder.push_str(format!(" catch ({}) ", catch.name.0).as_str());
+                block_printer(builder, &catch.body);
+            }
+            if let Some(finally) = &stmt.finally {
+                builder.push_str(" finally ");
+                block_printer(builder, finally);
+            }
+        },
+        Stmt::Yield(stmt) => {
+            builder.push_str("yield");
+            if let Some(exp) = &stmt.exp {
+                builder.push(' ');
+                exp_printer(builder, exp);
+            }
+            builder.push(';');
+        },
+        Stmt::Enum(decl) => {
+            let variants: Vec<&str> = decl.variants.iter().map(|variant| variant.0.as_str()).collect();
+            builder.push_str(format!("enum {} {{ {} }}", decl.name.0, variants.join(", ")).as_str());
         },
     }
     builder
@@ -129,7 +1184,6 @@ pub fn stmt_printer<'a>(builder: &'a mut String, stmt: &'a Stmt) -> &'a String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use scanner::TokenType;
 
     // TODO MC: Actually test unary, and identifier literal - probably fine for now
     #[test]
@@ -137,22 +1191,37 @@ mod tests {
     {
         let binary_exp: Exp = Exp::BinaryExp(
             BinaryExp {
+                id: NodeId(3),
                 left: Box::new(Exp::GroupingExp(
                     GroupingExp{
-                        exp: Box::new(Exp::LiteralExp(LiteralExp{ value: Literal::STRING("foobar".to_string()) }))
+                        id: NodeId(1),
+                        exp: Box::new(Exp::LiteralExp(LiteralExp{ id: NodeId(0), value: Literal::STRING("foobar".to_string()), span: Span { line: 0 } }))
                     }
                 )),
-                operator: Token {
-                    token_type: TokenType::EqualEqual,
-                    lexeme: "==".to_string(),
-                    line: 0
-                },
-                right: Box::new(Exp::LiteralExp(LiteralExp{value: Literal::NUMBER(2)}))});
-        let binary_stmt : Stmt = Stmt::Statement(binary_exp);
+                operator: BinaryOp::EqualEqual,
+                span: Span { line: 0 },
+                right: Box::new(Exp::LiteralExp(LiteralExp{ id: NodeId(2), value: Literal::NUMBER(2), span: Span { line: 0 } }))});
+        let binary_stmt : Stmt = Stmt::Statement(ExpStmt { id: NodeId(4), exp: binary_exp });
 
         let mut output_string = String::new();
         let output = stmt_printer(&mut output_string, &binary_stmt);
 
-        assert_eq!(*output, "(== (group foobar) 2)".to_string());
+        assert_eq!(*output, "(== (group foobar) 2);".to_string());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn node_map_distinguishes_equal_subtrees()
+    {
+        let first = LiteralExp { id: NodeId(0), value: Literal::NUMBER(1), span: Span { line: 0 } };
+        let second = LiteralExp { id: NodeId(1), value: Literal::NUMBER(1), span: Span { line: 0 } };
+
+        let mut side_table = NodeMap::new();
+        side_table.insert(first.id, "first");
+        side_table.insert(second.id, "second");
+
+        assert_eq!(first.value, second.value);
+        assert_eq!(side_table.get(first.id), Some(&"first"));
+        assert_eq!(side_table.get(second.id), Some(&"second"));
+        assert_eq!(side_table.len(), 2);
+    }
+}
@@ -0,0 +1,98 @@
+use interpreter::Value;
+use std::fmt;
+use std::ptr;
+use std::rc::Rc;
+
+/// The value an `enum` declaration binds its name to, holding its variants in the order they were
+/// declared.
+pub struct Enum {
+    pub name: String,
+    variants: Vec<Value>,
+}
+
+/// One of the variants of an enum. Each is created once, when its enum is declared, and is only
+/// equal to itself.
+pub struct Variant {
+    pub enum_name: String,
+    pub name: String,
+}
+
+impl Enum {
+    pub fn new(name: String, variants: &[String]) -> Enum {
+        let variants = variants.iter()
+            .map(|variant| Value::Variant(Rc::new(Variant { enum_name: name.clone(), name: variant.clone() })))
+            .collect();
+        Enum { name, variants }
+    }
+
+    pub fn variant(&self, name: &str) -> Option<Value> {
+        self.variants.iter()
+            .find(|variant| matches!(variant, Value::Variant(variant) if variant.name == name))
+            .cloned()
+    }
+
+    pub fn variants(&self) -> &[Value] {
+        &self.variants
+    }
+}
+
+// Declaring the same enum twice makes two enums with distinct variants.
+impl PartialEq for Enum {
+    fn eq(&self, other: &Enum) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for Enum {}
+
+impl PartialEq for Variant {
+    fn eq(&self, other: &Variant) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for Variant {}
+
+impl fmt::Debug for Enum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Enum({})", self.name)
+    }
+}
+
+impl fmt::Debug for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.enum_name, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color() -> Enum {
+        Enum::new("Color".to_string(), &["Red".to_string(), "Green".to_string()])
+    }
+
+    #[test]
+    fn variants_are_only_equal_to_themselves()
+    {
+        let (color, other) = (color(), color());
+        assert_eq!(color.variant("Red"), color.variant("Red"));
+        assert_ne!(color.variant("Red"), color.variant("Green"));
+        assert_ne!(color.variant("Red"), other.variant("Red"));
+        assert_eq!(color.variant("Blue"), None);
+    }
+
+    #[test]
+    fn variants_print_with_their_enum_name()
+    {
+        let names: Vec<String> = color().variants().iter().map(Value::to_string).collect();
+        assert_eq!(names, vec!["Color.Red", "Color.Green"]);
+    }
+}
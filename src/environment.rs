@@ -1,24 +1,77 @@
-use std::collections::HashMap;
-use ast::Identifier;
+use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::rc::Rc;
 use interpreter::Value;
-use std::convert::TryInto;
-use std::borrow::Borrow;
 
-pub struct Environment{map : HashMap<String, Value>}
+/// A single scope of bindings. Lookups fall back to the `enclosing` scope, so closures keep the
+/// scope they were created in alive by holding a reference to it. Bindings declared with `const`
+/// are listed in `constants`, and can't be assigned to or declared again.
+pub struct Environment{map : HashMap<String, Value>, constants : HashSet<String>, enclosing : Option<Rc<RefCell<Environment>>>}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::new()
+    }
+}
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
-            map: HashMap::new()
+            map: HashMap::new(),
+            constants: HashSet::new(),
+            enclosing: None
         }
     }
 
-    pub fn put(&mut self, identifier: String, value: Value) -> () {
+    pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Environment {
+        Environment {
+            map: HashMap::new(),
+            constants: HashSet::new(),
+            enclosing: Some(enclosing)
+        }
+    }
+
+    /// Defines `identifier` in this scope, shadowing any binding in an enclosing scope.
+    pub fn put(&mut self, identifier: String, value: Value) {
+        self.map.insert(identifier, value);
+    }
+
+    /// Declares `identifier` in this scope like `put`, unless it's already a constant here. The
+    /// resolver catches most redeclarations, but not those of globals from an earlier REPL line.
+    pub fn declare(&mut self, identifier: String, value: Value, constant: bool) -> Result<(), String> {
+        if self.constants.contains(&identifier) {
+            return Err(format!("Can't redeclare constant '{}'", identifier))
+        }
+        if constant {
+            self.constants.insert(identifier.clone());
+        }
         self.map.insert(identifier, value);
-        ()
+        Ok(())
+    }
+
+    pub fn get(&self, identifier: &str) -> Option<Value> {
+        match self.map.get(identifier) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref().and_then(|enclosing| enclosing.borrow().get(identifier)),
+        }
+    }
+
+    /// Rebinds `identifier` in the innermost scope that defines it.
+    pub fn assign(&mut self, identifier: &str, value: Value) -> Result<(), String> {
+        if let Some(slot) = self.map.get_mut(identifier) {
+            if self.constants.contains(identifier) {
+                return Err(format!("Can't assign to constant '{}'", identifier))
+            }
+            *slot = value;
+            return Ok(())
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(identifier, value),
+            None => Err(format!("Undefined variable: {}", identifier)),
+        }
     }
 
-    pub fn get(&self, identifier: &String) -> Option<&Value> {
-        self.map.get(identifier)
+    pub fn remove(&mut self, identifier: &str) -> Option<Value> {
+        self.map.remove(identifier)
     }
-}
\ No newline at end of file
+}
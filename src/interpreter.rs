@@ -1,8 +1,30 @@
-use ast::{Exp, BinaryExp, GroupingExp, UnaryExp, LiteralExp, Stmt, Identifier};
-use scanner::{Literal, Token, TokenType};
-use std::iter::FromIterator;
+use ast;
+use ast::{Exp, BinaryExp, GroupingExp, UnaryExp, LiteralExp, LogicalExp, ConditionalExp, CommaExp, CallExp, AssignExp, Stmt};
+use ast::{ListExp, IndexExp, SetIndexExp, GetExp, MapExp, AssignTarget, CompoundAssignExp, IncrementExp, Element};
+use ast::{BinaryOp, UnaryOp, LogicalOp, Span, Pattern};
+use ir;
+use scanner::{Scanner, Literal};
+use parser::{Parser, MAX_ARGUMENTS};
+use lower::Lowerer;
+use analysis;
 use environment::Environment;
-use std::borrow::Borrow;
+use module;
+use module::{Module, ModuleLoader};
+use native;
+use native::{Native, NativeIterator};
+use map::{Map, MapKey};
+use range::Range;
+use enumeration::{Enum, Variant};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
 
 #[derive(Eq, PartialEq)]
 #[derive(Debug)]
@@ -12,6 +34,225 @@ pub enum Value {
     Boolean(bool),
     Number(i64),
     String(String),
+    Function(Rc<Closure>),
+    /// Lists are shared rather than copied, so changes made through one reference are visible
+    /// through every other.
+    List(Rc<RefCell<Vec<Value>>>),
+    /// Shared in the same way as lists.
+    Map(Rc<RefCell<Map>>),
+    Native(Rc<Native>),
+    Module(Rc<Module>),
+    /// A runtime error caught by a `try` statement.
+    Error(Rc<ErrorObject>),
+    /// The result of calling a generator function, which runs its body as `next()` is called.
+    Generator(Rc<Generator>),
+    /// An iterator over a list, map, string or range.
+    Iterator(Rc<NativeIterator>),
+    Range(Range),
+    /// The namespace an `enum` declaration binds, whose properties are its variants.
+    Enum(Rc<Enum>),
+    Variant(Rc<Variant>),
+}
+
+/// The `message`, `line` and `stack` of a runtime error, as seen by the `catch` clause handling it.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+pub struct ErrorObject {
+    pub message: String,
+    pub line: Option<usize>,
+    /// The calls the error unwound through before being caught, innermost first.
+    pub stack: Vec<String>,
+}
+
+/// A function value: the code to run along with the scope it was created in.
+pub struct Closure {
+    name: Option<String>,
+    params: Vec<String>,
+    /// How many of `params` have no default, and so must be passed.
+    required: usize,
+    /// Whether the last of `params` collects any arguments after the others into a list.
+    rest: bool,
+    body: FunctionBody,
+    closure: Rc<RefCell<Environment>>,
+}
+
+/// Closures are created both by the AST interpreter and from lowered code, and run their body with
+/// whichever created them.
+enum FunctionBody {
+    Surface(Rc<ast::Function>),
+    Core(Rc<ir::Function>),
+    /// The `next` method of a generator, which resumes it.
+    Resume(Rc<Generator>),
+}
+
+impl FunctionBody {
+    fn generator(&self) -> bool {
+        match self {
+            FunctionBody::Surface(function) => function.generator,
+            FunctionBody::Core(function) => function.generator,
+            FunctionBody::Resume(_) => false,
+        }
+    }
+}
+
+/// A call to a generator function. Its body runs until it reaches a `yield`, and then suspends
+/// until `next()` is called again.
+pub struct Generator {
+    function: Rc<Closure>,
+    state: RefCell<GeneratorState>,
+}
+
+enum GeneratorState {
+    /// Not started yet, with the scope holding the arguments the function was called with.
+    Ready(Environment),
+    /// Stopped at a `yield`, with the frames of the statements enclosing it.
+    Suspended(Vec<Frame>),
+    Running,
+    /// Returned, reached the end of its body or failed.
+    Finished,
+}
+
+// Each call to a generator function produces a separate generator, only equal to itself.
+impl PartialEq for Generator {
+    fn eq(&self, other: &Generator) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for Generator {}
+
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Generator({:?})", self.function.name)
+    }
+}
+
+/// What a statement enclosing a `yield` needs to carry on from where it was when its generator is
+/// resumed. Suspending a generator unwinds the statements it's running as far as its body, each
+/// pushing its frame, so the frames are resumed from the outermost statement inwards by popping
+/// them. Statements don't evaluate anything when resuming until the `yield` itself is reached.
+enum Frame {
+    /// Running the statement at `index` of a list of statements, in `environment`.
+    Statements { index: usize, environment: Rc<RefCell<Environment>> },
+    /// Running the `then` branch of an `if` statement, or else the `else` branch.
+    Branch(bool),
+    /// Running the body of a loop.
+    Loop,
+    /// Running the body of a `for`-`in` loop, in the scope holding the value from `iterator`.
+    Iteration { iterator: Value, environment: Rc<RefCell<Environment>> },
+    /// Running the body of the match arm at `arm`, whose bindings are in `environment`.
+    Arm { arm: usize, environment: Rc<RefCell<Environment>> },
+    Try(TryClause),
+    /// At the `yield` which suspended the generator.
+    Yield,
+}
+
+/// The clause of a `try` statement which is running.
+enum TryClause {
+    Body,
+    Catch,
+    /// Running the `finally` clause, after the others finished with `result`.
+    Finally(Result<Flow, RuntimeError>),
+}
+
+// Functions are only equal to themselves.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for Closure {}
+
+impl Closure {
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("<fn>")
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Closure({:?})", self.name)
+    }
+}
+
+/// How a statement finished. Anything but `Normal` unwinds the statements enclosing it until
+/// something handles it; a call handles `Return` and a loop handles `Break` and `Continue`.
+enum Flow {
+    Normal,
+    Return(Value),
+    Break,
+    Continue,
+    /// A generator suspended, producing the value. Each statement it unwinds records its frame.
+    Yield(Value),
+}
+
+/// Why evaluation was abandoned: either a runtime error or a value thrown by `throw`. Either kind
+/// unwinds until a `try` statement catches it, recording the calls it unwinds through on the way.
+pub struct RuntimeError {
+    message: String,
+    /// The line the error happened on, if it's known.
+    line: Option<usize>,
+    thrown: Option<Value>,
+    stack: Vec<String>,
+}
+
+impl From<String> for RuntimeError {
+    fn from(message: String) -> RuntimeError {
+        RuntimeError { message, line: None, thrown: None, stack: vec![] }
+    }
+}
+
+impl RuntimeError {
+    fn new(message: String, span: Span) -> RuntimeError {
+        RuntimeError { message, line: Some(span.line), thrown: None, stack: vec![] }
+    }
+
+    /// Converts the error messages of helpers which don't know where they were called from into
+    /// errors on the line of `span`.
+    fn at(span: Span) -> impl Fn(String) -> RuntimeError {
+        move |message| RuntimeError::new(message, span)
+    }
+
+    fn thrown(value: Value, span: Span) -> RuntimeError {
+        RuntimeError {
+            message: format!("Uncaught exception: {}", value),
+            line: Some(span.line),
+            thrown: Some(value),
+            stack: vec![],
+        }
+    }
+
+    /// Records that the error unwound out of a call to `function` made on the line of `span`.
+    fn unwound_from(mut self, function: &str, span: Span) -> RuntimeError {
+        self.stack.push(format!("{} called on line {}", function, span.line));
+        self
+    }
+
+    /// The value a `catch` clause binds: the thrown value, or an error object describing the
+    /// runtime error.
+    fn into_value(self) -> Value {
+        match self.thrown {
+            Some(value) => value,
+            None => Value::Error(Rc::new(ErrorObject { message: self.message, line: self.line, stack: self.stack })),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}, line: {}", self.message, line),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// An assignment target whose parts have been evaluated, so it can be read and then written
+/// without evaluating them again.
+enum Place {
+    Variable(String),
+    Index(Value, Value),
 }
 
 enum MatchedValues {
@@ -22,158 +263,1361 @@ enum MatchedValues {
 }
 
 pub struct Interpreter {
-    globals : Environment
+    environment : Rc<RefCell<Environment>>,
+    output : Box<dyn Write>,
+    modules : ModuleLoader,
+    /// The frames of the generator being resumed, until the statements they belong to take them,
+    /// or of the one being suspended, as the statements it's running push them.
+    frames : Vec<Frame>
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
-        Interpreter { globals: Environment::new() }
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates an interpreter whose `print` statements write to `output` rather than stdout.
+    pub fn with_output(output: Box<dyn Write>) -> Interpreter {
+        Interpreter {
+            environment: Rc::new(RefCell::new(Environment::new())),
+            output,
+            modules: ModuleLoader::new(),
+            frames: vec![],
+        }
+    }
+
+    /// Sets the file being run, which imports are resolved relative to.
+    pub fn set_main_file(&mut self, file : &Path) -> Result<(), String> {
+        self.modules.set_main_file(file)
     }
 
-    pub fn interpret(&mut self, stmts : &Vec<Stmt>) -> Result<(), String> {
-        let stmts_success: Result<Vec<()>, String> =
-            stmts
-                .iter()
-                .map(|stmt| self.execute(stmt))
-                .collect();
+    /// Adds a directory to look for imported modules in.
+    pub fn add_search_path(&mut self, directory : PathBuf) {
+        self.modules.add_search_path(directory);
+    }
+
+    pub fn interpret(&mut self, stmts : &[Stmt]) -> Result<(), String> {
+        self.execute_all(stmts).map(|_| ()).map_err(|err| err.to_string())
+    }
 
-        stmts_success.map(|x| ())
+    fn execute_all(&mut self, stmts : &[Stmt]) -> Result<Flow, RuntimeError> {
+        let start = self.resume_statements();
+        for (index, stmt) in stmts.iter().enumerate().skip(start) {
+            match self.execute(stmt)? {
+                Flow::Normal => {},
+                flow => return Ok(self.suspended_statements(flow, index)),
+            }
+        }
+        Ok(Flow::Normal)
     }
 
-    fn execute(&mut self, stmt : &Stmt) -> Result<(), String> {
+    fn execute(&mut self, stmt : &Stmt) -> Result<Flow, RuntimeError> {
         match stmt {
             Stmt::VarDecl(decl) => {
                 let val = match &decl.exp {
                     None => {Ok(Value::Nil)},
                     Some(exp) => {self.evaluate(exp)},
                 };
-                val.map(|v| {
-                    self.globals.put(decl.identifier.0.clone(), v);
-                    ()
-                })
+                self.declare_pattern(&decl.pattern, val?, decl.constant, decl.span)?;
+                Ok(Flow::Normal)
+            },
+            Stmt::Statement(stmt) => {
+                let val = self.evaluate(&stmt.exp);
+                val.map(|_| Flow::Normal)
+            },
+            Stmt::PrintStmt(stmt) => {
+                let val = self.evaluate(&stmt.exp);
+                val.and_then(|x| self.print(&x)).map(|_| Flow::Normal)
+            },
+            Stmt::Block(block) => {
+                let environment = Environment::new_enclosed(self.environment.clone());
+                self.execute_block(&block.stmts, environment)
+            },
+            Stmt::FunDecl(decl) => {
+                let function = self.closure(Some(decl.name.0.clone()), FunctionBody::Surface(decl.function.clone()));
+                self.declare(decl.name.0.clone(), function, false, decl.function.span)?;
+                Ok(Flow::Normal)
+            },
+            Stmt::Return(stmt) => {
+                let value = match &stmt.exp {
+                    None => Value::Nil,
+                    Some(exp) => self.evaluate(exp)?,
+                };
+                Ok(Flow::Return(value))
+            },
+            Stmt::If(stmt) => {
+                let then = match self.frames.pop() {
+                    None => self.evaluate(&stmt.condition)?.is_truthy(),
+                    Some(Frame::Branch(then)) => then,
+                    Some(_) => unreachable!("an if statement resumed with another statement's frame"),
+                };
+                let flow = match (then, &stmt.else_branch) {
+                    (true, _) => self.execute(&stmt.then_branch)?,
+                    (false, None) => Flow::Normal,
+                    (false, Some(else_branch)) => self.execute(else_branch)?,
+                };
+                Ok(self.suspended(flow, || Frame::Branch(then)))
+            },
+            Stmt::While(stmt) => {
+                let mut resuming = self.resume_loop();
+                while mem::take(&mut resuming) || self.evaluate(&stmt.condition)?.is_truthy() {
+                    match self.execute(&stmt.body)? {
+                        Flow::Normal | Flow::Continue => {},
+                        Flow::Break => break,
+                        flow => return Ok(self.suspended(flow, || Frame::Loop)),
+                    }
+                    if let Some(increment) = &stmt.increment {
+                        self.evaluate(increment)?;
+                    }
+                }
+                Ok(Flow::Normal)
+            },
+            Stmt::ForIn(stmt) => self.execute_for_in(&stmt.name.0, &stmt.iterable, Interpreter::evaluate, &*stmt.body,
+                                                     Interpreter::execute, stmt.span),
+            Stmt::Match(stmt) => {
+                let (arm, environment) = match self.resume_arm() {
+                    Some(resumed) => resumed,
+                    None => {
+                        let subject = self.evaluate(&stmt.subject)?;
+                        let arms = stmt.arms.iter().map(|arm| (&arm.pattern, arm.guard.as_ref()));
+                        self.select_arm(&subject, arms, Interpreter::evaluate, stmt.span)?
+                    },
+                };
+                let flow = self.in_environment(environment.clone(), |this| this.execute(&stmt.arms[arm].body))?;
+                Ok(self.suspended(flow, || Frame::Arm { arm, environment }))
+            },
+            Stmt::Break(_) => Ok(Flow::Break),
+            Stmt::Continue(_) => Ok(Flow::Continue),
+            Stmt::Import(stmt) => {
+                let module = self.import(&stmt.path, stmt.span, false)?;
+                self.declare(stmt.name.0.clone(), module, false, stmt.span)?;
+                Ok(Flow::Normal)
+            },
+            Stmt::Enum(decl) => {
+                let variants: Vec<String> = decl.variants.iter().map(|variant| variant.0.clone()).collect();
+                let enumeration = Value::Enum(Rc::new(Enum::new(decl.name.0.clone(), &variants)));
+                self.declare(decl.name.0.clone(), enumeration, false, decl.span)?;
+                Ok(Flow::Normal)
             },
-            Stmt::Statement(exp) => {
-                let val = self.evaluate(exp);
-                val.map(|_| ())
+            Stmt::Export(stmt) => self.execute(&stmt.decl),
+            Stmt::Throw(stmt) => {
+                let value = self.evaluate(&stmt.exp)?;
+                Err(RuntimeError::thrown(value, stmt.span))
             },
-            Stmt::PrintStmt(exp) => {
-                let val = self.evaluate(exp);
-                val.map(|x|
-                    println!("{}", x.to_string())
-                )
+            Stmt::Try(stmt) => {
+                let catch = stmt.catch.as_ref().map(|catch| (catch.name.0.as_str(), catch.body.as_slice()));
+                self.execute_try(&stmt.body, catch, stmt.finally.as_deref(), Interpreter::execute_block)
+            },
+            Stmt::Yield(stmt) => {
+                if self.resume_yield() {
+                    return Ok(Flow::Normal)
+                }
+                let value = match &stmt.exp {
+                    None => Value::Nil,
+                    Some(exp) => self.evaluate(exp)?,
+                };
+                Ok(self.suspended(Flow::Yield(value), || Frame::Yield))
             },
         }
     }
 
-    fn evaluate(&self, exp : &Exp) -> Result<Value, String> {
-        match exp {
-            Exp::BinaryExp(bin_exp) => self.interpret_binary(bin_exp),
-            Exp::GroupingExp(grouping_exp) => self.interpret_grouping(grouping_exp),
-            Exp::UnaryExp(unary_exp) => self.interpret_unary(unary_exp),
-            Exp::LiteralExp(literal_exp) => self.interpret_literal(literal_exp),
+    /// Executes `stmts` in `environment`, restoring the current environment afterwards.
+    fn execute_block(&mut self, stmts : &[Stmt], environment : Environment) -> Result<Flow, RuntimeError> {
+        let previous = mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = self.execute_all(stmts);
+        self.environment = previous;
+        result
+    }
+
+    /// Runs `f` with `environment` as the current environment, restoring the current one afterwards.
+    fn in_environment<R>(&mut self, environment : Rc<RefCell<Environment>>, f : impl FnOnce(&mut Interpreter) -> R) -> R {
+        let previous = mem::replace(&mut self.environment, environment);
+        let result = f(self);
+        self.environment = previous;
+        result
+    }
+
+    /// Runs `body` with `execute` for each value produced by the iterator of `iterable`. Each value
+    /// is bound to `name` in a new scope, so closures created by the body capture the value of
+    /// their own iteration.
+    fn execute_for_in<E, S>(&mut self, name : &str, iterable : &E, evaluate : fn(&mut Interpreter, &E) -> Result<Value, RuntimeError>,
+                            body : &S, execute : fn(&mut Interpreter, &S) -> Result<Flow, RuntimeError>, span : Span)
+                            -> Result<Flow, RuntimeError> {
+        let (iterator, mut resumed) = match self.frames.pop() {
+            None => {
+                let iterable = evaluate(self, iterable)?;
+                (self.iterator(iterable, span)?, None)
+            },
+            Some(Frame::Iteration { iterator, environment }) => (iterator, Some(environment)),
+            Some(_) => unreachable!("a for-in loop resumed with another statement's frame"),
+        };
+        loop {
+            let environment = match resumed.take() {
+                Some(environment) => environment,
+                None => match self.advance(&iterator, span)? {
+                    None => break,
+                    Some(value) => {
+                        let mut environment = Environment::new_enclosed(self.environment.clone());
+                        environment.put(name.to_string(), value);
+                        Rc::new(RefCell::new(environment))
+                    },
+                },
+            };
+            match self.in_environment(environment.clone(), |this| execute(this, body))? {
+                Flow::Normal | Flow::Continue => {},
+                Flow::Break => break,
+                flow => return Ok(self.suspended(flow, || Frame::Iteration { iterator, environment })),
+            }
         }
+        Ok(Flow::Normal)
     }
 
-    fn interpret_literal(&self, exp : &LiteralExp) -> Result<Value, String> {
-        match &exp.value {
-            Literal::IDENTIFIER(id) => {
-                match self.globals.get(&id.clone()) {
-                    None => {Err(format!("Unable to find global variable: {}", id))},
-                    Some(value) => {Ok(value.clone())},
+    /// Gets an iterator over `iterable` by calling its `iterator()` method.
+    fn iterator(&mut self, iterable : Value, span : Span) -> Result<Value, RuntimeError> {
+        let method = property_value(&iterable, "iterator")
+            .map_err(|_| RuntimeError::new(format!("Can't iterate over {}", iterable.type_name()), span))?;
+        self.call(method, vec![], span)
+    }
+
+    /// Adds each value iterating over `iterable` produces to `values`.
+    fn spread(&mut self, iterable : Value, values : &mut Vec<Value>, span : Span) -> Result<(), RuntimeError> {
+        let iterator = self.iterator(iterable, span)?;
+        while let Some(value) = self.advance(&iterator, span)? {
+            values.push(value);
+        }
+        Ok(())
+    }
+
+    fn evaluate_ir_elements(&mut self, elements : &[ir::Element], span : Span) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = vec![];
+        for element in elements {
+            match element {
+                ir::Element::Single(exp) => values.push(self.evaluate_ir(exp)?),
+                ir::Element::Spread(exp) => {
+                    let iterable = self.evaluate_ir(exp)?;
+                    self.spread(iterable, &mut values, span)?;
+                },
+            }
+        }
+        Ok(values)
+    }
+
+    /// Advances `iterator` by calling its `next()` method, producing the next value unless it's done.
+    fn advance(&mut self, iterator : &Value, span : Span) -> Result<Option<Value>, RuntimeError> {
+        let next = property_value(iterator, "next").map_err(RuntimeError::at(span))?;
+        let result = self.call(next, vec![], span)?;
+        native::iteration_value(&result).map_err(RuntimeError::at(span))
+    }
+
+    /// Runs the clauses of a `try` statement, each in its own scope, using `execute_block`. The
+    /// `finally` clause runs however the others finish, and only changes the outcome if it fails
+    /// or jumps itself.
+    fn execute_try<S>(&mut self, body : &[S], catch : Option<(&str, &[S])>, finally : Option<&[S]>,
+                      execute_block : fn(&mut Interpreter, &[S], Environment) -> Result<Flow, RuntimeError>)
+                      -> Result<Flow, RuntimeError> {
+        let resumed = match self.frames.pop() {
+            None => TryClause::Body,
+            Some(Frame::Try(clause)) => clause,
+            Some(_) => unreachable!("a try statement resumed with another statement's frame"),
+        };
+        let environment = Environment::new_enclosed(self.environment.clone());
+        let (clause, result) = match (resumed, catch) {
+            (TryClause::Body, catch) => match (execute_block(self, body, environment), catch) {
+                (Err(error), Some((name, handler))) => {
+                    let mut environment = Environment::new_enclosed(self.environment.clone());
+                    environment.put(name.to_string(), error.into_value());
+                    (TryClause::Catch, execute_block(self, handler, environment))
+                },
+                (result, _) => (TryClause::Body, result),
+            },
+            (TryClause::Catch, Some((_, handler))) => (TryClause::Catch, execute_block(self, handler, environment)),
+            (TryClause::Catch, None) => unreachable!("only a try statement with a catch clause suspends in it"),
+            (TryClause::Finally(result), _) => {
+                let finally = finally.expect("only a try statement with a finally clause suspends in it");
+                return self.execute_finally(finally, result, execute_block)
+            },
+        };
+        if let Ok(Flow::Yield(value)) = result {
+            return Ok(self.suspended(Flow::Yield(value), || Frame::Try(clause)))
+        }
+        match finally {
+            None => result,
+            Some(finally) => self.execute_finally(finally, result, execute_block),
+        }
+    }
+
+    /// Runs the `finally` clause of a `try` statement whose other clauses finished with `result`.
+    fn execute_finally<S>(&mut self, finally : &[S], result : Result<Flow, RuntimeError>,
+                          execute_block : fn(&mut Interpreter, &[S], Environment) -> Result<Flow, RuntimeError>)
+                          -> Result<Flow, RuntimeError> {
+        let environment = Environment::new_enclosed(self.environment.clone());
+        match execute_block(self, finally, environment)? {
+            Flow::Normal => result,
+            Flow::Yield(value) => Ok(self.suspended(Flow::Yield(value), || Frame::Try(TryClause::Finally(result)))),
+            flow => Ok(flow),
+        }
+    }
+
+    /// Records `frame` for the statement which produced `flow` if it's suspending a generator.
+    fn suspended(&mut self, flow : Flow, frame : impl FnOnce() -> Frame) -> Flow {
+        if let Flow::Yield(_) = flow {
+            let frame = frame();
+            self.frames.push(frame);
+        }
+        flow
+    }
+
+    /// Records the position of the statement which produced `flow`, if it's suspending a generator.
+    fn suspended_statements(&mut self, flow : Flow, index : usize) -> Flow {
+        let environment = self.environment.clone();
+        self.suspended(flow, || Frame::Statements { index, environment })
+    }
+
+    /// When resuming a generator, switches to the scope the statements being resumed were running
+    /// in. Produces the position of the statement to carry on from.
+    fn resume_statements(&mut self) -> usize {
+        match self.frames.pop() {
+            None => 0,
+            Some(Frame::Statements { index, environment }) => {
+                self.environment = environment;
+                index
+            },
+            Some(_) => unreachable!("statements resumed with another statement's frame"),
+        }
+    }
+
+    /// Whether a loop is being resumed, in which case its body carries on without checking the
+    /// condition first.
+    fn resume_loop(&mut self) -> bool {
+        match self.frames.pop() {
+            None => false,
+            Some(Frame::Loop) => true,
+            Some(_) => unreachable!("a loop resumed with another statement's frame"),
+        }
+    }
+
+    /// The arm a match statement being resumed was running, and the scope of its bindings.
+    fn resume_arm(&mut self) -> Option<(usize, Rc<RefCell<Environment>>)> {
+        match self.frames.pop() {
+            None => None,
+            Some(Frame::Arm { arm, environment }) => Some((arm, environment)),
+            Some(_) => unreachable!("a match statement resumed with another statement's frame"),
+        }
+    }
+
+    /// Whether the `yield` being run is the one its generator is resuming from, which carries on
+    /// past it rather than suspending again.
+    fn resume_yield(&mut self) -> bool {
+        match self.frames.pop() {
+            None => false,
+            Some(Frame::Yield) => true,
+            Some(_) => unreachable!("a yield resumed with another statement's frame"),
+        }
+    }
+
+    /// Finds the first arm whose pattern matches `subject` and whose guard passes. Produces its
+    /// position along with the scope holding the pattern's bindings, which its body runs in.
+    fn select_arm<'b, G : 'b>(&mut self, subject : &Value, arms : impl Iterator<Item = (&'b Pattern, Option<&'b G>)>,
+                             evaluate_guard : fn(&mut Interpreter, &G) -> Result<Value, RuntimeError>, span : Span)
+                             -> Result<(usize, Rc<RefCell<Environment>>), RuntimeError> {
+        for (position, (pattern, guard)) in arms.enumerate() {
+            let mut bindings = vec![];
+            if !match_pattern(pattern, subject, &self.environment.borrow(), &mut bindings).map_err(RuntimeError::at(span))? {
+                continue
+            }
+            let mut environment = Environment::new_enclosed(self.environment.clone());
+            for (name, value) in bindings {
+                environment.put(name, value);
+            }
+            let environment = Rc::new(RefCell::new(environment));
+            if let Some(guard) = guard {
+                if !self.in_environment(environment.clone(), |this| evaluate_guard(this, guard))?.is_truthy() {
+                    continue
+                }
+            }
+            return Ok((position, environment))
+        }
+        Err(RuntimeError::new(format!("No match arm matches {}", subject), span))
+    }
+
+    /// Produces the module at `path`, running it first if this is the first time it's imported.
+    /// Modules are run the same way as the importer, either interpreting the AST or lowering it.
+    fn import(&mut self, path : &str, span : Span, lower : bool) -> Result<Value, RuntimeError> {
+        let file = self.modules.resolve(path).map_err(RuntimeError::at(span))?;
+        if let Some(module) = self.modules.loaded(&file) {
+            return Ok(Value::Module(module))
+        }
+        self.modules.begin(file.clone(), path).map_err(RuntimeError::at(span))?;
+        let result = self.run_module(&file, path, span, lower);
+        self.modules.finish(result).map(Value::Module)
+    }
+
+    /// Runs a module in a new global scope, so it can't see or change the importer's globals.
+    fn run_module(&mut self, file : &Path, path : &str, span : Span, lower : bool) -> Result<Module, RuntimeError> {
+        let source = fs::read_to_string(file)
+            .map_err(|err| RuntimeError::new(format!("Can't read module {:?}: {}", path, err), span))?;
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().clone();
+        if let Some(err) = scanner.errors.first() {
+            return Err(format!("In module {:?}: {}", path, err).into())
+        }
+        let stmts = Parser::new(&tokens).parse()
+            .map_err(|err| format!("In module {:?}: {}", path, err))?;
+        let analysis = analysis::analyze(&stmts);
+        for warning in &analysis.warnings {
+            writeln!(self.output, "Warning: In module {:?}: {}", path, warning).map_err(|e| RuntimeError::from(e.to_string()))?;
+        }
+        if let Some(err) = analysis.errors.first() {
+            return Err(format!("In module {:?}: {}", path, err).into())
+        }
+
+        let environment = Rc::new(RefCell::new(Environment::new()));
+        self.in_environment(environment.clone(), |this| if lower {
+            let core = Lowerer::new().lower(&stmts)?;
+            this.execute_ir_all(&core)
+        } else {
+            this.execute_all(&stmts)
+        })?;
+        Ok(Module::new(path.to_string(), module::exported_names(&stmts), environment))
+    }
+
+    fn declare(&mut self, name : String, value : Value, constant : bool, span : Span) -> Result<(), RuntimeError> {
+        self.environment.borrow_mut().declare(name, value, constant)
+            .map_err(RuntimeError::at(span))
+    }
+
+    /// Declares each name `pattern` binds, once the whole of `value` is known to fit the pattern.
+    fn declare_pattern(&mut self, pattern : &Pattern, value : Value, constant : bool, span : Span) -> Result<(), RuntimeError> {
+        let mut bindings = vec![];
+        destructure(pattern, &value, &mut bindings).map_err(RuntimeError::at(span))?;
+        for (name, value) in bindings {
+            self.declare(name, value, constant, span)?;
+        }
+        Ok(())
+    }
+
+    /// Assigns to each name `pattern` binds, once the whole of `value` is known to fit the pattern.
+    fn assign_pattern(&mut self, pattern : &Pattern, value : Value, span : Span) -> Result<Value, RuntimeError> {
+        let mut bindings = vec![];
+        destructure(pattern, &value, &mut bindings).map_err(RuntimeError::at(span))?;
+        for (name, value) in bindings {
+            self.assign(&name, value, span)?;
+        }
+        Ok(value)
+    }
+
+    fn define(&mut self, name : String, value : Value) {
+        self.environment.borrow_mut().put(name, value);
+    }
+
+    fn closure(&self, name : Option<String>, body : FunctionBody) -> Value {
+        let (params, required, rest) = match &body {
+            FunctionBody::Surface(function) =>
+                (function.params.iter().map(|param| param.0.clone()).collect(), function.required(), function.rest),
+            FunctionBody::Core(function) => (function.params.clone(), function.required(), function.rest),
+            FunctionBody::Resume(_) => (vec![], 0, false),
+        };
+        Value::Function(Rc::new(Closure { name, params, required, rest, body, closure: self.environment.clone() }))
+    }
+
+    fn call(&mut self, callee : Value, arguments : Vec<Value>, span : Span) -> Result<Value, RuntimeError> {
+        self.call_named(callee, arguments, vec![], span)
+    }
+
+    /// Calls `callee` with the positional `arguments`, followed by the `named` ones.
+    fn call_named(&mut self, callee : Value, arguments : Vec<Value>, named : Vec<(String, Value)>, span : Span)
+                  -> Result<Value, RuntimeError> {
+        // The parser limits the arguments written in a call, but spreading a list can pass more.
+        if arguments.len() + named.len() > MAX_ARGUMENTS {
+            return Err(RuntimeError::new(format!("Can't have more than {} arguments but got {}",
+                               MAX_ARGUMENTS, arguments.len() + named.len()), span))
+        }
+        let function = match callee {
+            Value::Function(function) => function,
+            Value::Native(native) => {
+                if !named.is_empty() {
+                    return Err(RuntimeError::new(format!("Can't pass named arguments to {}", native.name), span))
+                }
+                if arguments.len() != native.arity {
+                    return Err(RuntimeError::new(format!("Expected {} arguments but got {}",
+                                       native.arity, arguments.len()), span))
                 }
+                return native.call(arguments)
+                    .map_err(|err| RuntimeError::new(err, span).unwound_from(native.name, span))
             },
-            Literal::STRING(str_literal) => {Ok(Value::String(str_literal.clone()))},
-            Literal::NUMBER(num_literal) => {Ok(Value::Number(num_literal.clone()))},
+            other => return Err(RuntimeError::new(format!("Can only call functions, not {}", other.type_name()), span)),
+        };
+        let environment = self.bind_arguments(&function, arguments, named, span)?;
+
+        if let FunctionBody::Resume(generator) = &function.body {
+            return self.resume(generator, span)
+        }
+
+        if function.body.generator() {
+            let state = RefCell::new(GeneratorState::Ready(environment));
+            return Ok(Value::Generator(Rc::new(Generator { function, state })))
+        }
+        let flow = self.execute_body(&function, environment)
+            .map_err(|err| err.unwound_from(function.name(), span))?;
+        // The parser rejects `break` and `continue` outside of a loop, and `yield` makes the
+        // function a generator, so they never reach here.
+        match flow {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal | Flow::Break | Flow::Continue | Flow::Yield(_) => Ok(Value::Nil),
         }
     }
 
-    fn interpret_binary(&self, exp : &BinaryExp) -> Result<Value, String> {
-        let right = self.evaluate(exp.right.as_ref());
-        let left = self.evaluate(exp.left.as_ref());
+    /// The scope a call to `function` runs in, binding each parameter to its argument, or to its
+    /// default when it wasn't passed.
+    fn bind_arguments(&mut self, function : &Closure, mut arguments : Vec<Value>, named : Vec<(String, Value)>, span : Span)
+                      -> Result<Environment, RuntimeError> {
+        let positional = function.params.len() - function.rest as usize;
+        if !function.rest && arguments.len() > positional {
+            let expected = if function.required == positional { "" } else { "at most " };
+            return Err(RuntimeError::new(format!("Expected {}{} arguments but got {}",
+                               expected, positional, arguments.len()), span))
+        }
+        let rest = arguments.split_off(arguments.len().min(positional));
+        let mut slots : Vec<Option<Value>> = arguments.into_iter().map(Some).collect();
+        slots.resize(positional, None);
+        for (name, value) in named {
+            match function.params[..positional].iter().position(|param| *param == name) {
+                None => return Err(RuntimeError::new(format!("Unknown parameter '{}'", name), span)),
+                Some(position) if slots[position].is_some() =>
+                    return Err(RuntimeError::new(format!("Got more than one argument for parameter '{}'", name), span)),
+                Some(position) => slots[position] = Some(value),
+            }
+        }
+        if let Some(missing) = slots[..function.required].iter().position(Option::is_none) {
+            return Err(RuntimeError::new(format!("Missing argument for parameter '{}'", function.params[missing]), span))
+        }
 
-        fn match_numbers(l : Result<Value, String>, r : Result<Value, String>)
-                         -> Result<(i64, i64), String> {
-            match_items(l, r)
-                .and_then(|x| match x {
-                    MatchedValues::Number(l, r) => Ok((l, r)),
-                    x => {Err("Non-number values not supported with operator -".to_string())},
+        let mut environment = Environment::new_enclosed(function.closure.clone());
+        if function.rest {
+            environment.put(function.params[positional].clone(), Value::List(Rc::new(RefCell::new(rest))));
+        }
+        let mut defaulted = vec![];
+        for (position, slot) in slots.into_iter().enumerate() {
+            match slot {
+                Some(value) => environment.put(function.params[position].clone(), value),
+                None => defaulted.push(position),
+            }
+        }
+        if defaulted.is_empty() {
+            return Ok(environment)
+        }
+        // Defaults can refer to the other parameters, so they're evaluated in a scope holding
+        // them, which the body then runs inside of.
+        let scope = Rc::new(RefCell::new(environment));
+        for position in defaulted {
+            let value = self.in_environment(scope.clone(), |this| this.evaluate_default(function, position))
+                .map_err(|err| err.unwound_from(function.name(), span))?;
+            scope.borrow_mut().put(function.params[position].clone(), value);
+        }
+        Ok(Environment::new_enclosed(scope))
+    }
+
+    fn evaluate_default(&mut self, function : &Closure, position : usize) -> Result<Value, RuntimeError> {
+        match &function.body {
+            FunctionBody::Surface(body) => self.evaluate(body.defaults[position].as_ref().expect("a parameter with a default")),
+            FunctionBody::Core(body) => self.evaluate_ir(body.defaults[position].as_ref().expect("a parameter with a default")),
+            FunctionBody::Resume(_) => unreachable!("a generator's next method has no parameters"),
+        }
+    }
+
+    /// Runs the body of `function` in `environment`, which holds its arguments.
+    fn execute_body(&mut self, function : &Closure, environment : Environment) -> Result<Flow, RuntimeError> {
+        match &function.body {
+            FunctionBody::Surface(body) => self.execute_block(&body.body, environment),
+            FunctionBody::Core(body) => self.execute_ir_block(&body.body, environment),
+            FunctionBody::Resume(_) => unreachable!("resuming a generator doesn't run a body of its own"),
+        }
+    }
+
+    /// Runs `generator` until it yields or finishes, producing a map with the `value` it yielded
+    /// and whether it's `done`. Once done, it stays done.
+    fn resume(&mut self, generator : &Generator, span : Span) -> Result<Value, RuntimeError> {
+        let state = mem::replace(&mut *generator.state.borrow_mut(), GeneratorState::Running);
+        // Resuming starts with an empty scope, which the frame of the body replaces with the one
+        // it was running in.
+        let (environment, frames) = match state {
+            GeneratorState::Ready(environment) => (environment, vec![]),
+            GeneratorState::Suspended(frames) => (Environment::new(), frames),
+            GeneratorState::Running =>
+                return Err(RuntimeError::new("Can't resume a generator while it's running".to_string(), span)),
+            GeneratorState::Finished => {
+                *generator.state.borrow_mut() = GeneratorState::Finished;
+                return Ok(native::iteration(Value::Nil, true))
+            },
+        };
+        let enclosing_frames = mem::replace(&mut self.frames, frames);
+        let flow = self.execute_body(&generator.function, environment);
+        let frames = mem::replace(&mut self.frames, enclosing_frames);
+        let (state, result) = match flow {
+            Ok(Flow::Yield(value)) => (GeneratorState::Suspended(frames), Ok(native::iteration(value, false))),
+            Ok(_) => (GeneratorState::Finished, Ok(native::iteration(Value::Nil, true))),
+            Err(err) => (GeneratorState::Finished, Err(err.unwound_from(generator.function.name(), span))),
+        };
+        *generator.state.borrow_mut() = state;
+        result
+    }
+
+    fn print(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        writeln!(self.output, "{}", value).map_err(|e| RuntimeError::from(e.to_string()))
+    }
+
+    /// Executes statements that have already been lowered to the core IR.
+    pub fn interpret_ir(&mut self, stmts : &[ir::Stmt]) -> Result<(), String> {
+        self.execute_ir_all(stmts).map(|_| ()).map_err(|err| err.to_string())
+    }
+
+    fn execute_ir_all(&mut self, stmts : &[ir::Stmt]) -> Result<Flow, RuntimeError> {
+        let start = self.resume_statements();
+        for (index, stmt) in stmts.iter().enumerate().skip(start) {
+            match self.execute_ir(stmt)? {
+                Flow::Normal => {},
+                flow => return Ok(self.suspended_statements(flow, index)),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn execute_ir_block(&mut self, stmts : &[ir::Stmt], environment : Environment) -> Result<Flow, RuntimeError> {
+        let previous = mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = self.execute_ir_all(stmts);
+        self.environment = previous;
+        result
+    }
+
+    fn execute_ir(&mut self, stmt : &ir::Stmt) -> Result<Flow, RuntimeError> {
+        match stmt {
+            ir::Stmt::Let(decl) => {
+                let value = self.evaluate_ir(&decl.value)?;
+                self.declare(decl.name.clone(), value, decl.constant, decl.span)?;
+                Ok(Flow::Normal)
+            },
+            ir::Stmt::Destructure(decl) => {
+                let value = self.evaluate_ir(&decl.value)?;
+                self.declare_pattern(&decl.pattern, value, decl.constant, decl.span)?;
+                Ok(Flow::Normal)
+            },
+            ir::Stmt::Expression(exp) => self.evaluate_ir(exp).map(|_| Flow::Normal),
+            ir::Stmt::Print(exp) => {
+                let value = self.evaluate_ir(exp)?;
+                self.print(&value).map(|_| Flow::Normal)
+            },
+            ir::Stmt::Block(stmts) => {
+                let environment = Environment::new_enclosed(self.environment.clone());
+                self.execute_ir_block(stmts, environment)
+            },
+            ir::Stmt::Return(exp) => self.evaluate_ir(exp).map(Flow::Return),
+            ir::Stmt::Branch(branch) => {
+                let then = match self.frames.pop() {
+                    None => self.evaluate_ir(&branch.condition)?.is_truthy(),
+                    Some(Frame::Branch(then)) => then,
+                    Some(_) => unreachable!("a branch resumed with another statement's frame"),
+                };
+                let flow = match (then, &branch.else_branch) {
+                    (true, _) => self.execute_ir(&branch.then_branch)?,
+                    (false, None) => Flow::Normal,
+                    (false, Some(else_branch)) => self.execute_ir(else_branch)?,
+                };
+                Ok(self.suspended(flow, || Frame::Branch(then)))
+            },
+            ir::Stmt::Loop(ir_loop) => {
+                let mut resuming = self.resume_loop();
+                while mem::take(&mut resuming) || self.evaluate_ir(&ir_loop.condition)?.is_truthy() {
+                    match self.execute_ir(&ir_loop.body)? {
+                        Flow::Normal | Flow::Continue => {},
+                        Flow::Break => break,
+                        flow => return Ok(self.suspended(flow, || Frame::Loop)),
+                    }
+                    if let Some(step) = &ir_loop.step {
+                        self.evaluate_ir(step)?;
+                    }
                 }
-                )
+                Ok(Flow::Normal)
+            },
+            ir::Stmt::ForIn(for_in) => self.execute_for_in(&for_in.name, &for_in.iterable, Interpreter::evaluate_ir,
+                                                           &*for_in.body, Interpreter::execute_ir, for_in.span),
+            ir::Stmt::Match(ir_match) => {
+                let (arm, environment) = match self.resume_arm() {
+                    Some(resumed) => resumed,
+                    None => {
+                        let subject = self.evaluate_ir(&ir_match.subject)?;
+                        let arms = ir_match.arms.iter().map(|arm| (&arm.pattern, arm.guard.as_ref()));
+                        self.select_arm(&subject, arms, Interpreter::evaluate_ir, ir_match.span)?
+                    },
+                };
+                let flow = self.in_environment(environment.clone(), |this| this.execute_ir(&ir_match.arms[arm].body))?;
+                Ok(self.suspended(flow, || Frame::Arm { arm, environment }))
+            },
+            ir::Stmt::Break => Ok(Flow::Break),
+            ir::Stmt::Continue => Ok(Flow::Continue),
+            ir::Stmt::Import(import) => {
+                let module = self.import(&import.path, import.span, true)?;
+                self.declare(import.name.clone(), module, false, import.span)?;
+                Ok(Flow::Normal)
+            },
+            ir::Stmt::Enum(decl) => {
+                let enumeration = Value::Enum(Rc::new(Enum::new(decl.name.clone(), &decl.variants)));
+                self.declare(decl.name.clone(), enumeration, false, decl.span)?;
+                Ok(Flow::Normal)
+            },
+            ir::Stmt::Throw(throw) => {
+                let value = self.evaluate_ir(&throw.value)?;
+                Err(RuntimeError::thrown(value, throw.span))
+            },
+            ir::Stmt::Try(ir_try) => {
+                let catch = ir_try.catch.as_ref().map(|catch| (catch.name.as_str(), catch.body.as_slice()));
+                self.execute_try(&ir_try.body, catch, ir_try.finally.as_deref(), Interpreter::execute_ir_block)
+            },
+            ir::Stmt::Yield(exp) => {
+                if self.resume_yield() {
+                    return Ok(Flow::Normal)
+                }
+                let value = self.evaluate_ir(exp)?;
+                Ok(self.suspended(Flow::Yield(value), || Frame::Yield))
+            },
         }
+    }
 
-        // TODO: Work out whether or not using &str in stead of String is more appropriate / efficient
-        match &exp.operator {
-            Token { token_type: TokenType::MINUS, lexeme, line } => {
-                match_numbers(left, right)
-                    .map(|(l, r)| Value::Number(l - r))
+    fn evaluate_ir(&mut self, exp : &ir::Exp) -> Result<Value, RuntimeError> {
+        match exp {
+            ir::Exp::Constant(ir::Constant::Nil) => Ok(Value::Nil),
+            ir::Exp::Constant(ir::Constant::Boolean(b)) => Ok(Value::Boolean(*b)),
+            ir::Exp::Constant(ir::Constant::Number(num)) => Ok(Value::Number(*num)),
+            ir::Exp::Constant(ir::Constant::String(st)) => Ok(Value::String(st.clone())),
+            ir::Exp::Variable(variable) => self.lookup(&variable.name, variable.span),
+            ir::Exp::Binary(binary) => {
+                let left = self.evaluate_ir(&binary.left)?;
+                let right = self.evaluate_ir(&binary.right)?;
+                binary_values(binary.operator, Ok(left), Ok(right)).map_err(RuntimeError::at(binary.span))
+            },
+            ir::Exp::Unary(unary) => {
+                let right = self.evaluate_ir(&unary.right)?;
+                unary_value(unary.operator, Ok(right)).map_err(RuntimeError::at(unary.span))
+            },
+            ir::Exp::If(if_exp) => {
+                if self.evaluate_ir(&if_exp.condition)?.is_truthy() {
+                    self.evaluate_ir(&if_exp.then_branch)
+                } else {
+                    self.evaluate_ir(&if_exp.else_branch)
+                }
+            },
+            ir::Exp::Local(local) => {
+                let value = self.evaluate_ir(&local.value)?;
+                self.define(local.name.clone(), value);
+                let result = self.evaluate_ir(&local.body);
+                self.environment.borrow_mut().remove(&local.name);
+                result
+            },
+            ir::Exp::Call(call) => {
+                let callee = self.evaluate_ir(&call.callee)?;
+                let arguments = self.evaluate_ir_elements(&call.arguments, call.span)?;
+                let mut named = vec![];
+                for (name, argument) in &call.named {
+                    named.push((name.clone(), self.evaluate_ir(argument)?));
+                }
+                self.call_named(callee, arguments, named, call.span)
             },
-            Token { token_type: TokenType::SLASH, lexeme, line } => {
-                match_numbers(left, right)
-                    .map(|(l, r)| Value::Number(l / r))
+            ir::Exp::Assign(assign) => {
+                let value = self.evaluate_ir(&assign.value)?;
+                self.assign(&assign.name, value, assign.span)
             },
-            Token { token_type: TokenType::STAR, lexeme, line } => {
-                match_numbers(left, right)
-                    .map(|(l, r)| Value::Number(l * r))
+            ir::Exp::DestructureAssign(assign) => {
+                let value = self.evaluate_ir(&assign.value)?;
+                self.assign_pattern(&assign.pattern, value, assign.span)
             },
-            Token { token_type: TokenType::GREATER, lexeme, line } => {
-                match_numbers(left, right)
-                    .map(|(l, r)| Value::Boolean(l > r))
+            ir::Exp::List(list) => {
+                let values = self.evaluate_ir_elements(&list.elements, list.span)?;
+                Ok(Value::List(Rc::new(RefCell::new(values))))
             },
-            Token { token_type: TokenType::GreaterEqual, lexeme, line } => {
-                match_numbers(left, right)
-                    .map(|(l, r)| Value::Boolean(l >= r))
+            ir::Exp::Map(map) => {
+                let mut entries = vec![];
+                for (key, value) in &map.entries {
+                    entries.push((self.evaluate_ir(key)?, self.evaluate_ir(value)?));
+                }
+                map_value(entries).map_err(RuntimeError::at(map.span))
             },
-            Token { token_type: TokenType::LESS, lexeme, line } => {
-                match_numbers(left, right)
-                    .map(|(l, r)| Value::Boolean(l < r))
+            ir::Exp::Index(index) => {
+                let object = self.evaluate_ir(&index.object)?;
+                let position = self.evaluate_ir(&index.index)?;
+                index_value(&object, &position).map_err(RuntimeError::at(index.span))
             },
-            Token { token_type: TokenType::LessEqual, lexeme, line } => {
-                match_numbers(left, right)
-                    .map(|(l, r)| Value::Boolean(l <= r))
+            ir::Exp::SetIndex(set) => {
+                let object = self.evaluate_ir(&set.object)?;
+                let position = self.evaluate_ir(&set.index)?;
+                let value = self.evaluate_ir(&set.value)?;
+                set_index_value(&object, &position, value).map_err(RuntimeError::at(set.span))
             },
-            Token { token_type: TokenType::PLUS, lexeme, line } => {
-                match_items(left, right)
-                    .and_then(|x| match x {
-                        MatchedValues::Number(l, r) => Ok(Value::Number(l + r)),
-                        MatchedValues::String(l, r) => {
-                            Ok(Value::String(l + r.as_str()))},
-                        x => {
-                            Err("Only numbers and strings are supported for operator +".to_string())}
-                    })
+            ir::Exp::Get(get) => {
+                let object = self.evaluate_ir(&get.object)?;
+                property_value(&object, &get.name).map_err(RuntimeError::at(get.span))
             },
-            Token { token_type: TokenType::BangEqual, lexeme, line } => {
-                let items : Result<Vec<Value>, _> = [left, right].iter().cloned().collect();
-                items.map(|x| Value::Boolean(!x[0].is_equal(&x[1])))
+            ir::Exp::Match(ir_match) => {
+                let subject = self.evaluate_ir(&ir_match.subject)?;
+                let arms = ir_match.arms.iter().map(|arm| (&arm.pattern, arm.guard.as_ref()));
+                let (arm, environment) = self.select_arm(&subject, arms, Interpreter::evaluate_ir, ir_match.span)?;
+                self.in_environment(environment, |this| this.evaluate_ir(&ir_match.arms[arm].body))
             },
-            Token { token_type: TokenType::EqualEqual, lexeme, line } => {
-                let items : Result<Vec<Value>, _> = [left, right].iter().cloned().collect();
-                items.map(|x| Value::Boolean(x[0].is_equal(&x[1])))
+            ir::Exp::Closure(function) => Ok(self.closure(function.name.clone(), FunctionBody::Core(function.clone()))),
+        }
+    }
+
+    fn lookup(&self, name : &str, span : Span) -> Result<Value, RuntimeError> {
+        match self.environment.borrow().get(name) {
+            None => {Err(RuntimeError::new(format!("Undefined variable: {}", name), span))},
+            Some(value) => {Ok(value)},
+        }
+    }
+
+    fn assign(&mut self, name : &str, value : Value, span : Span) -> Result<Value, RuntimeError> {
+        self.environment.borrow_mut().assign(name, value.clone())
+            .map(|_| value)
+            .map_err(RuntimeError::at(span))
+    }
+
+    fn evaluate(&mut self, exp : &Exp) -> Result<Value, RuntimeError> {
+        match exp {
+            Exp::BinaryExp(bin_exp) => self.interpret_binary(bin_exp),
+            Exp::GroupingExp(grouping_exp) => self.interpret_grouping(grouping_exp),
+            Exp::UnaryExp(unary_exp) => self.interpret_unary(unary_exp),
+            Exp::LiteralExp(literal_exp) => self.interpret_literal(literal_exp),
+            Exp::LogicalExp(logical_exp) => self.interpret_logical(logical_exp),
+            Exp::ConditionalExp(conditional_exp) => self.interpret_conditional(conditional_exp),
+            Exp::CommaExp(comma_exp) => self.interpret_comma(comma_exp),
+            Exp::CallExp(_) | Exp::IndexExp(_) | Exp::GetExp(_) =>
+                self.evaluate_chain(exp).map(|value| value.unwrap_or(Value::Nil)),
+            Exp::LambdaExp(lambda_exp) => Ok(self.closure(None, FunctionBody::Surface(lambda_exp.function.clone()))),
+            Exp::AssignExp(assign_exp) => self.interpret_assign(assign_exp),
+            Exp::ListExp(list_exp) => self.interpret_list(list_exp),
+            Exp::MapExp(map_exp) => self.interpret_map(map_exp),
+            Exp::CompoundAssignExp(assign_exp) => self.interpret_compound_assign(assign_exp),
+            Exp::IncrementExp(increment_exp) => self.interpret_increment(increment_exp),
+            Exp::DestructureExp(exp) => {
+                let value = self.evaluate(&exp.value)?;
+                self.assign_pattern(&exp.pattern, value, exp.span)
             },
-            Token { token_type, lexeme, line } =>
-                Err(format!("Unknown TokenType for binary expression: {:?}, line: {}", token_type, line)),
+            Exp::MatchExp(match_exp) => {
+                let subject = self.evaluate(&match_exp.subject)?;
+                let arms = match_exp.arms.iter().map(|arm| (&arm.pattern, arm.guard.as_ref()));
+                let (arm, environment) = self.select_arm(&subject, arms, Interpreter::evaluate, match_exp.span)?;
+                self.in_environment(environment, |this| this.evaluate(&match_exp.arms[arm].body))
+            },
+            Exp::SetIndexExp(set_index_exp) => self.interpret_set_index(set_index_exp),
         }
     }
 
-    fn interpret_grouping(&self, exp : &GroupingExp) -> Result<Value, String> {
+    fn interpret_literal(&mut self, exp : &LiteralExp) -> Result<Value, RuntimeError> {
+        match &exp.value {
+            Literal::IDENTIFIER(id) => self.lookup(id, exp.span),
+            Literal::STRING(str_literal) => {Ok(Value::String(str_literal.clone()))},
+            Literal::NUMBER(num_literal) => {Ok(Value::Number(*num_literal))},
+            Literal::BOOLEAN(bool_literal) => {Ok(Value::Boolean(*bool_literal))},
+            Literal::NIL => {Ok(Value::Nil)},
+        }
+    }
+
+    fn interpret_binary(&mut self, exp : &BinaryExp) -> Result<Value, RuntimeError> {
+        let left = self.evaluate(exp.left.as_ref())?;
+        let right = self.evaluate(exp.right.as_ref())?;
+        binary_values(exp.operator, Ok(left), Ok(right)).map_err(RuntimeError::at(exp.span))
+    }
+
+    fn interpret_grouping(&mut self, exp : &GroupingExp) -> Result<Value, RuntimeError> {
         self.evaluate(exp.exp.as_ref())
     }
 
-    fn interpret_unary(&self, exp : &UnaryExp) -> Result<Value, String> {
-        let right = self.evaluate(exp.right.as_ref());
-        match &exp.operator {
-            Token { token_type: TokenType::BANG, lexeme: _, line: _ } => {
-                right.map(|x| Value::Boolean(!x.is_truthy()))
+    fn interpret_unary(&mut self, exp : &UnaryExp) -> Result<Value, RuntimeError> {
+        let right = self.evaluate(exp.right.as_ref())?;
+        unary_value(exp.operator, Ok(right)).map_err(RuntimeError::at(exp.span))
+    }
+
+    fn interpret_conditional(&mut self, exp : &ConditionalExp) -> Result<Value, RuntimeError> {
+        if self.evaluate(exp.condition.as_ref())?.is_truthy() {
+            self.evaluate(exp.then_branch.as_ref())
+        } else {
+            self.evaluate(exp.else_branch.as_ref())
+        }
+    }
+
+    fn interpret_comma(&mut self, exp : &CommaExp) -> Result<Value, RuntimeError> {
+        self.evaluate(exp.left.as_ref())?;
+        self.evaluate(exp.right.as_ref())
+    }
+
+    /// Evaluates a call, index or property access. It produces `None` when an optional property
+    /// access (`?.`) in the chain of them found nil, which skips the rest of the chain.
+    fn evaluate_chain(&mut self, exp : &Exp) -> Result<Option<Value>, RuntimeError> {
+        let object = match exp {
+            Exp::CallExp(CallExp { callee: object, .. }) |
+            Exp::IndexExp(IndexExp { object, .. }) |
+            Exp::GetExp(GetExp { object, .. }) => match self.evaluate_chain(object)? {
+                Some(object) => object,
+                None => return Ok(None),
             },
-            Token { token_type: TokenType::MINUS, lexeme: _, line: _ } => {
-                right.and_then(|x| match x {
-                    Value::Number(value) => {Ok(Value::Number(-value))},
-                    other => {Err(format!("Minus can't be used with this value: {:?}", other))},
-                })
+            other => return self.evaluate(other).map(Some),
+        };
+        match exp {
+            Exp::CallExp(call_exp) => self.interpret_call(call_exp, object).map(Some),
+            Exp::IndexExp(index_exp) => self.interpret_index(index_exp, object).map(Some),
+            Exp::GetExp(get_exp) if get_exp.optional && object == Value::Nil => Ok(None),
+            Exp::GetExp(get_exp) => Ok(Some(property_value(&object, &get_exp.name.0).map_err(RuntimeError::at(get_exp.span))?)),
+            _ => unreachable!("only chain links have an object"),
+        }
+    }
+
+    fn interpret_call(&mut self, exp : &CallExp, callee : Value) -> Result<Value, RuntimeError> {
+        let arguments = self.evaluate_elements(&exp.arguments, exp.span)?;
+        let mut named = vec![];
+        for (name, argument) in &exp.named {
+            named.push((name.0.clone(), self.evaluate(argument)?));
+        }
+        self.call_named(callee, arguments, named, exp.span)
+    }
+
+    fn evaluate_elements(&mut self, elements : &[Element], span : Span) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = vec![];
+        for element in elements {
+            match element {
+                Element::Single(exp) => values.push(self.evaluate(exp)?),
+                Element::Spread(exp) => {
+                    let iterable = self.evaluate(exp)?;
+                    self.spread(iterable, &mut values, span)?;
+                },
+            }
+        }
+        Ok(values)
+    }
+
+    fn interpret_assign(&mut self, exp : &AssignExp) -> Result<Value, RuntimeError> {
+        let value = self.evaluate(&exp.value)?;
+        self.assign(&exp.name.0, value, exp.span)
+    }
+
+    fn interpret_list(&mut self, exp : &ListExp) -> Result<Value, RuntimeError> {
+        let elements = self.evaluate_elements(&exp.elements, exp.span)?;
+        Ok(Value::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn interpret_map(&mut self, exp : &MapExp) -> Result<Value, RuntimeError> {
+        let mut entries = vec![];
+        for (key, value) in &exp.entries {
+            entries.push((self.evaluate(key)?, self.evaluate(value)?));
+        }
+        map_value(entries).map_err(RuntimeError::at(exp.span))
+    }
+
+    fn interpret_index(&mut self, exp : &IndexExp, object : Value) -> Result<Value, RuntimeError> {
+        let index = self.evaluate(&exp.index)?;
+        index_value(&object, &index).map_err(RuntimeError::at(exp.span))
+    }
+
+    fn interpret_set_index(&mut self, exp : &SetIndexExp) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(&exp.object)?;
+        let index = self.evaluate(&exp.index)?;
+        let value = self.evaluate(&exp.value)?;
+        set_index_value(&object, &index, value).map_err(RuntimeError::at(exp.span))
+    }
+
+    fn interpret_compound_assign(&mut self, exp : &CompoundAssignExp) -> Result<Value, RuntimeError> {
+        let place = self.place(&exp.target)?;
+        let current = self.read_place(&place, exp.span)?;
+        let value = self.evaluate(&exp.value)?;
+        let updated = binary_values(exp.operator, Ok(current), Ok(value)).map_err(RuntimeError::at(exp.span))?;
+        self.write_place(place, updated, exp.span)
+    }
+
+    fn interpret_increment(&mut self, exp : &IncrementExp) -> Result<Value, RuntimeError> {
+        let place = self.place(&exp.target)?;
+        let current = self.read_place(&place, exp.span)?;
+        let updated = binary_values(exp.operator, Ok(current.clone()), Ok(Value::Number(1))).map_err(RuntimeError::at(exp.span))?;
+        let updated = self.write_place(place, updated, exp.span)?;
+        Ok(if exp.postfix { current } else { updated })
+    }
+
+    fn place(&mut self, target : &AssignTarget) -> Result<Place, RuntimeError> {
+        match target {
+            AssignTarget::Variable(name) => Ok(Place::Variable(name.0.clone())),
+            AssignTarget::Index { object, index } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                Ok(Place::Index(object, index))
             },
-            Token { token_type, lexeme: _, line } =>
-                panic!("Unknown TokenType for unary: {:?}, line: {}", token_type, line),
         }
     }
+
+    fn read_place(&self, place : &Place, span : Span) -> Result<Value, RuntimeError> {
+        match place {
+            Place::Variable(name) => self.lookup(name, span),
+            Place::Index(object, index) => index_value(object, index).map_err(RuntimeError::at(span)),
+        }
+    }
+
+    fn write_place(&mut self, place : Place, value : Value, span : Span) -> Result<Value, RuntimeError> {
+        match place {
+            Place::Variable(name) => self.assign(&name, value, span),
+            Place::Index(object, index) => set_index_value(&object, &index, value).map_err(RuntimeError::at(span)),
+        }
+    }
+
+    fn interpret_logical(&mut self, exp : &LogicalExp) -> Result<Value, RuntimeError> {
+        let left = self.evaluate(exp.left.as_ref())?;
+        match exp.operator {
+            LogicalOp::Or if left.is_truthy() => Ok(left),
+            LogicalOp::And if !left.is_truthy() => Ok(left),
+            LogicalOp::QuestionQuestion if left != Value::Nil => Ok(left),
+            _ => self.evaluate(exp.right.as_ref()),
+        }
+    }
+}
+
+fn binary_values(operator : BinaryOp, left : Result<Value, String>, right : Result<Value, String>)
+                 -> Result<Value, String> {
+    let overflow = || format!("Integer overflow with operator {}", operator);
+    let match_numbers = |l : Result<Value, String>, r : Result<Value, String>|
+                     -> Result<(i64, i64), String> {
+        match_items(l, r)
+            .and_then(|x| match x {
+                MatchedValues::Number(l, r) => Ok((l, r)),
+                _ => {Err(format!("Non-number values not supported with operator {}", operator))},
+            }
+            )
+    };
+
+    // TODO: Work out whether or not using &str in stead of String is more appropriate / efficient
+    match operator {
+        BinaryOp::Minus => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| l.checked_sub(r).ok_or_else(overflow))
+                .map(Value::Number)
+        },
+        BinaryOp::Slash => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| checked_division(l, r, operator))
+                .map(Value::Number)
+        },
+        BinaryOp::TildeSlash => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| {
+                    let quotient = checked_division(l, r, operator)?;
+                    // `/` rounds towards zero, which is one too high when there's a remainder and the signs differ.
+                    Ok(if l % r != 0 && (l < 0) != (r < 0) { quotient - 1 } else { quotient })
+                })
+                .map(Value::Number)
+        },
+        BinaryOp::Percent => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| {
+                    if r == 0 {
+                        return Err("Modulo by zero".to_string())
+                    }
+                    // `i64::MIN % -1` overflows in Rust, but the remainder is 0 either way.
+                    let remainder = l.wrapping_rem(r);
+                    Ok(if remainder != 0 && (remainder < 0) != (r < 0) { remainder + r } else { remainder })
+                })
+                .map(Value::Number)
+        },
+        BinaryOp::StarStar => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| {
+                    if r < 0 {
+                        return Err(format!("Exponent can't be negative: {}", r))
+                    }
+                    u32::try_from(r).ok()
+                        .and_then(|r| l.checked_pow(r))
+                        .ok_or_else(overflow)
+                })
+                .map(Value::Number)
+        },
+        BinaryOp::Star => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| l.checked_mul(r).ok_or_else(overflow))
+                .map(Value::Number)
+        },
+        BinaryOp::Ampersand => {
+            match_numbers(left, right)
+                .map(|(l, r)| Value::Number(l & r))
+        },
+        BinaryOp::Pipe => {
+            match_numbers(left, right)
+                .map(|(l, r)| Value::Number(l | r))
+        },
+        BinaryOp::Caret => {
+            match_numbers(left, right)
+                .map(|(l, r)| Value::Number(l ^ r))
+        },
+        BinaryOp::LessLess => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| Ok(l << shift_amount(r)?))
+                .map(Value::Number)
+        },
+        BinaryOp::GreaterGreater => {
+            match_numbers(left, right)
+                .and_then(|(l, r)| Ok(l >> shift_amount(r)?))
+                .map(Value::Number)
+        },
+        BinaryOp::Greater => {
+            match_numbers(left, right)
+                .map(|(l, r)| Value::Boolean(l > r))
+        },
+        BinaryOp::GreaterEqual => {
+            match_numbers(left, right)
+                .map(|(l, r)| Value::Boolean(l >= r))
+        },
+        BinaryOp::Less => {
+            match_numbers(left, right)
+                .map(|(l, r)| Value::Boolean(l < r))
+        },
+        BinaryOp::LessEqual => {
+            match_numbers(left, right)
+                .map(|(l, r)| Value::Boolean(l <= r))
+        },
+        BinaryOp::Plus => {
+            match_items(left, right)
+                .and_then(|x| match x {
+                    MatchedValues::Number(l, r) => l.checked_add(r).map(Value::Number).ok_or_else(overflow),
+                    MatchedValues::String(l, r) => {
+                        Ok(Value::String(l + r.as_str()))},
+                    _ => {
+                        Err("Only numbers and strings are supported for operator +".to_string())}
+                })
+        },
+        BinaryOp::BangEqual => {
+            let items : Result<Vec<Value>, _> = [left, right].iter().cloned().collect();
+            items.map(|x| Value::Boolean(!x[0].is_equal(&x[1])))
+        },
+        BinaryOp::EqualEqual => {
+            let items : Result<Vec<Value>, _> = [left, right].iter().cloned().collect();
+            items.map(|x| Value::Boolean(x[0].is_equal(&x[1])))
+        },
+        BinaryOp::DotDot | BinaryOp::DotDotEqual => {
+            match (left?, right?) {
+                (Value::Number(start), Value::Number(end)) =>
+                    Ok(Value::Range(Range::new(start, end, operator == BinaryOp::DotDotEqual))),
+                (start, end) => Err(format!("Range bounds must be numbers, not {} and {}", start.type_name(), end.type_name())),
+            }
+        },
+    }
+}
+
+/// Shifting an `i64` by 64 or more bits isn't defined, so those amounts are errors rather than being masked.
+fn shift_amount(amount : i64) -> Result<i64, String> {
+    if (0..64).contains(&amount) {
+        Ok(amount)
+    } else {
+        Err(format!("Shift amount must be between 0 and 63, not {}", amount))
+    }
+}
+
+fn checked_division(l : i64, r : i64, operator : BinaryOp) -> Result<i64, String> {
+    if r == 0 {
+        return Err("Division by zero".to_string())
+    }
+    l.checked_div(r).ok_or_else(|| format!("Integer overflow with operator {}", operator))
+}
+
+/// Checks whether `value` matches `pattern`, adding anything the pattern binds to `bindings`.
+/// Variant patterns look their enum up in `environment`.
+fn match_pattern(pattern : &Pattern, value : &Value, environment : &Environment, bindings : &mut Vec<(String, Value)>)
+                 -> Result<bool, String> {
+    match pattern {
+        Pattern::Wildcard => Ok(true),
+        Pattern::Literal(literal) => Ok(literal_value(literal).is_equal(value)),
+        Pattern::Binding(name) => {
+            bindings.push((name.0.clone(), value.clone()));
+            Ok(true)
+        },
+        Pattern::Variant(name, variant) => {
+            let enumeration = environment.get(&name.0)
+                .ok_or_else(|| format!("Undefined variable: {}", name.0))?;
+            Ok(property_value(&enumeration, &variant.0)?.is_equal(value))
+        },
+        Pattern::List(elements) => match value {
+            Value::List(list) => {
+                let list = list.borrow();
+                if list.len() != elements.len() {
+                    return Ok(false)
+                }
+                for (element, value) in elements.iter().zip(list.iter()) {
+                    if !match_pattern(element, value, environment, bindings)? {
+                        return Ok(false)
+                    }
+                }
+                Ok(true)
+            },
+            _ => Ok(false),
+        },
+        Pattern::Map(entries) => match value {
+            Value::Map(map) => {
+                let map = map.borrow();
+                for (key, entry) in entries {
+                    let value = MapKey::from_literal(key).and_then(|key| map.get(&key));
+                    match value {
+                        Some(value) if match_pattern(entry, value, environment, bindings)? => {},
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            },
+            _ => Ok(false),
+        },
+    }
+}
+
+/// Takes `value` apart with a declaration or assignment pattern, adding what it binds to
+/// `bindings`. Unlike `match_pattern`, a value that doesn't fit is an error.
+fn destructure(pattern : &Pattern, value : &Value, bindings : &mut Vec<(String, Value)>) -> Result<(), String> {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => Ok(()),
+        (Pattern::Binding(name), value) => {
+            bindings.push((name.0.clone(), value.clone()));
+            Ok(())
+        },
+        (Pattern::List(elements), Value::List(list)) => {
+            let list = list.borrow();
+            if list.len() != elements.len() {
+                return Err(format!("Expected {} elements to destructure but got {}", elements.len(), list.len()))
+            }
+            elements.iter().zip(list.iter())
+                .try_for_each(|(element, value)| destructure(element, value, bindings))
+        },
+        (Pattern::List(_), other) => Err(format!("Can't destructure {} as a list", other)),
+        (Pattern::Map(entries), Value::Map(map)) => {
+            let map = map.borrow();
+            entries.iter().try_for_each(|(key, entry)| {
+                let key = MapKey::from_literal(key).expect("the parser only allows valid map keys");
+                let value = map.get(&key)
+                    .ok_or_else(|| format!("Key {} not found in map", key))?;
+                destructure(entry, value, bindings)
+            })
+        },
+        (Pattern::Map(_), other) => Err(format!("Can't destructure {} as a map", other)),
+        (Pattern::Literal(_), _) | (Pattern::Variant(..), _) => unreachable!("the parser only allows names in destructuring patterns"),
+    }
+}
+
+/// The value of a literal pattern. Identifiers in patterns are bindings, so never reach here.
+fn literal_value(literal : &Literal) -> Value {
+    match literal {
+        Literal::STRING(st) => Value::String(st.clone()),
+        Literal::NUMBER(num) => Value::Number(*num),
+        Literal::BOOLEAN(b) => Value::Boolean(*b),
+        Literal::NIL | Literal::IDENTIFIER(_) => Value::Nil,
+    }
+}
+
+/// Builds a map from evaluated entries. A repeated key keeps its first position but takes the last value.
+fn map_value(entries : Vec<(Value, Value)>) -> Result<Value, String> {
+    let mut map = Map::new();
+    for (key, value) in entries {
+        map.insert(MapKey::from_value(&key)?, value);
+    }
+    Ok(Value::Map(Rc::new(RefCell::new(map))))
+}
+
+fn index_value(object : &Value, index : &Value) -> Result<Value, String> {
+    match object {
+        Value::List(list) => {
+            let list = list.borrow();
+            if let Value::Range(range) = index {
+                return native::list_slice(&list, range)
+            }
+            let position = native::list_index(index, list.len())?;
+            Ok(list[position].clone())
+        },
+        Value::Map(map) => {
+            let key = MapKey::from_value(index)?;
+            map.borrow().get(&key)
+                .cloned()
+                .ok_or_else(|| format!("Key {} not found in map", key))
+        },
+        other => Err(format!("Can only index lists and maps, not {}", other.type_name())),
+    }
+}
+
+fn set_index_value(object : &Value, index : &Value, value : Value) -> Result<Value, String> {
+    match object {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let position = native::list_index(index, list.len())?;
+            list[position] = value.clone();
+            Ok(value)
+        },
+        Value::Map(map) => {
+            let key = MapKey::from_value(index)?;
+            map.borrow_mut().insert(key, value.clone());
+            Ok(value)
+        },
+        other => Err(format!("Can only index lists and maps, not {}", other.type_name())),
+    }
+}
+
+fn property_value(object : &Value, name : &str) -> Result<Value, String> {
+    let method = match object {
+        Value::List(list) => native::list_method(list, name),
+        Value::Map(map) => native::map_method(map, name),
+        Value::Module(module) => return module.get(name),
+        Value::Error(error) => return error_property(error, name),
+        Value::String(string) => native::string_method(string, name),
+        Value::Range(range) => native::range_method(*range, name),
+        Value::Iterator(iterator) => native::iterator_method(iterator, name),
+        Value::Enum(enumeration) => match enumeration.variant(name) {
+            Some(variant) => return Ok(variant),
+            None => Some(native::enum_method(enumeration, name)
+                .ok_or_else(|| format!("Enum {} has no variant '{}'", enumeration.name, name))?),
+        },
+        Value::Generator(generator) if name == "next" => {
+            let body = FunctionBody::Resume(generator.clone());
+            let closure = generator.function.closure.clone();
+            return Ok(Value::Function(Rc::new(Closure { name: Some("next".to_string()), params: vec![], required: 0, rest: false, body, closure })))
+        },
+        Value::Generator(_) if name == "iterator" => Some(native::itself(object.clone())),
+        _ => None,
+    };
+    method
+        .map(|method| Value::Native(Rc::new(method)))
+        .ok_or_else(|| format!("Undefined property '{}' on {}", name, object.type_name()))
+}
+
+fn error_property(error : &ErrorObject, name : &str) -> Result<Value, String> {
+    match name {
+        "message" => Ok(Value::String(error.message.clone())),
+        "line" => Ok(error.line.map_or(Value::Nil, |line| Value::Number(line as i64))),
+        "stack" => {
+            let stack = error.stack.iter().map(|call| Value::String(call.clone())).collect();
+            Ok(Value::List(Rc::new(RefCell::new(stack))))
+        },
+        _ => Err(format!("Undefined property '{}' on error", name)),
+    }
+}
+
+fn unary_value(operator : UnaryOp, right : Result<Value, String>) -> Result<Value, String> {
+    match operator {
+        UnaryOp::Bang => {
+            right.map(|x| Value::Boolean(!x.is_truthy()))
+        },
+        UnaryOp::Minus => {
+            right.and_then(|x| match x {
+                Value::Number(value) => value.checked_neg().map(Value::Number)
+                    .ok_or_else(|| format!("Integer overflow with operator {}", operator)),
+                other => {Err(format!("Minus can't be used with this value: {}", other.type_name()))},
+            })
+        },
+        UnaryOp::Tilde => {
+            right.and_then(|x| match x {
+                Value::Number(value) => {Ok(Value::Number(!value))},
+                other => {Err(format!("Tilde can't be used with this value: {}", other.type_name()))},
+            })
+        },
+    }
 }
 
 fn match_items(l : Result<Value, String>, r : Result<Value, String>)
@@ -184,40 +1628,180 @@ fn match_items(l : Result<Value, String>, r : Result<Value, String>)
         (Ok(Value::Boolean(l_bool)), Ok(Value::Boolean(r_bool))) => Ok(MatchedValues::Boolean(l_bool, r_bool)),
         (Ok(Value::Nil), Ok(Value::Nil)) => Ok(MatchedValues::Nil),
         (Ok(l_value), Ok(r_value)) =>
-            Err(format!("Both sides of value must be the same type: {:?}, {:?}", l_value, r_value)),
-        (Ok(l_other), Err(r_other)) => Err(r_other),
+            Err(format!("Both sides of value must be the same type: {}, {}", l_value.type_name(), r_value.type_name())),
+        (Ok(_), Err(r_other)) => Err(r_other),
         (Err(l_other), Ok(_)) => Err(l_other),
         (Err(l_other), Err(_)) => Err(l_other),
     }
 }
 
-impl ToString for Value {
-    fn to_string(&self) -> String {
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Value::Nil => {"nil".to_string()},
+            Value::Nil => {write!(f, "nil")},
             Value::Boolean(bl) => {
                 if *bl {
-                    "true".to_string()
+                    write!(f, "true")
                 } else {
-                    "false".to_string()
+                    write!(f, "false")
                 }},
-            Value::Number(num) => {format!("{}", num)},
-            Value::String(st) => {st.to_string()},
+            Value::Number(num) => {write!(f, "{}", num)},
+            Value::String(st) => {write!(f, "{}", st)},
+            Value::Function(function) => match &function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::List(_) | Value::Map(_) => self.fmt_within(f, &mut vec![]),
+            Value::Native(native) => write!(f, "<native fn {}>", native.name),
+            Value::Module(module) => write!(f, "<module {:?}>", module.name),
+            Value::Error(error) => write!(f, "Error: {}", error.message),
+            Value::Generator(generator) => match &generator.function.name {
+                Some(name) => write!(f, "<generator {}>", name),
+                None => write!(f, "<generator>"),
+            },
+            Value::Iterator(iterator) => write!(f, "<{}>", iterator.name),
+            Value::Range(range) => write!(f, "{}", range),
+            Value::Enum(enumeration) => write!(f, "<enum {}>", enumeration.name),
+            Value::Variant(variant) => write!(f, "{}", variant),
         }
     }
 }
 
 impl Value {
+    /// Writes a value inside the lists and maps in `printing`, which are already being written. A
+    /// list or map that contains itself is written as `[...]` or `{...}` where it repeats.
+    fn fmt_within(&self, f: &mut fmt::Formatter, printing: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::List(list) => {
+                let pointer = Rc::as_ptr(list) as *const ();
+                if printing.contains(&pointer) {
+                    return write!(f, "[...]")
+                }
+                printing.push(pointer);
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.fmt_element(f, printing)?;
+                }
+                printing.pop();
+                write!(f, "]")
+            },
+            Value::Map(map) => {
+                let pointer = Rc::as_ptr(map) as *const ();
+                if printing.contains(&pointer) {
+                    return write!(f, "{{...}}")
+                }
+                printing.push(pointer);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    value.fmt_element(f, printing)?;
+                }
+                printing.pop();
+                write!(f, "}}")
+            },
+            other => write!(f, "{}", other),
+        }
+    }
+
+    /// Strings are quoted inside lists and maps so that `["a, b"]` can be told apart from `["a", "b"]`.
+    fn fmt_element(&self, f: &mut fmt::Formatter, printing: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::String(st) => write!(f, "{:?}", st),
+            other => other.fmt_within(f, printing),
+        }
+    }
+
+    /// The name error messages use for a value's type, matching the names of type annotations.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "Nil",
+            Value::Boolean(_) => "Bool",
+            Value::Number(_) => "Number",
+            Value::String(_) => "String",
+            Value::Function(_) | Value::Native(_) => "Function",
+            Value::List(_) => "List",
+            Value::Map(_) => "Map",
+            Value::Module(_) => "Module",
+            Value::Error(_) => "Error",
+            Value::Generator(_) => "Generator",
+            Value::Iterator(_) => "Iterator",
+            Value::Range(_) => "Range",
+            Value::Enum(_) => "Enum",
+            Value::Variant(_) => "Variant",
+        }
+    }
+
     fn is_truthy(&self) -> bool {
         match self {
             Value::Nil => {false},
             Value::Boolean(bool_value) => {*bool_value},
             Value::Number(_) => {true},
             Value::String(_) => {true},
+            Value::Function(_) => {true},
+            Value::List(_) => {true},
+            Value::Map(_) => {true},
+            Value::Native(_) => {true},
+            Value::Module(_) => {true},
+            Value::Error(_) => {true},
+            Value::Generator(_) => {true},
+            Value::Iterator(_) => {true},
+            Value::Range(_) => {true},
+            Value::Enum(_) => {true},
+            Value::Variant(_) => {true},
         }
     }
 
     fn is_equal(&self, other : &Value) -> bool {
+        self.is_equal_within(other, &mut vec![])
+    }
+
+    /// Compares values inside the lists and maps in `comparing`, which are already being compared.
+    /// Meeting one of those pairs again means the values are cyclic, and if they differ anywhere
+    /// that's found on the way around the cycle, so the repeated pair is taken to be equal.
+    fn is_equal_within(&self, other : &Value, comparing : &mut Vec<(*const (), *const ())>) -> bool {
+        match (self, other) {
+            (Value::Function(l), Value::Function(r)) => return Rc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => return Rc::ptr_eq(l, r),
+            (Value::Module(l), Value::Module(r)) => return Rc::ptr_eq(l, r),
+            (Value::Error(l), Value::Error(r)) => return Rc::ptr_eq(l, r),
+            (Value::Generator(l), Value::Generator(r)) => return Rc::ptr_eq(l, r),
+            (Value::Iterator(l), Value::Iterator(r)) => return Rc::ptr_eq(l, r),
+            (Value::Range(l), Value::Range(r)) => return l == r,
+            (Value::Enum(l), Value::Enum(r)) => return Rc::ptr_eq(l, r),
+            (Value::Variant(l), Value::Variant(r)) => return Rc::ptr_eq(l, r),
+            // Lists are equal when their elements are, so `[1] == [1]` even though they're different lists.
+            (Value::List(l), Value::List(r)) => {
+                let pair = (Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ());
+                if Rc::ptr_eq(l, r) || comparing.contains(&pair) {
+                    return true
+                }
+                comparing.push(pair);
+                let (l, r) = (l.borrow(), r.borrow());
+                let equal = l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| l.is_equal_within(r, comparing));
+                comparing.pop();
+                return equal
+            },
+            // Maps are equal when they have the same keys with equal values, in any order.
+            (Value::Map(l), Value::Map(r)) => {
+                let pair = (Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ());
+                if Rc::ptr_eq(l, r) || comparing.contains(&pair) {
+                    return true
+                }
+                comparing.push(pair);
+                let (l, r) = (l.borrow(), r.borrow());
+                let equal = l.len() == r.len()
+                    && l.iter().all(|(key, l_value)| r.get(key).is_some_and(|r_value| l_value.is_equal_within(r_value, comparing)));
+                comparing.pop();
+                return equal
+            },
+            _ => {},
+        }
         match_items(Ok(self.clone()), Ok(other.clone()))
             .map_or_else(|_| false, |x| match x {
                 MatchedValues::Nil => {true},
@@ -0,0 +1,601 @@
+use ast::{BinaryOp, UnaryOp, Span, Pattern, pattern_printer};
+use core::borrow::{Borrow};
+use std::rc::Rc;
+
+// The core IR is the small language that the surface `ast` is lowered into by `lower`. Surface
+// constructs which can be expressed in terms of others (e.g. `and`/`or`) don't get a node here, so
+// the evaluator and any future compiler have fewer constructs to handle.
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Constant {
+    Nil,
+    Boolean(bool),
+    Number(i64),
+    String(String),
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Stmt {
+    /// Introduces a binding in the current scope.
+    Let(Let),
+    /// Introduces a binding in the current scope for each name the pattern binds.
+    Destructure(Destructure),
+    Expression(Exp),
+    Print(Exp),
+    /// Runs the statements in a new scope.
+    Block(Vec<Stmt>),
+    Return(Exp),
+    Branch(Branch),
+    /// Runs `body` while `condition` holds, evaluating `step` after each iteration, including ones
+    /// cut short by `Continue`.
+    Loop(Loop),
+    /// Runs `body` for each value an iterator produces, bound to `name` in a new scope each time.
+    ForIn(ForIn),
+    Break,
+    Continue,
+    Match(Match<Stmt>),
+    /// Binds `name` to the module at `path`, running it if it hasn't been imported before.
+    Import(Import),
+    Throw(Throw),
+    /// Runs `body`, then `catch` if it failed, and then `finally` however they finished.
+    Try(Try),
+    /// Suspends the enclosing generator, producing the value from `next()`.
+    Yield(Exp),
+    /// Binds `name` to a new enum, with a fresh value for each of its variants.
+    Enum(Enum),
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Throw {
+    pub value: Exp,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Try {
+    pub body: Vec<Stmt>,
+    pub catch: Option<Catch>,
+    pub finally: Option<Vec<Stmt>>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Catch {
+    pub name: String,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Import {
+    pub path: String,
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Enum {
+    pub name: String,
+    pub variants: Vec<String>,
+    pub span: Span,
+}
+
+/// Runs the first arm whose pattern matches the subject and whose guard passes. Patterns are
+/// shared with the `ast`, as they contain no expressions to lower.
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Match<T> {
+    pub subject: Box<Exp>,
+    pub arms: Vec<MatchArm<T>>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct MatchArm<T> {
+    pub pattern: Pattern,
+    pub guard: Option<Exp>,
+    pub body: T,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Branch {
+    pub condition: Exp,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Loop {
+    pub condition: Exp,
+    pub body: Box<Stmt>,
+    pub step: Option<Exp>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ForIn {
+    pub name: String,
+    pub iterable: Exp,
+    pub body: Box<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Let {
+    pub name: String,
+    pub value: Exp,
+    pub constant: bool,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Destructure {
+    pub pattern: Pattern,
+    pub value: Exp,
+    pub constant: bool,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Exp {
+    Constant(Constant),
+    Variable(Variable),
+    Binary(Binary),
+    Unary(Unary),
+    If(If),
+    /// Binds `name` to `value` while `body` is evaluated. Lowering uses this for temporaries, which
+    /// are given names that can't be written in source so they never shadow user bindings.
+    Local(Local),
+    Call(Call),
+    /// Rebinds an existing variable, producing the assigned value.
+    Assign(Assign),
+    /// Rebinds each variable the pattern binds, producing the whole assigned value.
+    DestructureAssign(DestructureAssign),
+    /// Creates a new list holding the elements.
+    List(List),
+    /// Creates a new map holding the entries, in order.
+    Map(Map),
+    Index(Index),
+    SetIndex(SetIndex),
+    /// Looks up a method on an object, producing it bound to the object.
+    Get(Get),
+    Match(Match<Exp>),
+    /// Creates a function value capturing the current scope.
+    Closure(Rc<Function>),
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Call {
+    pub callee: Box<Exp>,
+    pub arguments: Vec<Element>,
+    pub named: Vec<(String, Exp)>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Element {
+    Single(Exp),
+    /// Stands for each of the values iterating over the expression produces.
+    Spread(Exp),
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Assign {
+    pub name: String,
+    pub value: Box<Exp>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct DestructureAssign {
+    pub pattern: Pattern,
+    pub value: Box<Exp>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct List {
+    pub elements: Vec<Element>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Map {
+    pub entries: Vec<(Exp, Exp)>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Index {
+    pub object: Box<Exp>,
+    pub index: Box<Exp>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SetIndex {
+    pub object: Box<Exp>,
+    pub index: Box<Exp>,
+    pub value: Box<Exp>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Get {
+    pub object: Box<Exp>,
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    /// The default value of each parameter, evaluated when a call doesn't pass that parameter.
+    pub defaults: Vec<Option<Exp>>,
+    pub body: Vec<Stmt>,
+    /// Whether calls produce a generator running `body`, rather than running it straight away.
+    pub generator: bool,
+    /// Whether the last of `params` collects any arguments after the others into a list.
+    pub rest: bool,
+    pub span: Span,
+}
+
+impl Function {
+    /// How many parameters have no default and aren't the rest parameter, so must be passed.
+    pub fn required(&self) -> usize {
+        let positional = self.params.len() - self.rest as usize;
+        self.defaults[..positional].iter().take_while(|default| default.is_none()).count()
+    }
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Variable {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Binary {
+    pub left: Box<Exp>,
+    pub operator: BinaryOp,
+    pub span: Span,
+    pub right: Box<Exp>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Unary {
+    pub operator: UnaryOp,
+    pub span: Span,
+    pub right: Box<Exp>,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct If {
+    pub condition: Box<Exp>,
+    pub then_branch: Box<Exp>,
+    pub else_branch: Box<Exp>,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Local {
+    pub name: String,
+    pub value: Box<Exp>,
+    pub body: Box<Exp>,
+}
+
+pub fn exp_printer<'a>(builder: &'a mut String, exp: &'a Exp) -> &'a String {
+    fn add_parens<'a>(builder: &'a mut String, name: String, exprs: Vec<&Exp>) -> &'a String {
+        builder.push_str(format!("({}", name).as_str());
+        for exp in exprs {
+            builder.push(' ');
+            exp_printer(builder, exp);
+        }
+        builder.push(')');
+        builder
+    }
+    match exp {
+        Exp::Constant(Constant::Nil) => {
+            builder.push_str("nil");
+            builder
+        },
+        Exp::Constant(Constant::Boolean(x)) => {
+            builder.push_str(x.to_string().as_str());
+            builder
+        },
+        Exp::Constant(Constant::Number(x)) => {
+            builder.push_str(x.to_string().as_str());
+            builder
+        },
+        Exp::Constant(Constant::String(x)) => {
+            builder.push_str(format!("{:?}", x).as_str());
+            builder
+        },
+        Exp::Variable(variable) => {
+            builder.push_str(variable.name.as_str());
+            builder
+        },
+        Exp::Binary(x) => {
+            add_parens(builder, x.operator.to_string(), vec![x.left.borrow(), x.right.borrow()])
+        },
+        Exp::Unary(x) => {
+            add_parens(builder, x.operator.to_string(), vec![x.right.borrow()])
+        },
+        Exp::If(x) => {
+            add_parens(builder, "if".to_string(), vec![x.condition.borrow(), x.then_branch.borrow(), x.else_branch.borrow()])
+        },
+        Exp::Local(x) => {
+            add_parens(builder, format!("local {}", x.name), vec![x.value.borrow(), x.body.borrow()])
+        },
+        Exp::Call(x) => {
+            builder.push_str("(call ");
+            exp_printer(builder, &x.callee);
+            elements_printer(builder, &x.arguments);
+            for (name, value) in &x.named {
+                builder.push_str(format!(" (named {} ", name).as_str());
+                exp_printer(builder, value);
+                builder.push(')');
+            }
+            builder.push(')');
+            builder
+        },
+        Exp::Assign(x) => {
+            add_parens(builder, format!("set {}", x.name), vec![x.value.borrow()])
+        },
+        Exp::DestructureAssign(x) => {
+            builder.push_str("(set ");
+            pattern_printer(builder, &x.pattern);
+            builder.push(' ');
+            exp_printer(builder, &x.value);
+            builder.push(')');
+            builder
+        },
+        Exp::List(x) => {
+            builder.push_str("(list");
+            elements_printer(builder, &x.elements);
+            builder.push(')');
+            builder
+        },
+        Exp::Map(x) => {
+            add_parens(builder, "map".to_string(), x.entries.iter().flat_map(|(key, value)| vec![key, value]).collect())
+        },
+        Exp::Index(x) => {
+            add_parens(builder, "index".to_string(), vec![x.object.borrow(), x.index.borrow()])
+        },
+        Exp::SetIndex(x) => {
+            add_parens(builder, "set-index".to_string(), vec![x.object.borrow(), x.index.borrow(), x.value.borrow()])
+        },
+        Exp::Get(x) => {
+            add_parens(builder, format!("get {}", x.name), vec![x.object.borrow()])
+        },
+        Exp::Match(x) => {
+            match_printer(builder, x, |builder, body| { exp_printer(builder, body); })
+        },
+        Exp::Closure(x) => {
+            let kind = if x.generator { "generator" } else { "closure" };
+            let mut params: Vec<String> = x.params.iter().zip(&x.defaults)
+                .map(|(param, default)| match default {
+                    None => param.clone(),
+                    Some(default) => format!("({} {})", param, exp_printer(&mut String::new(), default)),
+                })
+                .collect();
+            if x.rest {
+                if let Some(last) = params.last_mut() {
+                    last.insert_str(0, "...");
+                }
+            }
+            builder.push_str(format!("({} ({})", kind, params.join(" ")).as_str());
+            for stmt in &x.body {
+                builder.push(' ');
+                stmt_printer(builder, stmt);
+            }
+            builder.push(')');
+            builder
+        },
+    }
+}
+
+/// Prints each element preceded by a space.
+fn elements_printer<'a>(builder: &'a mut String, elements: &'a [Element]) -> &'a String {
+    for element in elements {
+        builder.push(' ');
+        match element {
+            Element::Single(exp) => {
+                exp_printer(builder, exp);
+            },
+            Element::Spread(exp) => {
+                builder.push_str("(spread ");
+                exp_printer(builder, exp);
+                builder.push(')');
+            },
+        }
+    }
+    builder
+}
+
+fn match_printer<'a, T>(builder: &'a mut String, x: &'a Match<T>, body_printer: fn(&mut String, &T)) -> &'a String {
+    builder.push_str("(match ");
+    exp_printer(builder, &x.subject);
+    for arm in &x.arms {
+        builder.push_str(" (");
+        pattern_printer(builder, &arm.pattern);
+        if let Some(guard) = &arm.guard {
+            builder.push_str(" if ");
+            exp_printer(builder, guard);
+        }
+        builder.push(' ');
+        body_printer(builder, &arm.body);
+        builder.push(')');
+    }
+    builder.push(')');
+    builder
+}
+
+fn block_printer<'a>(builder: &'a mut String, stmts: &'a [Stmt]) -> &'a String {
+    builder.push_str("(block");
+    for stmt in stmts {
+        builder.push(' ');
+        stmt_printer(builder, stmt);
+    }
+    builder.push(')');
+    builder
+}
+
+pub fn stmt_printer<'a>(builder: &'a mut String, stmt: &'a Stmt) -> &'a String {
+    match stmt {
+        Stmt::Let(decl) => {
+            builder.push_str(format!("({} {} ", if decl.constant { "const" } else { "let" }, decl.name).as_str());
+            exp_printer(builder, &decl.value);
+            builder.push(')');
+        },
+        Stmt::Destructure(decl) => {
+            builder.push_str(format!("({} ", if decl.constant { "const" } else { "let" }).as_str());
+            pattern_printer(builder, &decl.pattern);
+            builder.push(' ');
+            exp_printer(builder, &decl.value);
+            builder.push(')');
+        },
+        Stmt::Expression(exp) => {
+            exp_printer(builder, exp);
+        },
+        Stmt::Print(exp) => {
+            builder.push_str("(print ");
+            exp_printer(builder, exp);
+            builder.push(')');
+        },
+        Stmt::Block(stmts) => {
+            block_printer(builder, stmts);
+        },
+        Stmt::Return(exp) => {
+            builder.push_str("(return ");
+            exp_printer(builder, exp);
+            builder.push(')');
+        },
+        Stmt::Yield(exp) => {
+            builder.push_str("(yield ");
+            exp_printer(builder, exp);
+            builder.push(')');
+        },
+        Stmt::Branch(branch) => {
+            builder.push_str("(if ");
+            exp_printer(builder, &branch.condition);
+            builder.push(' ');
+            stmt_printer(builder, &branch.then_branch);
+            if let Some(else_branch) = &branch.else_branch {
+                builder.push(' ');
+                stmt_printer(builder, else_branch);
+            }
+            builder.push(')');
+        },
+        Stmt::Loop(x) => {
+            builder.push_str("(loop ");
+            exp_printer(builder, &x.condition);
+            builder.push(' ');
+            stmt_printer(builder, &x.body);
+            if let Some(step) = &x.step {
+                builder.push(' ');
+                exp_printer(builder, step);
+            }
+            builder.push(')');
+        },
+        Stmt::ForIn(x) => {
+            builder.push_str(format!("(for {} ", x.name).as_str());
+            exp_printer(builder, &x.iterable);
+            builder.push(' ');
+            stmt_printer(builder, &x.body);
+            builder.push(')');
+        },
+        Stmt::Match(x) => {
+            match_printer(builder, x, |builder, body| { stmt_printer(builder, body); });
+        },
+        Stmt::Break => {
+            builder.push_str("(break)");
+        },
+        Stmt::Continue => {
+            builder.push_str("(continue)");
+        },
+        Stmt::Import(x) => {
+            builder.push_str(format!("(import {:?} {})", x.path, x.name).as_str());
+        },
+        Stmt::Enum(x) => {
+            builder.push_str(format!("(enum {} {})", x.name, x.variants.join(" ")).as_str());
+        },
+        Stmt::Throw(x) => {
+            builder.push_str("(throw ");
+            exp_printer(builder, &x.value);
+            builder.push(')');
+        },
+        Stmt::Try(x) => {
+            builder.push_str("(try ");
+            block_printer(builder, &x.body);
+            if let Some(catch) = &x.catch {
+                builder.push_str(format!(" (catch {} ", catch.name).as_str());
+                block_printer(builder, &catch.body);
+                builder.push(')');
+            }
+            if let Some(finally) = &x.finally {
+                builder.push_str(" (finally ");
+                block_printer(builder, finally);
+                builder.push(')');
+            }
+            builder.push(')');
+        },
+    }
+    builder
+}
@@ -7,4 +7,14 @@ pub mod scanner;
 pub mod ast;
 pub mod parser;
 pub mod interpreter;
-pub mod environment;
\ No newline at end of file
+pub mod environment;
+pub mod ir;
+pub mod lower;
+pub mod native;
+pub mod map;
+pub mod range;
+pub mod enumeration;
+pub mod module;
+pub mod resolver;
+pub mod typecheck;
+pub mod analysis;
\ No newline at end of file
@@ -0,0 +1,440 @@
+use ast;
+use ast::{AssignTarget, BinaryOp, LogicalOp, Span};
+use ir;
+use scanner::Literal;
+use std::rc::Rc;
+
+/// Lowers the surface `ast` into the core `ir`, desugaring constructs the IR has no node for.
+pub struct Lowerer {
+    next_temporary: usize
+}
+
+impl Default for Lowerer {
+    fn default() -> Lowerer {
+        Lowerer::new()
+    }
+}
+
+impl Lowerer {
+    pub fn new() -> Lowerer {
+        Lowerer {
+            next_temporary: 0
+        }
+    }
+
+    pub fn lower(&mut self, stmts: &[ast::Stmt]) -> Result<Vec<ir::Stmt>, String> {
+        stmts.iter()
+            .map(|stmt| self.lower_stmt(stmt))
+            .collect()
+    }
+
+    fn lower_stmt(&mut self, stmt: &ast::Stmt) -> Result<ir::Stmt, String> {
+        match stmt {
+            ast::Stmt::VarDecl(decl) => {
+                let value = match &decl.exp {
+                    None => ir::Exp::Constant(ir::Constant::Nil),
+                    Some(exp) => self.lower_exp(exp)?,
+                };
+                Ok(match &decl.pattern {
+                    ast::Pattern::Binding(name) =>
+                        ir::Stmt::Let(ir::Let { name: name.0.clone(), value, constant: decl.constant, span: decl.span }),
+                    pattern =>
+                        ir::Stmt::Destructure(ir::Destructure { pattern: pattern.clone(), value, constant: decl.constant, span: decl.span }),
+                })
+            },
+            ast::Stmt::Statement(stmt) => self.lower_exp(&stmt.exp).map(ir::Stmt::Expression),
+            ast::Stmt::PrintStmt(stmt) => self.lower_exp(&stmt.exp).map(ir::Stmt::Print),
+            ast::Stmt::Block(block) => self.lower(&block.stmts).map(ir::Stmt::Block),
+            // A declaration binds its name to a closure; the closure captures the scope the name is
+            // bound in, so the function can still call itself recursively.
+            ast::Stmt::FunDecl(decl) => Ok(ir::Stmt::Let(ir::Let {
+                name: decl.name.0.clone(),
+                value: self.lower_function(Some(decl.name.0.clone()), &decl.function)?,
+                constant: false,
+                span: decl.function.span,
+            })),
+            ast::Stmt::Return(stmt) => Ok(ir::Stmt::Return(match &stmt.exp {
+                None => ir::Exp::Constant(ir::Constant::Nil),
+                Some(exp) => self.lower_exp(exp)?,
+            })),
+            ast::Stmt::Yield(stmt) => Ok(ir::Stmt::Yield(match &stmt.exp {
+                None => ir::Exp::Constant(ir::Constant::Nil),
+                Some(exp) => self.lower_exp(exp)?,
+            })),
+            ast::Stmt::If(stmt) => Ok(ir::Stmt::Branch(ir::Branch {
+                condition: self.lower_exp(&stmt.condition)?,
+                then_branch: Box::new(self.lower_stmt(&stmt.then_branch)?),
+                else_branch: match &stmt.else_branch {
+                    None => None,
+                    Some(else_branch) => Some(Box::new(self.lower_stmt(else_branch)?)),
+                },
+            })),
+            ast::Stmt::While(stmt) => Ok(ir::Stmt::Loop(ir::Loop {
+                condition: self.lower_exp(&stmt.condition)?,
+                body: Box::new(self.lower_stmt(&stmt.body)?),
+                step: match &stmt.increment {
+                    None => None,
+                    Some(increment) => Some(self.lower_exp(increment)?),
+                },
+            })),
+            ast::Stmt::ForIn(stmt) => Ok(ir::Stmt::ForIn(ir::ForIn {
+                name: stmt.name.0.clone(),
+                iterable: self.lower_exp(&stmt.iterable)?,
+                body: Box::new(self.lower_stmt(&stmt.body)?),
+                span: stmt.span,
+            })),
+            ast::Stmt::Match(stmt) => {
+                let mut arms = vec![];
+                for arm in &stmt.arms {
+                    arms.push(ir::MatchArm {
+                        pattern: arm.pattern.clone(),
+                        guard: self.lower_guard(&arm.guard)?,
+                        body: self.lower_stmt(&arm.body)?,
+                    });
+                }
+                let subject = Box::new(self.lower_exp(&stmt.subject)?);
+                Ok(ir::Stmt::Match(ir::Match { subject, arms, span: stmt.span }))
+            },
+            ast::Stmt::Break(_) => Ok(ir::Stmt::Break),
+            ast::Stmt::Continue(_) => Ok(ir::Stmt::Continue),
+            ast::Stmt::Import(stmt) => Ok(ir::Stmt::Import(ir::Import {
+                path: stmt.path.clone(),
+                name: stmt.name.0.clone(),
+                span: stmt.span,
+            })),
+            ast::Stmt::Enum(decl) => Ok(ir::Stmt::Enum(ir::Enum {
+                name: decl.name.0.clone(),
+                variants: decl.variants.iter().map(|variant| variant.0.clone()).collect(),
+                span: decl.span,
+            })),
+            // Which names a module exports is read from its `ast` before it's lowered.
+            ast::Stmt::Export(stmt) => self.lower_stmt(&stmt.decl),
+            ast::Stmt::Throw(stmt) => Ok(ir::Stmt::Throw(ir::Throw {
+                value: self.lower_exp(&stmt.exp)?,
+                span: stmt.span,
+            })),
+            ast::Stmt::Try(stmt) => Ok(ir::Stmt::Try(ir::Try {
+                body: self.lower(&stmt.body)?,
+                catch: match &stmt.catch {
+                    None => None,
+                    Some(catch) => Some(ir::Catch { name: catch.name.0.clone(), body: self.lower(&catch.body)? }),
+                },
+                finally: match &stmt.finally {
+                    None => None,
+                    Some(finally) => Some(self.lower(finally)?),
+                },
+            })),
+        }
+    }
+
+    fn lower_guard(&mut self, guard: &Option<ast::Exp>) -> Result<Option<ir::Exp>, String> {
+        match guard {
+            None => Ok(None),
+            Some(guard) => self.lower_exp(guard).map(Some),
+        }
+    }
+
+    fn lower_function(&mut self, name: Option<String>, function: &ast::Function) -> Result<ir::Exp, String> {
+        Ok(ir::Exp::Closure(Rc::new(ir::Function {
+            name,
+            params: function.params.iter().map(|param| param.0.clone()).collect(),
+            defaults: function.defaults.iter()
+                .map(|default| default.as_ref().map(|default| self.lower_exp(default)).transpose())
+                .collect::<Result<Vec<Option<ir::Exp>>, String>>()?,
+            body: self.lower(&function.body)?,
+            generator: function.generator,
+            rest: function.rest,
+            span: function.span,
+        })))
+    }
+
+    fn lower_elements(&mut self, elements: &[ast::Element]) -> Result<Vec<ir::Element>, String> {
+        elements.iter()
+            .map(|element| match element {
+                ast::Element::Single(exp) => self.lower_exp(exp).map(ir::Element::Single),
+                ast::Element::Spread(exp) => self.lower_exp(exp).map(ir::Element::Spread),
+            })
+            .collect()
+    }
+
+    fn lower_exp(&mut self, exp: &ast::Exp) -> Result<ir::Exp, String> {
+        match exp {
+            ast::Exp::LiteralExp(literal) => Ok(match &literal.value {
+                Literal::IDENTIFIER(name) => variable(name.clone(), literal.span),
+                Literal::STRING(value) => ir::Exp::Constant(ir::Constant::String(value.clone())),
+                Literal::NUMBER(value) => ir::Exp::Constant(ir::Constant::Number(*value)),
+                Literal::BOOLEAN(value) => ir::Exp::Constant(ir::Constant::Boolean(*value)),
+                Literal::NIL => ir::Exp::Constant(ir::Constant::Nil),
+            }),
+            ast::Exp::GroupingExp(grouping) => self.lower_exp(&grouping.exp),
+            ast::Exp::BinaryExp(binary) => Ok(ir::Exp::Binary(ir::Binary {
+                left: Box::new(self.lower_exp(&binary.left)?),
+                operator: binary.operator,
+                span: binary.span,
+                right: Box::new(self.lower_exp(&binary.right)?),
+            })),
+            ast::Exp::UnaryExp(unary) => Ok(ir::Exp::Unary(ir::Unary {
+                operator: unary.operator,
+                span: unary.span,
+                right: Box::new(self.lower_exp(&unary.right)?),
+            })),
+            ast::Exp::CallExp(_) | ast::Exp::IndexExp(_) | ast::Exp::GetExp(_) => self.lower_chain(exp),
+            ast::Exp::AssignExp(assign) => Ok(ir::Exp::Assign(ir::Assign {
+                name: assign.name.0.clone(),
+                value: Box::new(self.lower_exp(&assign.value)?),
+                span: assign.span,
+            })),
+            ast::Exp::DestructureExp(destructure) => Ok(ir::Exp::DestructureAssign(ir::DestructureAssign {
+                pattern: destructure.pattern.clone(),
+                value: Box::new(self.lower_exp(&destructure.value)?),
+                span: destructure.span,
+            })),
+            ast::Exp::ListExp(list) => Ok(ir::Exp::List(ir::List {
+                elements: self.lower_elements(&list.elements)?,
+                span: list.span,
+            })),
+            ast::Exp::MapExp(map) => Ok(ir::Exp::Map(ir::Map {
+                entries: map.entries.iter()
+                    .map(|(key, value)| Ok((self.lower_exp(key)?, self.lower_exp(value)?)))
+                    .collect::<Result<Vec<(ir::Exp, ir::Exp)>, String>>()?,
+                span: map.span,
+            })),
+            ast::Exp::SetIndexExp(set) => Ok(ir::Exp::SetIndex(ir::SetIndex {
+                object: Box::new(self.lower_exp(&set.object)?),
+                index: Box::new(self.lower_exp(&set.index)?),
+                value: Box::new(self.lower_exp(&set.value)?),
+                span: set.span,
+            })),
+            ast::Exp::CompoundAssignExp(assign) => {
+                let value = self.lower_exp(&assign.value)?;
+                self.lower_update(&assign.target, assign.operator, value, false, assign.span)
+            },
+            ast::Exp::IncrementExp(increment) => {
+                let one = ir::Exp::Constant(ir::Constant::Number(1));
+                self.lower_update(&increment.target, increment.operator, one, increment.postfix, increment.span)
+            },
+            ast::Exp::MatchExp(match_exp) => {
+                let subject = Box::new(self.lower_exp(&match_exp.subject)?);
+                let mut arms = vec![];
+                for arm in &match_exp.arms {
+                    arms.push(ir::MatchArm {
+                        pattern: arm.pattern.clone(),
+                        guard: self.lower_guard(&arm.guard)?,
+                        body: self.lower_exp(&arm.body)?,
+                    });
+                }
+                Ok(ir::Exp::Match(ir::Match { subject, arms, span: match_exp.span }))
+            },
+            ast::Exp::LambdaExp(lambda) => self.lower_function(None, &lambda.function),
+            ast::Exp::ConditionalExp(conditional) => Ok(ir::Exp::If(ir::If {
+                condition: Box::new(self.lower_exp(&conditional.condition)?),
+                then_branch: Box::new(self.lower_exp(&conditional.then_branch)?),
+                else_branch: Box::new(self.lower_exp(&conditional.else_branch)?),
+                span: conditional.span,
+            })),
+            // `a, b` only needs `a` evaluated before `b`, which binding it to an unused temporary does.
+            ast::Exp::CommaExp(comma) => Ok(ir::Exp::Local(ir::Local {
+                name: self.temporary("comma"),
+                value: Box::new(self.lower_exp(&comma.left)?),
+                body: Box::new(self.lower_exp(&comma.right)?),
+            })),
+            // `a or b` becomes `local t = a in (if t then t else b)`, and `and` swaps the branches,
+            // so the left operand is still only evaluated once. `a ?? b` tests `t == nil` instead.
+            ast::Exp::LogicalExp(logical) => {
+                let left = self.lower_exp(&logical.left)?;
+                let right = self.lower_exp(&logical.right)?;
+                let temporary = self.temporary("logical");
+                let span = logical.span;
+                let (condition, then_branch, else_branch) = match logical.operator {
+                    LogicalOp::Or => (variable(temporary.clone(), span), variable(temporary.clone(), span), right),
+                    LogicalOp::And => (variable(temporary.clone(), span), right, variable(temporary.clone(), span)),
+                    LogicalOp::QuestionQuestion =>
+                        (is_nil(variable(temporary.clone(), span), span), right, variable(temporary.clone(), span)),
+                };
+                Ok(ir::Exp::Local(ir::Local {
+                    name: temporary,
+                    value: Box::new(left),
+                    body: Box::new(ir::Exp::If(ir::If {
+                        condition: Box::new(condition),
+                        then_branch: Box::new(then_branch),
+                        else_branch: Box::new(else_branch),
+                        span,
+                    })),
+                }))
+            },
+        }
+    }
+
+    /// Lowers a call, index or property access, along with the rest of the chain of them to its
+    /// left. Each optional property access (`?.`) in the chain binds its object to a temporary which
+    /// guards the whole chain, so `a?.b.c()` becomes `local t = a in (if t == nil then nil else t.b.c())`.
+    fn lower_chain(&mut self, exp: &ast::Exp) -> Result<ir::Exp, String> {
+        let mut guards = vec![];
+        let chain = self.lower_chain_link(exp, &mut guards)?;
+        Ok(guards.into_iter().rev().fold(chain, |body, (name, value, span)| ir::Exp::Local(ir::Local {
+            name: name.clone(),
+            value: Box::new(value),
+            body: Box::new(ir::Exp::If(ir::If {
+                condition: Box::new(is_nil(variable(name, span), span)),
+                then_branch: Box::new(ir::Exp::Constant(ir::Constant::Nil)),
+                else_branch: Box::new(body),
+                span,
+            })),
+        })))
+    }
+
+    fn lower_chain_link(&mut self, exp: &ast::Exp, guards: &mut Vec<(String, ir::Exp, Span)>) -> Result<ir::Exp, String> {
+        match exp {
+            ast::Exp::CallExp(call) => Ok(ir::Exp::Call(ir::Call {
+                callee: Box::new(self.lower_chain_link(&call.callee, guards)?),
+                arguments: self.lower_elements(&call.arguments)?,
+                named: call.named.iter()
+                    .map(|(name, argument)| Ok((name.0.clone(), self.lower_exp(argument)?)))
+                    .collect::<Result<Vec<(String, ir::Exp)>, String>>()?,
+                span: call.span,
+            })),
+            ast::Exp::IndexExp(index) => Ok(ir::Exp::Index(ir::Index {
+                object: Box::new(self.lower_chain_link(&index.object, guards)?),
+                index: Box::new(self.lower_exp(&index.index)?),
+                span: index.span,
+            })),
+            ast::Exp::GetExp(get) => {
+                let mut object = self.lower_chain_link(&get.object, guards)?;
+                if get.optional {
+                    let temporary = self.temporary("optional");
+                    guards.push((temporary.clone(), object, get.span));
+                    object = variable(temporary, get.span);
+                }
+                Ok(ir::Exp::Get(ir::Get {
+                    object: Box::new(object),
+                    name: get.name.0.clone(),
+                    span: get.span,
+                }))
+            },
+            other => self.lower_exp(other),
+        }
+    }
+
+    /// `target op= value` becomes `target = target op value`, with the object and index of an index
+    /// target bound to temporaries so they're only evaluated once. When `postfix`, the old value is
+    /// kept in another temporary to be produced once the update is done.
+    fn lower_update(&mut self, target: &AssignTarget, operator: BinaryOp, value: ir::Exp, postfix: bool, span: Span)
+                    -> Result<ir::Exp, String> {
+        let mut bindings = vec![];
+        let place = match target {
+            AssignTarget::Variable(name) => Place::Variable(name.0.clone()),
+            AssignTarget::Index { object, index } => {
+                let object_temporary = self.temporary("object");
+                let index_temporary = self.temporary("index");
+                bindings.push((object_temporary.clone(), self.lower_exp(object)?));
+                bindings.push((index_temporary.clone(), self.lower_exp(index)?));
+                Place::Index(object_temporary, index_temporary)
+            },
+        };
+
+        let updated = if postfix {
+            let old = self.temporary("old");
+            let update = self.temporary("update");
+            let new_value = binary(variable(old.clone(), span), operator, value, span);
+            ir::Exp::Local(ir::Local {
+                name: old.clone(),
+                value: Box::new(place.read(span)),
+                body: Box::new(ir::Exp::Local(ir::Local {
+                    name: update,
+                    value: Box::new(place.write(new_value, span)),
+                    body: Box::new(variable(old, span)),
+                })),
+            })
+        } else {
+            place.write(binary(place.read(span), operator, value, span), span)
+        };
+        Ok(bindings.into_iter().rev().fold(updated, |body, (name, value)| ir::Exp::Local(ir::Local {
+            name,
+            value: Box::new(value),
+            body: Box::new(body),
+        })))
+    }
+
+    fn temporary(&mut self, purpose: &str) -> String {
+        let name = format!("${}{}", purpose, self.next_temporary);
+        self.next_temporary += 1;
+        name
+    }
+}
+
+/// Where `lower_update` reads and writes, once any parts of the target are in temporaries.
+enum Place {
+    Variable(String),
+    Index(String, String),
+}
+
+impl Place {
+    fn read(&self, span: Span) -> ir::Exp {
+        match self {
+            Place::Variable(name) => variable(name.clone(), span),
+            Place::Index(object, index) => ir::Exp::Index(ir::Index {
+                object: Box::new(variable(object.clone(), span)),
+                index: Box::new(variable(index.clone(), span)),
+                span,
+            }),
+        }
+    }
+
+    fn write(&self, value: ir::Exp, span: Span) -> ir::Exp {
+        match self {
+            Place::Variable(name) => ir::Exp::Assign(ir::Assign {
+                name: name.clone(),
+                value: Box::new(value),
+                span,
+            }),
+            Place::Index(object, index) => ir::Exp::SetIndex(ir::SetIndex {
+                object: Box::new(variable(object.clone(), span)),
+                index: Box::new(variable(index.clone(), span)),
+                value: Box::new(value),
+                span,
+            }),
+        }
+    }
+}
+
+fn is_nil(exp: ir::Exp, span: Span) -> ir::Exp {
+    binary(exp, BinaryOp::EqualEqual, ir::Exp::Constant(ir::Constant::Nil), span)
+}
+
+fn variable(name: String, span: Span) -> ir::Exp {
+    ir::Exp::Variable(ir::Variable { name, span })
+}
+
+fn binary(left: ir::Exp, operator: BinaryOp, right: ir::Exp, span: Span) -> ir::Exp {
+    ir::Exp::Binary(ir::Binary {
+        left: Box::new(left),
+        operator,
+        span,
+        right: Box::new(right),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::{Exp, LiteralExp, LogicalExp, NodeId, Stmt, PrintStmt};
+
+    #[test]
+    fn logical_or_lowers_to_local_and_if()
+    {
+        let or_exp = Exp::LogicalExp(LogicalExp {
+            id: NodeId(2),
+            left: Box::new(Exp::LiteralExp(LiteralExp { id: NodeId(0), value: Literal::IDENTIFIER("a".to_string()), span: Span { line: 0 } })),
+            operator: LogicalOp::Or,
+            span: Span { line: 0 },
+            right: Box::new(Exp::LiteralExp(LiteralExp { id: NodeId(1), value: Literal::NUMBER(2), span: Span { line: 0 } })),
+        });
+        let stmts = vec![Stmt::PrintStmt(PrintStmt { id: NodeId(3), exp: or_exp })];
+
+        let lowered = Lowerer::new().lower(&stmts).expect("lowering should succeed");
+
+        let mut output = String::new();
+        ir::stmt_printer(&mut output, &lowered[0]);
+        assert_eq!(output, "(print (local $logical0 a (if $logical0 $logical0 2)))");
+    }
+}
@@ -2,56 +2,74 @@ extern crate argparse;
 extern crate loxrust;
 
 use std::io::{self, Read};
-use argparse::{ArgumentParser, Store};
+use argparse::{ArgumentParser, Store, StoreTrue, Collect};
 use std::fs::File;
 use std::io::BufReader;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use loxrust::parser::Parser;
 use loxrust::interpreter::Interpreter;
+use loxrust::lower::Lowerer;
+use loxrust::analysis::analyze;
 
 fn main() {
     let mut file_name = String::new();
+    let mut lower = false;
+    let mut search_path: Vec<String> = vec![];
 
     {
         let mut ap = ArgumentParser::new();
         ap.set_description("Execute code written in lox");
         ap.refer(&mut file_name)
             .add_argument("file", Store, "File containing lox code to run");
+        ap.refer(&mut lower)
+            .add_option(&["--lower"], StoreTrue, "Lower the code to the core IR before executing it");
+        ap.refer(&mut search_path)
+            .add_option(&["-I", "--module-path"], Collect, "Directory to look for imported modules in");
         ap.parse_args_or_exit();
     }
+    let mut interpreter = Interpreter::new();
+    for directory in search_path {
+        interpreter.add_search_path(PathBuf::from(directory));
+    }
     match file_name.len() {
-        0 => run_prompt(),
-        _ => run_file(&file_name)
+        0 => run_prompt(interpreter, lower),
+        _ => run_file(&file_name, interpreter, lower)
     }
 }
 
-pub fn run_prompt() {
+pub fn run_prompt(mut interpreter: Interpreter, lower: bool) {
     let stdin = io::stdin();
-    let mut interpreter = Interpreter::new();
     for line in stdin.lock().lines() {
         let line = line.unwrap();
         println!("> {}", line);
-        run(line, &mut interpreter);
+        run(line, &mut interpreter, lower);
     }
 }
 
-pub fn run_file(source_file: &String) {
+pub fn run_file(source_file: &str, mut interpreter: Interpreter, lower: bool) {
     let file = File::open(source_file).expect("failed to open file");
+    interpreter.set_main_file(Path::new(source_file)).expect("failed to find file");
 
     let mut buf_reader = BufReader::new(file);
-    let mut interpreter = Interpreter::new();
     let mut contents = String::new();
     buf_reader.read_to_string(&mut contents).expect("failed to read file contents to buffer");
 
     println!("contents: {}", contents);
-    run(contents, &mut interpreter);
+    run(contents, &mut interpreter, lower);
 }
 
-pub fn run(source: String, interpreter: &mut Interpreter) {
+pub fn run(source: String, interpreter: &mut Interpreter, lower: bool) {
     let mut scanner = loxrust::scanner::Scanner::new(source);
     {
-        let tokens = scanner.scan_tokens();
-        let mut parser = Parser::new(tokens);
+        let tokens = scanner.scan_tokens().clone();
+        if !scanner.errors.is_empty() {
+            for error in &scanner.errors {
+                println!("{}", error);
+            }
+            return
+        }
+        let mut parser = Parser::new(&tokens);
         let parse_result = parser.parse();
 
         match parse_result {
@@ -63,8 +81,25 @@ pub fn run(source: String, interpreter: &mut Interpreter) {
                     println!("Resulting AST: {:?}", ast);
                 }
 
-                match interpreter.interpret(&stmts) {
-                    Ok(_0) => {},
+                let analysis = analyze(&stmts);
+                for warning in &analysis.warnings {
+                    println!("Warning: {}", warning);
+                }
+                if !analysis.errors.is_empty() {
+                    for error in &analysis.errors {
+                        println!("{}", error);
+                    }
+                    return
+                }
+
+                let result = if lower {
+                    Lowerer::new().lower(&stmts)
+                        .and_then(|core| interpreter.interpret_ir(&core))
+                } else {
+                    interpreter.interpret(&stmts)
+                };
+                match result {
+                    Ok(_) => {},
                     Err(e) => {println!("{}", e)},
                 }
             }
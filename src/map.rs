@@ -0,0 +1,139 @@
+use interpreter::Value;
+use scanner::Literal;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The values which can be used as map keys. Keys compare and hash by content, matching how
+/// `Value::is_equal` compares booleans, numbers and strings, so `m[1]` and `m[2 - 1]` find the
+/// same entry. Lists and other maps can change after being used as a key, so aren't allowed.
+#[derive(Eq, PartialEq)]
+#[derive(Hash)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum MapKey {
+    Boolean(bool),
+    Number(i64),
+    String(String),
+}
+
+impl MapKey {
+    pub fn from_value(value: &Value) -> Result<MapKey, String> {
+        match value {
+            Value::Boolean(b) => Ok(MapKey::Boolean(*b)),
+            Value::Number(num) => Ok(MapKey::Number(*num)),
+            Value::String(st) => Ok(MapKey::String(st.clone())),
+            other => Err(format!("Map keys must be strings, numbers or booleans, not {}", other.type_name())),
+        }
+    }
+
+    /// The key for a literal in a map pattern, if it's a valid key.
+    pub fn from_literal(literal: &Literal) -> Option<MapKey> {
+        match literal {
+            Literal::BOOLEAN(b) => Some(MapKey::Boolean(*b)),
+            Literal::NUMBER(num) => Some(MapKey::Number(*num)),
+            Literal::STRING(st) => Some(MapKey::String(st.clone())),
+            Literal::IDENTIFIER(_) | Literal::NIL => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Boolean(b) => Value::Boolean(*b),
+            MapKey::Number(num) => Value::Number(*num),
+            MapKey::String(st) => Value::String(st.clone()),
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapKey::Boolean(b) => write!(f, "{}", b),
+            MapKey::Number(num) => write!(f, "{}", num),
+            MapKey::String(st) => write!(f, "{:?}", st),
+        }
+    }
+}
+
+/// A map which iterates over its entries in the order their keys were first inserted.
+#[derive(Eq, PartialEq)]
+#[derive(Default)]
+#[derive(Debug)]
+pub struct Map {
+    entries: Vec<(MapKey, Value)>,
+    positions: HashMap<MapKey, usize>,
+}
+
+impl Map {
+    pub fn new() -> Map {
+        Map::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.positions.get(key).map(|&position| &self.entries[position].1)
+    }
+
+    pub fn contains(&self, key: &MapKey) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    /// Sets the value for `key`. Replacing the value of an existing key keeps its position.
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        match self.positions.get(&key) {
+            Some(&position) => self.entries[position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            },
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<Value> {
+        let position = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+        for later in self.positions.values_mut() {
+            if *later > position {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MapKey, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_keep_insertion_order_across_updates_and_removals()
+    {
+        let mut map = Map::new();
+        map.insert(MapKey::String("a".to_string()), Value::Number(1));
+        map.insert(MapKey::Number(2), Value::Number(2));
+        map.insert(MapKey::Boolean(true), Value::Number(3));
+        map.insert(MapKey::String("a".to_string()), Value::Number(4));
+        assert_eq!(map.remove(&MapKey::Number(2)), Some(Value::Number(2)));
+        map.insert(MapKey::Number(2), Value::Number(5));
+
+        let entries: Vec<(&MapKey, &Value)> = map.iter().collect();
+        assert_eq!(entries, vec![
+            (&MapKey::String("a".to_string()), &Value::Number(4)),
+            (&MapKey::Boolean(true), &Value::Number(3)),
+            (&MapKey::Number(2), &Value::Number(5)),
+        ]);
+        assert_eq!(map.get(&MapKey::Boolean(true)), Some(&Value::Number(3)));
+        assert_eq!(map.remove(&MapKey::Number(7)), None);
+    }
+}
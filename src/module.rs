@@ -0,0 +1,152 @@
+use ast::Stmt;
+use environment::Environment;
+use interpreter::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+/// A module which has been run. Exports are looked up in its scope when they're accessed, so an
+/// importer sees changes the module's own functions make to them later.
+pub struct Module {
+    pub name: String,
+    exports: Vec<String>,
+    environment: Rc<RefCell<Environment>>,
+}
+
+impl Module {
+    pub fn new(name: String, exports: Vec<String>, environment: Rc<RefCell<Environment>>) -> Module {
+        Module { name, exports, environment }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Value, String> {
+        if !self.exports.iter().any(|export| export == name) {
+            return Err(format!("Module {:?} doesn't export '{}'", self.name, name))
+        }
+        self.environment.borrow().get(name)
+            .ok_or_else(|| format!("Undefined variable: {}", name))
+    }
+}
+
+// Each file is only run once, so a module is only equal to itself.
+impl PartialEq for Module {
+    fn eq(&self, other: &Module) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for Module {}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Module({:?})", self.name)
+    }
+}
+
+/// The names bound by a module's `export` declarations.
+pub fn exported_names(stmts: &[Stmt]) -> Vec<String> {
+    stmts.iter()
+        .flat_map(|stmt| match stmt {
+            Stmt::Export(export) => export.names(),
+            _ => vec![],
+        })
+        .map(|name| name.0.clone())
+        .collect()
+}
+
+/// Finds the files imports refer to, and keeps track of which modules are running or have already
+/// run, so that each file runs at most once and import cycles are reported.
+#[derive(Default)]
+pub struct ModuleLoader {
+    search_path: Vec<PathBuf>,
+    /// The files currently running, innermost last, along with the path each was imported by.
+    running: Vec<(PathBuf, String)>,
+    loaded: HashMap<PathBuf, Rc<Module>>,
+}
+
+impl ModuleLoader {
+    pub fn new() -> ModuleLoader {
+        ModuleLoader::default()
+    }
+
+    /// Adds a directory to look for modules in when they aren't found next to the importing file.
+    pub fn add_search_path(&mut self, directory: PathBuf) {
+        self.search_path.push(directory);
+    }
+
+    /// Records `file` as the script being run, so imports in it are resolved relative to its
+    /// directory and importing it again is reported as a cycle.
+    pub fn set_main_file(&mut self, file: &Path) -> Result<(), String> {
+        let canonical = file.canonicalize()
+            .map_err(|err| format!("Can't find {:?}: {}", file, err))?;
+        self.running = vec![(canonical, file.display().to_string())];
+        Ok(())
+    }
+
+    /// Finds the file `path` refers to. Relative paths are tried against the directory of the file
+    /// doing the import, or the working directory outside of any file, and then each directory of
+    /// the search path in turn.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let importer_directory = match self.running.last() {
+            Some((file, _)) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => PathBuf::new(),
+        };
+        iter::once(&importer_directory).chain(self.search_path.iter())
+            .map(|directory| directory.join(path))
+            .find(|candidate| candidate.is_file())
+            .and_then(|file| file.canonicalize().ok())
+            .ok_or_else(|| format!("Can't find module {:?}", path))
+    }
+
+    pub fn loaded(&self, file: &Path) -> Option<Rc<Module>> {
+        self.loaded.get(file).cloned()
+    }
+
+    /// Marks `file` as running. Fails if it's already running, as then it imports itself through
+    /// the modules in between.
+    pub fn begin(&mut self, file: PathBuf, path: &str) -> Result<(), String> {
+        if let Some(start) = self.running.iter().position(|(running, _)| *running == file) {
+            let cycle: Vec<String> = self.running[start..].iter()
+                .map(|(_, name)| name.as_str())
+                .chain(iter::once(path))
+                .map(|name| format!("{:?}", name))
+                .collect();
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")))
+        }
+        self.running.push((file, path.to_string()));
+        Ok(())
+    }
+
+    /// Marks the innermost running module as finished, remembering it if it ran successfully.
+    pub fn finish<E>(&mut self, result: Result<Module, E>) -> Result<Rc<Module>, E> {
+        let (file, _) = self.running.pop().expect("a module should be running");
+        let module = Rc::new(result?);
+        self.loaded.insert(file, module.clone());
+        Ok(module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_a_module_again_before_it_finishes_is_a_cycle()
+    {
+        let mut loader = ModuleLoader::new();
+        loader.begin(PathBuf::from("/a.lox"), "a.lox").expect("a isn't running");
+        loader.begin(PathBuf::from("/b.lox"), "b.lox").expect("b isn't running");
+        assert_eq!(loader.begin(PathBuf::from("/a.lox"), "../a.lox"),
+                   Err("Import cycle: \"a.lox\" -> \"b.lox\" -> \"../a.lox\"".to_string()));
+
+        let environment = Rc::new(RefCell::new(Environment::new()));
+        let b = loader.finish::<String>(Ok(Module::new("b.lox".to_string(), vec![], environment))).expect("b ran");
+        assert_eq!(loader.loaded(Path::new("/b.lox")), Some(b));
+        assert!(loader.finish(Err("failed".to_string())).is_err());
+        assert_eq!(loader.loaded(Path::new("/a.lox")), None);
+        loader.begin(PathBuf::from("/a.lox"), "a.lox").expect("a finished running");
+    }
+}
@@ -0,0 +1,283 @@
+use interpreter::Value;
+use map::{Map, MapKey};
+use range::Range;
+use enumeration::Enum;
+use std::cell::RefCell;
+use std::fmt;
+use std::ptr;
+use std::rc::Rc;
+
+type NativeFn = Box<dyn Fn(Vec<Value>) -> Result<Value, String>>;
+type NextFn = Box<dyn FnMut() -> Option<Value>>;
+
+/// A method implemented by the interpreter, already bound to the value it was looked up on, so
+/// `xs.push` can be passed around and called later like any other function.
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    function: NativeFn,
+}
+
+impl Native {
+    fn new(name: &'static str, arity: usize, function: NativeFn) -> Native {
+        Native { name, arity, function }
+    }
+
+    /// Runs the method. The caller has already checked that there are `arity` arguments.
+    pub fn call(&self, arguments: Vec<Value>) -> Result<Value, String> {
+        (self.function)(arguments)
+    }
+}
+
+// Like closures, bound methods are only equal to themselves.
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for Native {}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+/// An iterator over a built-in value, produced by its `iterator()` method.
+pub struct NativeIterator {
+    pub name: &'static str,
+    next: RefCell<NextFn>,
+}
+
+impl NativeIterator {
+    fn new(name: &'static str, next: NextFn) -> NativeIterator {
+        NativeIterator { name, next: RefCell::new(next) }
+    }
+
+    fn advance(&self) -> Option<Value> {
+        (self.next.borrow_mut())()
+    }
+}
+
+// Each call to `iterator()` produces a separate iterator, only equal to itself.
+impl PartialEq for NativeIterator {
+    fn eq(&self, other: &NativeIterator) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for NativeIterator {}
+
+impl fmt::Debug for NativeIterator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeIterator({})", self.name)
+    }
+}
+
+/// The `iterator()` method of a value which is already an iterator, which produces the value itself
+/// so iterators can be looped over.
+pub fn itself(iterator: Value) -> Native {
+    Native::new("iterator", 0, Box::new(move |_| Ok(iterator.clone())))
+}
+
+/// Looks up the method `name` on `iterator`.
+pub fn iterator_method(iterator: &Rc<NativeIterator>, name: &str) -> Option<Native> {
+    match name {
+        "next" => {
+            let iterator = iterator.clone();
+            Some(Native::new("next", 0, Box::new(move |_| {
+                Ok(match iterator.advance() {
+                    Some(value) => iteration(value, false),
+                    None => iteration(Value::Nil, true),
+                })
+            })))
+        },
+        "iterator" => Some(itself(Value::Iterator(iterator.clone()))),
+        _ => None,
+    }
+}
+
+/// The result of advancing an iterator: a map holding the `value` it produced, and whether it's
+/// `done`, in which case there was no value to produce.
+pub fn iteration(value: Value, done: bool) -> Value {
+    let mut result = Map::new();
+    result.insert(MapKey::String("value".to_string()), value);
+    result.insert(MapKey::String("done".to_string()), Value::Boolean(done));
+    Value::Map(Rc::new(RefCell::new(result)))
+}
+
+/// Reads the result of advancing an iterator, producing its value unless it's done.
+pub fn iteration_value(result: &Value) -> Result<Option<Value>, String> {
+    let invalid = || format!("An iterator's next() must produce a map with 'value' and 'done', not {}",
+                             result.type_name());
+    let map = match result {
+        Value::Map(map) => map.borrow(),
+        _ => return Err(invalid()),
+    };
+    match map.get(&MapKey::String("done".to_string())) {
+        Some(Value::Boolean(true)) => Ok(None),
+        Some(Value::Boolean(false)) => map.get(&MapKey::String("value".to_string())).cloned().map(Some).ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+/// Copies the elements of `list` at the positions `range` produces, which must all be in bounds.
+pub fn list_slice(list: &[Value], range: &Range) -> Result<Value, String> {
+    let mut elements = vec![];
+    for position in range.values() {
+        elements.push(list[list_index(&Value::Number(position), list.len())?].clone());
+    }
+    Ok(Value::List(Rc::new(RefCell::new(elements))))
+}
+
+/// Converts `index` to the position of one of the `len` elements of a list.
+pub fn list_index(index: &Value, len: usize) -> Result<usize, String> {
+    checked_position(index, len, len)
+}
+
+/// Converts `index` to a position between the elements of a list, which includes the position
+/// after the last element.
+fn list_position(index: &Value, len: usize) -> Result<usize, String> {
+    checked_position(index, len + 1, len)
+}
+
+fn checked_position(index: &Value, bound: usize, len: usize) -> Result<usize, String> {
+    match index {
+        Value::Number(number) if *number >= 0 && (*number as u64) < bound as u64 => Ok(*number as usize),
+        Value::Number(number) => Err(format!("Index {} out of bounds for list of length {}", number, len)),
+        other => Err(format!("List index must be a number, not {}", other.type_name())),
+    }
+}
+
+/// Looks up the method `name` on `list`.
+pub fn list_method(list: &Rc<RefCell<Vec<Value>>>, name: &str) -> Option<Native> {
+    let list = list.clone();
+    let method = match name {
+        "push" => Native::new("push", 1, Box::new(move |mut arguments| {
+            list.borrow_mut().push(arguments.remove(0));
+            Ok(Value::Nil)
+        })),
+        "pop" => Native::new("pop", 0, Box::new(move |_| {
+            list.borrow_mut().pop()
+                .ok_or_else(|| "Can't pop from an empty list".to_string())
+        })),
+        "len" => Native::new("len", 0, Box::new(move |_| {
+            Ok(Value::Number(list.borrow().len() as i64))
+        })),
+        // Inserting at the length appends, so the position may be one past the last element.
+        "insert" => Native::new("insert", 2, Box::new(move |mut arguments| {
+            let len = list.borrow().len();
+            let position = list_position(&arguments[0], len)?;
+            list.borrow_mut().insert(position, arguments.remove(1));
+            Ok(Value::Nil)
+        })),
+        "remove" => Native::new("remove", 1, Box::new(move |arguments| {
+            let len = list.borrow().len();
+            let position = list_index(&arguments[0], len)?;
+            Ok(list.borrow_mut().remove(position))
+        })),
+        // Iterates over the list as it changes, so elements pushed while looping are reached.
+        "iterator" => Native::new("iterator", 0, Box::new(move |_| {
+            let list = list.clone();
+            let mut position = 0;
+            Ok(Value::Iterator(Rc::new(NativeIterator::new("list iterator", Box::new(move || {
+                let element = list.borrow().get(position).cloned();
+                position += 1;
+                element
+            })))))
+        })),
+        // `slice(start, end)` copies the elements from `start` up to but not including `end`.
+        "slice" => Native::new("slice", 2, Box::new(move |arguments| {
+            let len = list.borrow().len();
+            let start = list_position(&arguments[0], len)?;
+            let end = list_position(&arguments[1], len)?;
+            if start > end {
+                return Err(format!("Slice start {} is after its end {}", start, end))
+            }
+            Ok(Value::List(Rc::new(RefCell::new(list.borrow()[start..end].to_vec()))))
+        })),
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// Looks up the method `name` on `map`.
+pub fn map_method(map: &Rc<RefCell<Map>>, name: &str) -> Option<Native> {
+    let map = map.clone();
+    let method = match name {
+        "keys" => Native::new("keys", 0, Box::new(move |_| {
+            let keys = map.borrow().iter().map(|(key, _)| key.to_value()).collect();
+            Ok(Value::List(Rc::new(RefCell::new(keys))))
+        })),
+        "values" => Native::new("values", 0, Box::new(move |_| {
+            let values = map.borrow().iter().map(|(_, value)| value.clone()).collect();
+            Ok(Value::List(Rc::new(RefCell::new(values))))
+        })),
+        "has" => Native::new("has", 1, Box::new(move |arguments| {
+            let key = MapKey::from_value(&arguments[0])?;
+            Ok(Value::Boolean(map.borrow().contains(&key)))
+        })),
+        // Produces whether there was an entry to delete.
+        "delete" => Native::new("delete", 1, Box::new(move |arguments| {
+            let key = MapKey::from_value(&arguments[0])?;
+            Ok(Value::Boolean(map.borrow_mut().remove(&key).is_some()))
+        })),
+        "len" => Native::new("len", 0, Box::new(move |_| {
+            Ok(Value::Number(map.borrow().len() as i64))
+        })),
+        // Iterates over the keys the map has when the iterator is created.
+        "iterator" => Native::new("iterator", 0, Box::new(move |_| {
+            let mut keys = map.borrow().iter().map(|(key, _)| key.to_value()).collect::<Vec<Value>>().into_iter();
+            Ok(Value::Iterator(Rc::new(NativeIterator::new("map iterator", Box::new(move || keys.next())))))
+        })),
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// Looks up the method `name` on `string`.
+pub fn string_method(string: &str, name: &str) -> Option<Native> {
+    let string = string.to_string();
+    let method = match name {
+        // Iterates over the characters of the string, each as a string of its own.
+        "iterator" => Native::new("iterator", 0, Box::new(move |_| {
+            let mut characters = string.chars().map(|character| Value::String(character.to_string()))
+                .collect::<Vec<Value>>().into_iter();
+            Ok(Value::Iterator(Rc::new(NativeIterator::new("string iterator", Box::new(move || characters.next())))))
+        })),
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// Looks up the method `name` on `enumeration`, whose variants take precedence over its methods.
+pub fn enum_method(enumeration: &Enum, name: &str) -> Option<Native> {
+    let method = match name {
+        "iterator" => {
+            let variants = enumeration.variants().to_vec();
+            Native::new("iterator", 0, Box::new(move |_| {
+                let mut variants = variants.clone().into_iter();
+                Ok(Value::Iterator(Rc::new(NativeIterator::new("enum iterator", Box::new(move || variants.next())))))
+            }))
+        },
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// Looks up the method `name` on `range`.
+pub fn range_method(range: Range, name: &str) -> Option<Native> {
+    let method = match name {
+        "step" => Native::new("step", 1, Box::new(move |arguments| match &arguments[0] {
+            Value::Number(step) => Ok(Value::Range(range.with_step(*step)?)),
+            other => Err(format!("Range step must be a number, not {}", other.type_name())),
+        })),
+        "iterator" => Native::new("iterator", 0, Box::new(move |_| {
+            let mut values = range.values();
+            Ok(Value::Iterator(Rc::new(NativeIterator::new("range iterator", Box::new(move || values.next().map(Value::Number))))))
+        })),
+        _ => return None,
+    };
+    Some(method)
+}
@@ -1,29 +1,287 @@
 use scanner::Token;
 use scanner::TokenType;
 use scanner::Literal;
-use ast::{Exp, BinaryExp, UnaryExp, LiteralExp, GroupingExp, Stmt, VarDecl, Identifier};
+use ast::{Exp, BinaryExp, UnaryExp, LiteralExp, GroupingExp, LogicalExp, ConditionalExp, CommaExp, CallExp, LambdaExp, AssignExp};
+use ast::{ListExp, IndexExp, SetIndexExp, GetExp, MapExp, AssignTarget, CompoundAssignExp, IncrementExp, DestructureExp, Element};
+use ast::{Stmt, VarDecl, Identifier, NodeId, ExpStmt, PrintStmt, Block, FunDecl, Function, ReturnStmt};
+use ast::{IfStmt, WhileStmt, BreakStmt, ContinueStmt, MatchStmt, MatchExp, MatchArm, Pattern, ImportStmt, ExportStmt};
+use ast::{ThrowStmt, TryStmt, CatchClause, YieldStmt, ForInStmt, EnumDecl};
+use ast::{BinaryOp, UnaryOp, LogicalOp, Span, TypeAnnotation};
 use std::ops::Index;
+use std::mem;
 use std::mem::{Discriminant, discriminant};
-use std::borrow::Borrow;
+use std::rc::Rc;
 
 // TODO: Write macro to make Discriminant of a value
 
+/// The most arguments a call, or parameters a function, may have.
+pub const MAX_ARGUMENTS: usize = 255;
+
 #[derive(Clone)]
 pub struct Parser<'a> {
     data: &'a[Token],
-    current_position: usize
+    current_position: usize,
+    next_node_id: usize,
+    function_depth: usize,
+    loop_depth: usize,
+    /// Whether the innermost function being parsed contains a `yield`.
+    function_yields: bool
 }
 
 impl TokenType {
-    fn matches(&self, valid_tokens : &mut Iterator<Item = Discriminant<TokenType>>) -> bool {
+    fn matches(&self, mut valid_tokens : &mut dyn Iterator<Item = Discriminant<TokenType>>) -> bool {
         // Strictly speaking, a matcher function should be passed from the client in stead of doing `.contains(self)`
-        valid_tokens
-            .map(|x| x.eq(&discriminant(self)))
-            .any(|x| x)
+        Iterator::any(&mut valid_tokens, |x| x.eq(&discriminant(self)))
+    }
+}
+
+fn binary_op(operator: &Token) -> Result<BinaryOp, String> {
+    match operator.token_type {
+        TokenType::MINUS => Ok(BinaryOp::Minus),
+        TokenType::PLUS => Ok(BinaryOp::Plus),
+        TokenType::SLASH => Ok(BinaryOp::Slash),
+        TokenType::STAR => Ok(BinaryOp::Star),
+        TokenType::PERCENT => Ok(BinaryOp::Percent),
+        TokenType::StarStar => Ok(BinaryOp::StarStar),
+        TokenType::TildeSlash => Ok(BinaryOp::TildeSlash),
+        TokenType::AMPERSAND => Ok(BinaryOp::Ampersand),
+        TokenType::PIPE => Ok(BinaryOp::Pipe),
+        TokenType::CARET => Ok(BinaryOp::Caret),
+        TokenType::LessLess => Ok(BinaryOp::LessLess),
+        TokenType::GreaterGreater => Ok(BinaryOp::GreaterGreater),
+        TokenType::BangEqual => Ok(BinaryOp::BangEqual),
+        TokenType::EqualEqual => Ok(BinaryOp::EqualEqual),
+        TokenType::GREATER => Ok(BinaryOp::Greater),
+        TokenType::GreaterEqual => Ok(BinaryOp::GreaterEqual),
+        TokenType::LESS => Ok(BinaryOp::Less),
+        TokenType::LessEqual => Ok(BinaryOp::LessEqual),
+        TokenType::DotDot => Ok(BinaryOp::DotDot),
+        TokenType::DotDotEqual => Ok(BinaryOp::DotDotEqual),
+        ref other => Err(format!("{:?} is not a binary operator, line: {}", other, operator.line)),
+    }
+}
+
+fn unary_op(operator: &Token) -> Result<UnaryOp, String> {
+    match operator.token_type {
+        TokenType::BANG => Ok(UnaryOp::Bang),
+        TokenType::MINUS => Ok(UnaryOp::Minus),
+        TokenType::TILDE => Ok(UnaryOp::Tilde),
+        ref other => Err(format!("{:?} is not a unary operator, line: {}", other, operator.line)),
+    }
+}
+
+/// The operator a compound assignment or increment applies to its target.
+fn update_op(operator: &Token) -> Result<BinaryOp, String> {
+    match operator.token_type {
+        TokenType::PlusEqual | TokenType::PlusPlus => Ok(BinaryOp::Plus),
+        TokenType::MinusEqual | TokenType::MinusMinus => Ok(BinaryOp::Minus),
+        TokenType::StarEqual => Ok(BinaryOp::Star),
+        TokenType::SlashEqual => Ok(BinaryOp::Slash),
+        TokenType::PercentEqual => Ok(BinaryOp::Percent),
+        ref other => Err(format!("{:?} is not an update operator, line: {}", other, operator.line)),
+    }
+}
+
+/// Binds `name` in a pattern, unless the pattern already binds it.
+fn bind(name: &str, bindings: &mut Vec<String>, line: usize) -> Result<Pattern, String> {
+    if bindings.iter().any(|binding| binding == name) {
+        return Err(format!("Duplicate binding '{}' in pattern, line: {}", name, line))
+    }
+    bindings.push(name.to_string());
+    Ok(Pattern::Binding(Identifier(name.to_string())))
+}
+
+/// Checks that a pattern declaring variables only takes values apart, rather than testing them.
+fn declaration_pattern(pattern: &Pattern, span: Span) -> Result<(), String> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Binding(_) => Ok(()),
+        Pattern::Literal(_) | Pattern::Variant(..) =>
+            Err(format!("Can only destructure into names, not literals, line: {}", span.line)),
+        Pattern::List(elements) => elements.iter().try_for_each(|element| declaration_pattern(element, span)),
+        Pattern::Map(entries) => entries.iter().try_for_each(|(_, value)| declaration_pattern(value, span)),
+    }
+}
+
+/// The pattern a list or map expression on the left of `=` destructures into, as in
+/// `[a, b] = [b, a]`.
+fn assignment_pattern(target: Exp, bindings: &mut Vec<String>, line: usize) -> Result<Pattern, String> {
+    match target {
+        Exp::LiteralExp(LiteralExp { value: Literal::IDENTIFIER(name), .. }) if name == "_" => Ok(Pattern::Wildcard),
+        Exp::LiteralExp(LiteralExp { value: Literal::IDENTIFIER(name), .. }) => bind(&name, bindings, line),
+        Exp::ListExp(list) => list.elements.into_iter()
+            .map(|element| match element {
+                Element::Single(element) => assignment_pattern(element, bindings, line),
+                Element::Spread(_) => Err(format!("Invalid assignment target, line: {}", line)),
+            })
+            .collect::<Result<Vec<Pattern>, String>>()
+            .map(Pattern::List),
+        Exp::MapExp(map) => map.entries.into_iter()
+            .map(|(key, value)| match key {
+                Exp::LiteralExp(LiteralExp { value: key @ (Literal::STRING(_) | Literal::NUMBER(_) | Literal::BOOLEAN(_)), .. }) =>
+                    Ok((key, assignment_pattern(value, bindings, line)?)),
+                _ => Err(format!("Map pattern keys must be strings, numbers or booleans, line: {}", line)),
+            })
+            .collect::<Result<Vec<(Literal, Pattern)>, String>>()
+            .map(Pattern::Map),
+        _ => Err(format!("Invalid assignment target, line: {}", line)),
+    }
+}
+
+/// Checks that `target` can be assigned to in place.
+fn assign_target(target: Exp, operator: &Token) -> Result<AssignTarget, String> {
+    match target {
+        Exp::LiteralExp(LiteralExp { value: Literal::IDENTIFIER(name), .. }) => Ok(AssignTarget::Variable(Identifier(name))),
+        Exp::IndexExp(IndexExp { object, index, .. }) => Ok(AssignTarget::Index { object, index }),
+        _ => Err(format!("Invalid assignment target, line: {}", operator.line)),
+    }
+}
+
+fn logical_op(operator: &Token) -> Result<LogicalOp, String> {
+    match operator.token_type {
+        TokenType::AND => Ok(LogicalOp::And),
+        TokenType::OR => Ok(LogicalOp::Or),
+        TokenType::QuestionQuestion => Ok(LogicalOp::QuestionQuestion),
+        ref other => Err(format!("{:?} is not a logical operator, line: {}", other, operator.line)),
+    }
+}
+
+/// How tightly an operator binds, loosest first. While parsing at a given precedence, only operators
+/// with at least that precedence are allowed to extend the expression.
+#[derive(Eq, PartialEq)]
+#[derive(PartialOrd, Ord)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+enum Precedence {
+    Comma,
+    Assignment,
+    Conditional,
+    Coalesce,
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equality,
+    Comparison,
+    Range,
+    Shift,
+    Term,
+    Factor,
+    Unary,
+    Exponent,
+    Call,
+}
+
+impl Precedence {
+    fn tighter(self) -> Precedence {
+        match self {
+            Precedence::Comma => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Conditional,
+            Precedence::Conditional => Precedence::Coalesce,
+            Precedence::Coalesce => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::BitOr,
+            Precedence::BitOr => Precedence::BitXor,
+            Precedence::BitXor => Precedence::BitAnd,
+            Precedence::BitAnd => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Range,
+            Precedence::Range => Precedence::Shift,
+            Precedence::Shift => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Exponent,
+            Precedence::Exponent => Precedence::Call,
+            Precedence::Call => Precedence::Call,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// Parses an expression starting with the operator token that has just been consumed.
+type PrefixParser<'a> = fn(&mut Parser<'a>, &'a Token) -> Result<Exp, String>;
+/// Continues the expression to the left of the operator token that has just been consumed. Postfix
+/// operators are infix rules which don't parse a right hand operand.
+type InfixParser<'a> = fn(&mut Parser<'a>, Exp, &'a Token, InfixRule<'a>) -> Result<Exp, String>;
+
+#[derive(Clone, Copy)]
+struct InfixRule<'a> {
+    precedence: Precedence,
+    associativity: Associativity,
+    parse: InfixParser<'a>,
+}
+
+impl<'a> InfixRule<'a> {
+    fn new(precedence: Precedence, associativity: Associativity, parse: InfixParser<'a>) -> InfixRule<'a> {
+        InfixRule { precedence, associativity, parse }
+    }
+
+    fn right_operand_precedence(&self) -> Precedence {
+        match self.associativity {
+            Associativity::Left => self.precedence.tighter(),
+            Associativity::Right => self.precedence,
+        }
+    }
+}
+
+fn prefix_rule<'a>(token_type: &TokenType) -> Option<PrefixParser<'a>> {
+    match token_type {
+        TokenType::BANG | TokenType::MINUS | TokenType::TILDE => Some(Parser::unary),
+        TokenType::PlusPlus | TokenType::MinusMinus => Some(Parser::prefix_increment),
+        TokenType::LeftParen => Some(Parser::grouping),
+        TokenType::FUN => Some(Parser::lambda),
+        TokenType::LeftBracket => Some(Parser::list),
+        TokenType::LeftBrace => Some(Parser::map),
+        TokenType::MATCH => Some(Parser::match_expression),
+        TokenType::NIL | TokenType::TRUE | TokenType::FALSE | TokenType::Literal(_) => Some(Parser::literal),
+        _ => None,
+    }
+}
+
+fn infix_rule<'a>(token_type: &TokenType) -> Option<InfixRule<'a>> {
+    match token_type {
+        TokenType::COMMA => Some(InfixRule::new(Precedence::Comma, Associativity::Left, Parser::comma)),
+        TokenType::EQUAL => Some(InfixRule::new(Precedence::Assignment, Associativity::Right, Parser::assignment)),
+        TokenType::PlusEqual | TokenType::MinusEqual | TokenType::StarEqual | TokenType::SlashEqual | TokenType::PercentEqual =>
+            Some(InfixRule::new(Precedence::Assignment, Associativity::Right, Parser::compound_assignment)),
+        TokenType::QUESTION => Some(InfixRule::new(Precedence::Conditional, Associativity::Right, Parser::conditional)),
+        TokenType::QuestionQuestion => Some(InfixRule::new(Precedence::Coalesce, Associativity::Right, Parser::logical)),
+        TokenType::OR => Some(InfixRule::new(Precedence::Or, Associativity::Left, Parser::logical)),
+        TokenType::AND => Some(InfixRule::new(Precedence::And, Associativity::Left, Parser::logical)),
+        // As in C, the bitwise operators bind looser than equality, so `a & b == c` is `a & (b == c)`.
+        TokenType::PIPE => Some(InfixRule::new(Precedence::BitOr, Associativity::Left, Parser::binary)),
+        TokenType::CARET => Some(InfixRule::new(Precedence::BitXor, Associativity::Left, Parser::binary)),
+        TokenType::AMPERSAND => Some(InfixRule::new(Precedence::BitAnd, Associativity::Left, Parser::binary)),
+        TokenType::EqualEqual | TokenType::BangEqual =>
+            Some(InfixRule::new(Precedence::Equality, Associativity::Left, Parser::binary)),
+        TokenType::GREATER | TokenType::GreaterEqual | TokenType::LESS | TokenType::LessEqual =>
+            Some(InfixRule::new(Precedence::Comparison, Associativity::Left, Parser::binary)),
+        // Binds looser than arithmetic so `0..n - 1` ends at `n - 1`, and tighter than comparisons.
+        TokenType::DotDot | TokenType::DotDotEqual =>
+            Some(InfixRule::new(Precedence::Range, Associativity::Left, Parser::binary)),
+        TokenType::LessLess | TokenType::GreaterGreater =>
+            Some(InfixRule::new(Precedence::Shift, Associativity::Left, Parser::binary)),
+        TokenType::MINUS | TokenType::PLUS =>
+            Some(InfixRule::new(Precedence::Term, Associativity::Left, Parser::binary)),
+        TokenType::SLASH | TokenType::STAR | TokenType::PERCENT | TokenType::TildeSlash =>
+            Some(InfixRule::new(Precedence::Factor, Associativity::Left, Parser::binary)),
+        // Binds tighter than a unary minus to its left, so `-2 ** 2` is `-(2 ** 2)`.
+        TokenType::StarStar => Some(InfixRule::new(Precedence::Exponent, Associativity::Right, Parser::binary)),
+        TokenType::LeftParen => Some(InfixRule::new(Precedence::Call, Associativity::Left, Parser::call)),
+        TokenType::LeftBracket => Some(InfixRule::new(Precedence::Call, Associativity::Left, Parser::index)),
+        TokenType::DOT | TokenType::QuestionDot => Some(InfixRule::new(Precedence::Call, Associativity::Left, Parser::get)),
+        TokenType::PlusPlus | TokenType::MinusMinus =>
+            Some(InfixRule::new(Precedence::Call, Associativity::Left, Parser::postfix_increment)),
+        _ => None,
     }
 }
 
-fn instances_to_discriminants<A> (items : &'static [A]) -> Box<Iterator<Item=Discriminant<A>>> {
+fn instances_to_discriminants<A> (items : &'static [A]) -> Box<dyn Iterator<Item=Discriminant<A>>> {
     Box::new(items.iter().map(discriminant))
 }
 
@@ -31,42 +289,103 @@ impl<'a> Parser<'a> {
     pub fn new(data: &'a[Token]) -> Parser<'a> {
         Parser {
             data,
-            current_position: 0
+            current_position: 0,
+            next_node_id: 0,
+            function_depth: 0,
+            loop_depth: 0,
+            function_yields: false
         }
     }
 
+    /// Hands out the id for the next node built by this parser; every `Exp` and `Stmt` gets its own.
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_node_id);
+        self.next_node_id += 1;
+        id
+    }
+
     pub fn parse(&mut self) -> Result<Vec<Stmt>, String> {
-        let mut statements: Vec<Result<Stmt, String>> = vec![];
+        // Without error recovery a failed statement doesn't consume its tokens, so stop at the first error.
+        let mut statements: Vec<Stmt> = vec![];
         while self.current_position != self.data.len() {
-            statements.push(self.statement());
+            statements.push(self.top_level_statement()?);
         }
 
-        statements.iter().cloned().collect()
+        Ok(statements)
     }
 
+    /// Declarations can only be exported from the top level, so they're the only place `export`
+    /// is parsed.
+    fn top_level_statement(&mut self) -> Result<Stmt, String> {
+        if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::EXPORT]).as_mut()) {
+            self.consume_export_body().map(Stmt::Export)
+        } else {
+            self.statement()
+        }
+    }
 
     fn statement(&mut self) -> Result<Stmt, String> {
         // TODO: Implement some sort of error recovery
-        return if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::VAR]).as_mut()) {
-            self.consume_declaration_body().map(Stmt::VarDecl)
-        } else if self.consume_valid_tokens(&mut instances_to_discriminants(&[TokenType::PRINT]).as_mut()) {
-            self.consume_statement_body().map(Stmt::PrintStmt)
+        if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::VAR]).as_mut()) {
+            self.consume_declaration_body(false).map(Stmt::VarDecl)
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::CONST]).as_mut()) {
+            self.consume_declaration_body(true).map(Stmt::VarDecl)
+        } else if self.function_declaration_follows() {
+            self.consume_function_declaration().map(Stmt::FunDecl)
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::PRINT]).as_mut()) {
+            self.consume_statement_body().map(|exp| Stmt::PrintStmt(PrintStmt { id: self.next_id(), exp }))
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::RETURN]).as_mut()) {
+            self.consume_return_body().map(Stmt::Return)
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::YIELD]).as_mut()) {
+            self.consume_yield_body().map(Stmt::Yield)
+        } else if !self.map_literal_follows() && self.consume_valid_tokens(instances_to_discriminants(&[TokenType::LeftBrace]).as_mut()) {
+            self.consume_block().map(|stmts| Stmt::Block(Block { id: self.next_id(), stmts }))
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::IF]).as_mut()) {
+            self.consume_if_body()
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::WHILE]).as_mut()) {
+            self.consume_while_body()
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::FOR]).as_mut()) {
+            self.consume_for_body()
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::BREAK, TokenType::CONTINUE]).as_mut()) {
+            self.consume_loop_jump()
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::MATCH]).as_mut()) {
+            self.consume_match_body()
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::IMPORT]).as_mut()) {
+            self.consume_import_body().map(Stmt::Import)
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::ENUM]).as_mut()) {
+            self.consume_enum_body().map(Stmt::Enum)
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::THROW]).as_mut()) {
+            let line = self.data.index(self.current_position - 1).line;
+            self.consume_statement_body().map(|exp| Stmt::Throw(ThrowStmt { id: self.next_id(), exp, span: Span { line } }))
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::TRY]).as_mut()) {
+            self.consume_try_body().map(Stmt::Try)
+        } else if self.check(&TokenType::EXPORT) {
+            Err(format!("Can only export top-level declarations, line: {}", self.data.index(self.current_position).line))
         } else {
-            self.consume_statement_body().map(Stmt::Statement)
+            self.consume_statement_body().map(|exp| Stmt::Statement(ExpStmt { id: self.next_id(), exp }))
         }
     }
 
     // TODO: Maybe can undo all of the Discriminent stuff...
-    fn consume_declaration_body(&mut self) -> Result<VarDecl, String> {
+    fn consume_declaration_body(&mut self, constant: bool) -> Result<VarDecl, String> {
         let current = self.data.index(self.current_position);
+        let span = Span { line: current.line };
 
+        if matches!(current.token_type, TokenType::LeftBracket | TokenType::LeftBrace) {
+            let pattern = self.consume_pattern(&mut vec![])?;
+            declaration_pattern(&pattern, span)?;
+            self.consume_expected(TokenType::EQUAL, "Expected equals after destructuring pattern")?;
+            return self.consume_statement_body()
+                .map(|exp| VarDecl { id: self.next_id(), pattern, exp: Some(exp), constant, annotation: None, span })
+        }
         if let TokenType::Literal(literal) = current.clone().token_type {
             if let Literal::IDENTIFIER(id) = literal {
                 self.current_position += 1;
+                let annotation = self.consume_annotation()?;
                 return if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::EQUAL]).as_mut()) {
                     self.consume_statement_body()
                         .map(|exp| {
-                                VarDecl { identifier: Identifier(id), exp: Some(exp) }
+                                VarDecl { id: self.next_id(), pattern: Pattern::Binding(Identifier(id)), exp: Some(exp), constant, annotation, span }
                         })
                 } else {
                     Err("Expected equals after variable name".to_string())
@@ -74,7 +393,462 @@ impl<'a> Parser<'a> {
             }
             return Err("Expected Identifier token".to_string())
         }
-        return Err("Expected Literal token".to_string())
+        Err("Expected Literal token".to_string())
+    }
+
+    /// `fun` followed by a name starts a declaration, whereas any other `fun` starts a lambda.
+    fn function_declaration_follows(&self) -> bool {
+        match (self.data.get(self.current_position), self.data.get(self.current_position + 1)) {
+            (Some(keyword), Some(name)) =>
+                keyword.token_type == TokenType::FUN
+                    && matches!(name.token_type, TokenType::Literal(Literal::IDENTIFIER(_))),
+            _ => false,
+        }
+    }
+
+    /// A '{' starting a statement opens a block unless it's followed by a key and ':', which can't
+    /// start a statement. So `{}` on its own is an empty block, and `{"a": 1}.keys();` is a map.
+    fn map_literal_follows(&self) -> bool {
+        match (self.data.get(self.current_position), self.data.get(self.current_position + 2)) {
+            (Some(brace), Some(colon)) => brace.token_type == TokenType::LeftBrace && colon.token_type == TokenType::COLON,
+            _ => false,
+        }
+    }
+
+    fn consume_function_declaration(&mut self) -> Result<FunDecl, String> {
+        let keyword = self.consume_expected(TokenType::FUN, "Expect 'fun'")?;
+        let name = self.consume_identifier("Expect function name")?;
+        let function = self.consume_function(keyword)?;
+        Ok(FunDecl { id: self.next_id(), name, function })
+    }
+
+    /// Parses the parameter list and body that follow `fun` or a function's name.
+    fn consume_function(&mut self, keyword: &'a Token) -> Result<Rc<Function>, String> {
+        self.consume_expected(TokenType::LeftParen, "Expect '(' before parameters")?;
+        let mut params: Vec<Identifier> = vec![];
+        let mut param_types = vec![];
+        let mut defaults: Vec<Option<Exp>> = vec![];
+        let mut rest = false;
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(format!("Can't have more than {} parameters, line: {}", MAX_ARGUMENTS, keyword.line))
+                }
+                rest = self.consume_valid_tokens(instances_to_discriminants(&[TokenType::DotDotDot]).as_mut());
+                let param = self.consume_identifier("Expect parameter name")?;
+                if params.contains(&param) {
+                    return Err(format!("Duplicate parameter '{}', line: {}", param.0, keyword.line))
+                }
+                param_types.push(self.consume_annotation()?);
+                let default = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::EQUAL]).as_mut()) {
+                    if rest {
+                        return Err(format!("Rest parameter '{}' can't have a default, line: {}", param.0, keyword.line))
+                    }
+                    Some(self.parse_precedence(Precedence::Assignment)?)
+                } else {
+                    None
+                };
+                if default.is_none() && !rest && defaults.iter().any(Option::is_some) {
+                    return Err(format!("Parameter '{}' without a default can't follow parameters with defaults, line: {}",
+                                       param.0, keyword.line))
+                }
+                params.push(param);
+                defaults.push(default);
+                if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                    break
+                }
+                if rest {
+                    return Err(format!("Rest parameter must be the last parameter, line: {}", keyword.line))
+                }
+            }
+        }
+        self.consume_expected(TokenType::RightParen, "Expect ')' after parameters")?;
+        let return_type = self.consume_annotation()?;
+        self.consume_expected(TokenType::LeftBrace, "Expect '{' before function body")?;
+
+        // A function body isn't inside any loop enclosing the function, even when it's a lambda.
+        let enclosing_loop_depth = self.loop_depth;
+        let enclosing_yields = mem::replace(&mut self.function_yields, false);
+        self.loop_depth = 0;
+        self.function_depth += 1;
+        let body = self.consume_block();
+        self.function_depth -= 1;
+        self.loop_depth = enclosing_loop_depth;
+        let generator = mem::replace(&mut self.function_yields, enclosing_yields);
+
+        Ok(Rc::new(Function { params, param_types, defaults, return_type, body: body?, generator, rest, span: Span { line: keyword.line } }))
+    }
+
+    /// Parses the `: Type` that may follow a declared name or a parameter list.
+    fn consume_annotation(&mut self) -> Result<Option<TypeAnnotation>, String> {
+        if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COLON]).as_mut()) {
+            return Ok(None)
+        }
+        let line = self.data.index(self.current_position - 1).line;
+        let name = self.consume_identifier("Expect type name after ':'")?;
+        TypeAnnotation::from_name(&name.0)
+            .map(Some)
+            .ok_or_else(|| format!("Unknown type '{}', line: {}", name.0, line))
+    }
+
+    /// Parses the statements of a block whose '{' has already been consumed, up to and including the '}'.
+    fn consume_block(&mut self) -> Result<Vec<Stmt>, String> {
+        let mut stmts = vec![];
+        while !self.check(&TokenType::RightBrace) {
+            if self.current_position >= self.data.len() {
+                return Err("Expect '}' after block.".to_string())
+            }
+            stmts.push(self.statement()?);
+        }
+        self.current_position += 1;
+        Ok(stmts)
+    }
+
+    fn consume_return_body(&mut self) -> Result<ReturnStmt, String> {
+        let line = self.data.index(self.current_position - 1).line;
+        if self.function_depth == 0 {
+            return Err(format!("Can't return from top-level code, line: {}", line))
+        }
+        let exp = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::SEMICOLON]).as_mut()) {
+            None
+        } else {
+            Some(self.consume_statement_body()?)
+        };
+        Ok(ReturnStmt { id: self.next_id(), exp, span: Span { line } })
+    }
+
+    fn consume_yield_body(&mut self) -> Result<YieldStmt, String> {
+        let line = self.data.index(self.current_position - 1).line;
+        if self.function_depth == 0 {
+            return Err(format!("Can't yield from top-level code, line: {}", line))
+        }
+        self.function_yields = true;
+        let exp = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::SEMICOLON]).as_mut()) {
+            None
+        } else {
+            Some(self.consume_statement_body()?)
+        };
+        Ok(YieldStmt { id: self.next_id(), exp, span: Span { line } })
+    }
+
+    fn consume_if_body(&mut self) -> Result<Stmt, String> {
+        self.consume_expected(TokenType::LeftParen, "Expect '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume_expected(TokenType::RightParen, "Expect ')' after if condition")?;
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::ELSE]).as_mut()) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::If(IfStmt { id: self.next_id(), condition, then_branch, else_branch }))
+    }
+
+    fn consume_while_body(&mut self) -> Result<Stmt, String> {
+        self.consume_expected(TokenType::LeftParen, "Expect '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume_expected(TokenType::RightParen, "Expect ')' after condition")?;
+        let body = Box::new(self.consume_loop_body()?);
+        Ok(Stmt::While(WhileStmt { id: self.next_id(), condition, body, increment: None }))
+    }
+
+    /// Desugars `for (initializer; condition; increment) body` into a `while` loop, in a block
+    /// scoping the initializer to the loop.
+    fn consume_for_body(&mut self) -> Result<Stmt, String> {
+        let line = self.data.index(self.current_position - 1).line;
+        self.consume_expected(TokenType::LeftParen, "Expect '(' after 'for'")?;
+        if self.data.get(self.current_position + 1).is_some_and(|token| token.token_type == TokenType::IN) {
+            return self.consume_for_in_body(Span { line })
+        }
+        let initializer = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::SEMICOLON]).as_mut()) {
+            None
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::VAR]).as_mut()) {
+            Some(self.consume_declaration_body(false).map(Stmt::VarDecl)?)
+        } else {
+            let exp = self.consume_statement_body()?;
+            Some(Stmt::Statement(ExpStmt { id: self.next_id(), exp }))
+        };
+        let condition = if self.check(&TokenType::SEMICOLON) {
+            Exp::LiteralExp(LiteralExp { id: self.next_id(), value: Literal::BOOLEAN(true), span: Span { line } })
+        } else {
+            self.expression()?
+        };
+        self.consume_expected(TokenType::SEMICOLON, "Expect ';' after loop condition")?;
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume_expected(TokenType::RightParen, "Expect ')' after for clauses")?;
+        let body = Box::new(self.consume_loop_body()?);
+
+        let loop_stmt = Stmt::While(WhileStmt { id: self.next_id(), condition, body, increment });
+        match initializer {
+            None => Ok(loop_stmt),
+            Some(initializer) => Ok(Stmt::Block(Block { id: self.next_id(), stmts: vec![initializer, loop_stmt] })),
+        }
+    }
+
+    /// Parses the rest of a `for (name in iterable)` loop, after the '('.
+    fn consume_for_in_body(&mut self, span: Span) -> Result<Stmt, String> {
+        let name = self.consume_identifier("Expect loop variable name before 'in'")?;
+        self.consume_expected(TokenType::IN, "Expect 'in' after loop variable")?;
+        let iterable = self.expression()?;
+        self.consume_expected(TokenType::RightParen, "Expect ')' after for clauses")?;
+        let body = Box::new(self.consume_loop_body()?);
+        Ok(Stmt::ForIn(ForInStmt { id: self.next_id(), name, iterable, body, span }))
+    }
+
+    fn consume_loop_body(&mut self) -> Result<Stmt, String> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn consume_loop_jump(&mut self) -> Result<Stmt, String> {
+        let keyword = self.data.index(self.current_position - 1);
+        let span = Span { line: keyword.line };
+        let is_break = keyword.token_type == TokenType::BREAK;
+        if self.loop_depth == 0 {
+            let name = if is_break { "break" } else { "continue" };
+            return Err(format!("Can't use '{}' outside of a loop, line: {}", name, keyword.line))
+        }
+        self.consume_expected(TokenType::SEMICOLON, "Expect ';' after loop jump")?;
+        let id = self.next_id();
+        Ok(if is_break {
+            Stmt::Break(BreakStmt { id, span })
+        } else {
+            Stmt::Continue(ContinueStmt { id, span })
+        })
+    }
+
+    fn consume_import_body(&mut self) -> Result<ImportStmt, String> {
+        let line = self.data.index(self.current_position - 1).line;
+        let path = match self.data.get(self.current_position) {
+            Some(Token { token_type: TokenType::Literal(Literal::STRING(path)), .. }) => path.clone(),
+            _ => return Err(format!("Expect module path string after 'import', line: {}", line)),
+        };
+        self.current_position += 1;
+        self.consume_expected(TokenType::AS, "Expect 'as' after module path")?;
+        let name = self.consume_identifier("Expect module name after 'as'")?;
+        self.consume_expected(TokenType::SEMICOLON, "Expect ';' after import")?;
+        Ok(ImportStmt { id: self.next_id(), path, name, span: Span { line } })
+    }
+
+    /// `enum Name { A, B }`, with an optional trailing comma after the last variant.
+    fn consume_enum_body(&mut self) -> Result<EnumDecl, String> {
+        let line = self.data.index(self.current_position - 1).line;
+        let name = self.consume_identifier("Expect enum name")?;
+        self.consume_expected(TokenType::LeftBrace, "Expect '{' after enum name")?;
+        let mut variants: Vec<Identifier> = vec![];
+        loop {
+            let variant = self.consume_identifier("Expect variant name in enum")?;
+            if variants.contains(&variant) {
+                return Err(format!("Duplicate variant '{}' in enum {}, line: {}", variant.0, name.0, line))
+            }
+            variants.push(variant);
+            if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) || self.check(&TokenType::RightBrace) {
+                break
+            }
+        }
+        self.consume_expected(TokenType::RightBrace, "Expect '}' after enum variants")?;
+        Ok(EnumDecl { id: self.next_id(), name, variants, span: Span { line } })
+    }
+
+    fn consume_export_body(&mut self) -> Result<ExportStmt, String> {
+        let line = self.data.index(self.current_position - 1).line;
+        let decl = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::VAR]).as_mut()) {
+            self.consume_declaration_body(false).map(Stmt::VarDecl)?
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::CONST]).as_mut()) {
+            self.consume_declaration_body(true).map(Stmt::VarDecl)?
+        } else if self.function_declaration_follows() {
+            self.consume_function_declaration().map(Stmt::FunDecl)?
+        } else if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::ENUM]).as_mut()) {
+            self.consume_enum_body().map(Stmt::Enum)?
+        } else {
+            return Err(format!("Expect 'var', 'const', 'fun' or 'enum' declaration after 'export', line: {}", line))
+        };
+        Ok(ExportStmt { id: self.next_id(), decl: Box::new(decl) })
+    }
+
+    fn consume_try_body(&mut self) -> Result<TryStmt, String> {
+        let line = self.data.index(self.current_position - 1).line;
+        self.consume_expected(TokenType::LeftBrace, "Expect '{' after 'try'")?;
+        let body = self.consume_block()?;
+        let catch = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::CATCH]).as_mut()) {
+            self.consume_expected(TokenType::LeftParen, "Expect '(' after 'catch'")?;
+            let name = self.consume_identifier("Expect variable name in catch clause")?;
+            self.consume_expected(TokenType::RightParen, "Expect ')' after catch variable")?;
+            self.consume_expected(TokenType::LeftBrace, "Expect '{' before catch body")?;
+            Some(CatchClause { name, body: self.consume_block()? })
+        } else {
+            None
+        };
+        let finally = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::FINALLY]).as_mut()) {
+            self.consume_expected(TokenType::LeftBrace, "Expect '{' after 'finally'")?;
+            Some(self.consume_block()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(format!("Expect 'catch' or 'finally' after try block, line: {}", line))
+        }
+        Ok(TryStmt { id: self.next_id(), body, catch, finally })
+    }
+
+    fn consume_match_body(&mut self) -> Result<Stmt, String> {
+        let keyword = self.data.index(self.current_position - 1);
+        let subject = self.consume_match_subject()?;
+        let mut arms = vec![];
+        while !self.check(&TokenType::RightBrace) {
+            if self.current_position >= self.data.len() {
+                return Err("Expect '}' after match arms at end of input".to_string())
+            }
+            let (pattern, guard, span) = self.consume_arm_head()?;
+            let body = self.statement()?;
+            arms.push(MatchArm { pattern, guard, body, span });
+        }
+        self.current_position += 1;
+        Ok(Stmt::Match(MatchStmt { id: self.next_id(), subject, arms, span: Span { line: keyword.line } }))
+    }
+
+    /// Consumes `(subject) {`, which starts both forms of `match`.
+    fn consume_match_subject(&mut self) -> Result<Exp, String> {
+        self.consume_expected(TokenType::LeftParen, "Expect '(' after 'match'")?;
+        let subject = self.expression()?;
+        self.consume_expected(TokenType::RightParen, "Expect ')' after match subject")?;
+        self.consume_expected(TokenType::LeftBrace, "Expect '{' before match arms")?;
+        Ok(subject)
+    }
+
+    /// Consumes `pattern if guard =>`.
+    fn consume_arm_head(&mut self) -> Result<(Pattern, Option<Exp>, Span), String> {
+        let span = Span { line: self.data.get(self.current_position).map_or(0, |token| token.line) };
+        let pattern = self.consume_pattern(&mut vec![])?;
+        let guard = if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::IF]).as_mut()) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume_expected(TokenType::FatArrow, "Expect '=>' after match pattern")?;
+        Ok((pattern, guard, span))
+    }
+
+    /// Consumes a pattern, adding the names it binds to `bindings`.
+    fn consume_pattern(&mut self, bindings: &mut Vec<String>) -> Result<Pattern, String> {
+        let data = self.data;
+        let token = match data.get(self.current_position) {
+            Some(token) => token,
+            None => return Err("Expect pattern at end of input".to_string()),
+        };
+        self.current_position += 1;
+        match token.token_type {
+            TokenType::Literal(Literal::IDENTIFIER(ref name)) if name == "_" => Ok(Pattern::Wildcard),
+            TokenType::Literal(Literal::IDENTIFIER(ref name))
+                if self.consume_valid_tokens(instances_to_discriminants(&[TokenType::DOT]).as_mut()) => {
+                let variant = self.consume_identifier("Expect variant name after '.' in pattern")?;
+                Ok(Pattern::Variant(Identifier(name.clone()), variant))
+            },
+            TokenType::Literal(Literal::IDENTIFIER(ref name)) => bind(name, bindings, token.line),
+            TokenType::Literal(ref literal) => Ok(Pattern::Literal(literal.clone())),
+            TokenType::TRUE => Ok(Pattern::Literal(Literal::BOOLEAN(true))),
+            TokenType::FALSE => Ok(Pattern::Literal(Literal::BOOLEAN(false))),
+            TokenType::NIL => Ok(Pattern::Literal(Literal::NIL)),
+            TokenType::MINUS => match data.get(self.current_position) {
+                Some(Token { token_type: TokenType::Literal(Literal::NUMBER(number)), .. }) => {
+                    self.current_position += 1;
+                    Ok(Pattern::Literal(Literal::NUMBER(-number)))
+                },
+                _ => Err(format!("Expect number after '-' in pattern, line: {}", token.line)),
+            },
+            TokenType::LeftBracket => {
+                let mut elements = vec![];
+                if !self.check(&TokenType::RightBracket) {
+                    loop {
+                        elements.push(self.consume_pattern(bindings)?);
+                        if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                            break
+                        }
+                    }
+                }
+                self.consume_expected(TokenType::RightBracket, "Expect ']' after list pattern")?;
+                Ok(Pattern::List(elements))
+            },
+            TokenType::LeftBrace => {
+                let mut entries = vec![];
+                if !self.check(&TokenType::RightBrace) {
+                    loop {
+                        if let Some(name) = self.shorthand_entry() {
+                            entries.push((Literal::STRING(name.clone()), bind(name, bindings, token.line)?));
+                            if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                                break
+                            }
+                            continue
+                        }
+                        let key = match self.consume_pattern(&mut vec![])? {
+                            Pattern::Literal(Literal::NIL) => None,
+                            Pattern::Literal(key) => Some(key),
+                            _ => None,
+                        };
+                        let key = key.ok_or_else(||
+                            format!("Map pattern keys must be strings, numbers or booleans, line: {}", token.line))?;
+                        self.consume_expected(TokenType::COLON, "Expect ':' after map pattern key")?;
+                        entries.push((key, self.consume_pattern(bindings)?));
+                        if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                            break
+                        }
+                    }
+                }
+                self.consume_expected(TokenType::RightBrace, "Expect '}' after map pattern")?;
+                Ok(Pattern::Map(entries))
+            },
+            _ => Err(format!("Expect pattern, line: {}", token.line)),
+        }
+    }
+
+    /// Consumes a bare name in a map pattern, as in `{x, y}`, which is short for `{"x": x, "y": y}`.
+    fn shorthand_entry(&mut self) -> Option<&'a String> {
+        let data = self.data;
+        match (data.get(self.current_position), data.get(self.current_position + 1)) {
+            (Some(Token { token_type: TokenType::Literal(Literal::IDENTIFIER(name)), .. }), Some(next))
+                if matches!(next.token_type, TokenType::COMMA | TokenType::RightBrace) => {
+                self.current_position += 1;
+                Some(name)
+            },
+            _ => None,
+        }
+    }
+
+    fn check(&self, token_type: &TokenType) -> bool {
+        match self.data.get(self.current_position) {
+            Some(token) => discriminant(&token.token_type) == discriminant(token_type),
+            None => false,
+        }
+    }
+
+    fn consume_expected(&mut self, token_type: TokenType, message: &str) -> Result<&'a Token, String> {
+        let data = self.data;
+        match data.get(self.current_position) {
+            Some(token) if discriminant(&token.token_type) == discriminant(&token_type) => {
+                self.current_position += 1;
+                Ok(token)
+            },
+            Some(token) => Err(format!("{}, line: {}", message, token.line)),
+            None => Err(format!("{} at end of input", message)),
+        }
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> Result<Identifier, String> {
+        let data = self.data;
+        match data.get(self.current_position) {
+            Some(Token { token_type: TokenType::Literal(Literal::IDENTIFIER(name)), .. }) => {
+                self.current_position += 1;
+                Ok(Identifier(name.clone()))
+            },
+            Some(token) => Err(format!("{}, line: {}", message, token.line)),
+            None => Err(format!("{} at end of input", message)),
+        }
     }
 
     fn consume_statement_body(&mut self) -> Result<Exp, String> {
@@ -88,10 +862,10 @@ impl<'a> Parser<'a> {
     }
 
     fn expression(&mut self) -> Result<Exp, String> {
-        self.equality()
+        self.parse_precedence(Precedence::Comma)
     }
 
-    fn consume_valid_tokens(&mut self, valid_tokens : &mut Iterator<Item=Discriminant<TokenType>>) -> bool {
+    fn consume_valid_tokens(&mut self, valid_tokens : &mut dyn Iterator<Item=Discriminant<TokenType>>) -> bool {
         if self.data.len() <= self.current_position {
             return false
         }
@@ -104,163 +878,300 @@ impl<'a> Parser<'a> {
         false
     }
 
-    // TODO: Could implement this whole parser in terms of a huge match statement... Might be simpler...
-    fn execute_level(&mut self, valid_tokens : &mut Box<Iterator<Item=Discriminant<TokenType>>>, previous_exp : Exp,
-        current_exp_generator : &Fn(&mut Parser, &Token, Exp) -> Result<Exp, String>) -> Result<Exp, String> {
-        let mut expr = Ok(previous_exp);
+    fn parse_precedence(&mut self, precedence: Precedence) -> Result<Exp, String> {
+        // Tokens borrow from the slice rather than the parser, so the rules below can take `&mut self`.
+        let data = self.data;
+        let token = match data.get(self.current_position) {
+            None => return Err("No tokens to parse".to_string()),
+            Some(token) => token,
+        };
+        let prefix = match prefix_rule(&token.token_type) {
+            None => return Err(format!("Expected an expression but found {:?}, line: {}", token.token_type, token.line)),
+            Some(prefix) => prefix,
+        };
+        self.current_position += 1;
+        let mut exp = prefix(self, token)?;
 
-        while self.consume_valid_tokens(valid_tokens.as_mut()) {
-            let operator = self.data.index(self.current_position - 1);
-            expr = match expr {
-                Ok(ex) => current_exp_generator(self, operator, ex),
-                err => err
-            }
+        while let Some(operator) = data.get(self.current_position) {
+            let rule = match infix_rule(&operator.token_type) {
+                Some(rule) if rule.precedence >= precedence => rule,
+                _ => break,
+            };
+            self.current_position += 1;
+            exp = (rule.parse)(self, exp, operator, rule)?;
         }
 
-        expr
+        Ok(exp)
     }
 
-    fn equality(&mut self) -> Result<Exp, String> {
-        fn next_exp_generator(instance: &mut Parser, operator: &Token, curr_expr: Exp) -> Result<Exp, String> {
-            instance.comparison().map(
-              |right|  Exp::BinaryExp(
-                  BinaryExp{
-                      left: Box::new(curr_expr),
-                      operator: operator.clone(),
-                      right: Box::new(right) })
-            )
+    fn binary(&mut self, left: Exp, operator: &'a Token, rule: InfixRule<'a>) -> Result<Exp, String> {
+        let op = binary_op(operator)?;
+        let right = self.parse_precedence(rule.right_operand_precedence())?;
+        Ok(Exp::BinaryExp(
+            BinaryExp{
+                id: self.next_id(),
+                left: Box::new(left),
+                operator: op,
+                span: Span { line: operator.line },
+                right: Box::new(right) }))
+    }
 
+    fn logical(&mut self, left: Exp, operator: &'a Token, rule: InfixRule<'a>) -> Result<Exp, String> {
+        let op = logical_op(operator)?;
+        let right = self.parse_precedence(rule.right_operand_precedence())?;
+        Ok(Exp::LogicalExp(
+            LogicalExp{
+                id: self.next_id(),
+                left: Box::new(left),
+                operator: op,
+                span: Span { line: operator.line },
+                right: Box::new(right) }))
+    }
+
+    fn assignment(&mut self, target: Exp, equals: &'a Token, rule: InfixRule<'a>) -> Result<Exp, String> {
+        let value = self.parse_precedence(rule.right_operand_precedence())?;
+        match target {
+            Exp::LiteralExp(LiteralExp { value: Literal::IDENTIFIER(name), .. }) => Ok(Exp::AssignExp(
+                AssignExp{
+                    id: self.next_id(),
+                    name: Identifier(name),
+                    value: Box::new(value),
+                    span: Span { line: equals.line }})),
+            Exp::IndexExp(IndexExp { object, index, .. }) => Ok(Exp::SetIndexExp(
+                SetIndexExp{
+                    id: self.next_id(),
+                    object,
+                    index,
+                    value: Box::new(value),
+                    span: Span { line: equals.line }})),
+            target @ (Exp::ListExp(_) | Exp::MapExp(_)) => Ok(Exp::DestructureExp(
+                DestructureExp{
+                    id: self.next_id(),
+                    pattern: assignment_pattern(target, &mut vec![], equals.line)?,
+                    value: Box::new(value),
+                    span: Span { line: equals.line }})),
+            _ => Err(format!("Invalid assignment target, line: {}", equals.line)),
         }
-        match self.comparison() {
-            Ok(prev_exp) => self.execute_level(
-                &mut instances_to_discriminants(
-                    &[TokenType::EqualEqual, TokenType::BangEqual]),
-                prev_exp,
-                &next_exp_generator),
-            err => err
-        }
+    }
 
+    fn compound_assignment(&mut self, target: Exp, operator: &'a Token, rule: InfixRule<'a>) -> Result<Exp, String> {
+        let op = update_op(operator)?;
+        let target = assign_target(target, operator)?;
+        let value = self.parse_precedence(rule.right_operand_precedence())?;
+        Ok(Exp::CompoundAssignExp(
+            CompoundAssignExp{
+                id: self.next_id(),
+                target,
+                operator: op,
+                value: Box::new(value),
+                span: Span { line: operator.line }}))
     }
 
-    fn comparison(&mut self) -> Result<Exp, String> {
-        fn next_exp_generator(instance: &mut Parser, operator: &Token, curr_expr: Exp) -> Result<Exp, String> {
-            instance.addition().map(
-                | right | Exp::BinaryExp(
-                    BinaryExp{
-                        left: Box::new(curr_expr),
-                        operator: operator.clone(),
-                        right: Box::new(right) })
-            )
-        }
-        match self.addition() {
-            Ok(prev_exp) => self.execute_level(
-                &mut instances_to_discriminants(
-                    &[TokenType::GREATER, TokenType::GreaterEqual, TokenType::LESS, TokenType::LessEqual]),
-                prev_exp,
-                &next_exp_generator),
-            err => err
-        }
+    fn prefix_increment(&mut self, operator: &'a Token) -> Result<Exp, String> {
+        let target = self.parse_precedence(Precedence::Unary)?;
+        self.increment(target, operator, false)
     }
 
-    fn addition(&mut self) -> Result<Exp, String> {
-        fn next_exp_generator(instance: &mut Parser, operator: &Token, curr_expr: Exp) -> Result<Exp, String> {
-            instance.multiplication().map(
-                |right| Exp::BinaryExp(
-                    BinaryExp{
-                        left: Box::new(curr_expr),
-                        operator: operator.clone(),
-                        right: Box::new(right) })
-            )
-        }
-        match self.multiplication() {
-            Ok(prev_exp) => self.execute_level(
-                &mut instances_to_discriminants(
-                &[TokenType::MINUS, TokenType::PLUS]),
-                prev_exp,
-                &next_exp_generator),
-            err => err
-        }
+    fn postfix_increment(&mut self, target: Exp, operator: &'a Token, _rule: InfixRule<'a>) -> Result<Exp, String> {
+        self.increment(target, operator, true)
     }
 
-    fn multiplication(&mut self) -> Result<Exp, String> {
-        fn next_exp_generator(instance: &mut Parser, operator: &Token, curr_expr: Exp) -> Result<Exp, String> {
-            instance.unary().map(
-                | right | Exp::BinaryExp(
-                    BinaryExp{
-                        left: Box::new(curr_expr),
-                        operator: operator.clone(),
-                        right: Box::new(right) })
-            )
+    fn increment(&mut self, target: Exp, operator: &'a Token, postfix: bool) -> Result<Exp, String> {
+        Ok(Exp::IncrementExp(
+            IncrementExp{
+                id: self.next_id(),
+                target: assign_target(target, operator)?,
+                operator: update_op(operator)?,
+                postfix,
+                span: Span { line: operator.line }}))
+    }
+
+    fn conditional(&mut self, condition: Exp, operator: &'a Token, rule: InfixRule<'a>) -> Result<Exp, String> {
+        // Like C, anything may appear between '?' and ':' as it is delimited on both sides.
+        let then_branch = self.expression()?;
+        if !self.consume_valid_tokens(&mut instances_to_discriminants(&[TokenType::COLON])) {
+            return Err(format!("Expect ':' after then branch of conditional expression, line: {}", operator.line))
         }
-        match self.unary() {
-            Ok(prev_exp) => self.execute_level(
-                &mut instances_to_discriminants(
-                    &[TokenType::SLASH, TokenType::STAR]),
-                prev_exp,
-                &next_exp_generator),
-            err => err
+        let else_branch = self.parse_precedence(rule.right_operand_precedence())?;
+        Ok(Exp::ConditionalExp(
+            ConditionalExp{
+                id: self.next_id(),
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+                span: Span { line: operator.line }}))
+    }
+
+    fn comma(&mut self, left: Exp, operator: &'a Token, rule: InfixRule<'a>) -> Result<Exp, String> {
+        let right = self.parse_precedence(rule.right_operand_precedence())?;
+        Ok(Exp::CommaExp(
+            CommaExp{
+                id: self.next_id(),
+                left: Box::new(left),
+                span: Span { line: operator.line },
+                right: Box::new(right) }))
+    }
+
+    fn call(&mut self, callee: Exp, paren: &'a Token, _rule: InfixRule<'a>) -> Result<Exp, String> {
+        let mut arguments = vec![];
+        let mut named: Vec<(Identifier, Exp)> = vec![];
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() + named.len() >= MAX_ARGUMENTS {
+                    return Err(format!("Can't have more than {} arguments, line: {}", MAX_ARGUMENTS, paren.line))
+                }
+                if let Some(name) = self.argument_name() {
+                    if named.iter().any(|(other, _)| *other == name) {
+                        return Err(format!("Duplicate named argument '{}', line: {}", name.0, paren.line))
+                    }
+                    named.push((name, self.parse_precedence(Precedence::Assignment)?));
+                } else if !named.is_empty() {
+                    return Err(format!("Positional arguments must come before named arguments, line: {}", paren.line))
+                } else {
+                    arguments.push(self.element()?);
+                }
+                if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                    break
+                }
+            }
         }
+        self.consume_expected(TokenType::RightParen, "Expect ')' after arguments")?;
+        Ok(Exp::CallExp(
+            CallExp{
+                id: self.next_id(),
+                callee: Box::new(callee),
+                arguments,
+                named,
+                span: Span { line: paren.line }}))
+    }
+
+    fn index(&mut self, object: Exp, bracket: &'a Token, _rule: InfixRule<'a>) -> Result<Exp, String> {
+        let index = self.expression()?;
+        self.consume_expected(TokenType::RightBracket, "Expect ']' after index")?;
+        Ok(Exp::IndexExp(
+            IndexExp{
+                id: self.next_id(),
+                object: Box::new(object),
+                index: Box::new(index),
+                span: Span { line: bracket.line }}))
     }
 
-    fn unary(&mut self) -> Result<Exp, String> {
-        let valid_tokens =
-            &mut instances_to_discriminants(&[TokenType::BANG, TokenType::MINUS]);
+    fn get(&mut self, object: Exp, dot: &'a Token, _rule: InfixRule<'a>) -> Result<Exp, String> {
+        let name = self.consume_identifier("Expect property name after '.'")?;
+        Ok(Exp::GetExp(
+            GetExp{
+                id: self.next_id(),
+                object: Box::new(object),
+                name,
+                optional: dot.token_type == TokenType::QuestionDot,
+                span: Span { line: dot.line }}))
+    }
 
-        if self.consume_valid_tokens(valid_tokens) {
-            let operator = self.data.index(self.current_position - 1);
-            return self.unary().map(
-                | right | Exp::UnaryExp(
-                    UnaryExp{
-                        right: Box::new(right),
-                        operator: operator.clone()})
-            )
+    /// Consumes the `name:` starting a named argument, if there is one.
+    fn argument_name(&mut self) -> Option<Identifier> {
+        match (self.data.get(self.current_position), self.data.get(self.current_position + 1)) {
+            (Some(Token { token_type: TokenType::Literal(Literal::IDENTIFIER(name)), .. }), Some(colon))
+                if colon.token_type == TokenType::COLON => {
+                self.current_position += 2;
+                Some(Identifier(name.clone()))
+            },
+            _ => None,
         }
+    }
 
-        self.primary()
-    }
-
-    fn primary(&mut self) -> Result<Exp, String> {
-        fn advance(instance : &mut Parser) {
-            if instance.current_position != instance.data.len() {
-                instance.current_position += 1;
+    /// Parses a list element or call argument, which may be spread with `...`.
+    fn element(&mut self) -> Result<Element, String> {
+        let spread = self.consume_valid_tokens(instances_to_discriminants(&[TokenType::DotDotDot]).as_mut());
+        // Commas separate the elements, so each one is parsed above the comma operator.
+        let exp = self.parse_precedence(Precedence::Assignment)?;
+        Ok(if spread { Element::Spread(exp) } else { Element::Single(exp) })
+    }
+
+    fn list(&mut self, bracket: &'a Token) -> Result<Exp, String> {
+        let mut elements = vec![];
+        if !self.check(&TokenType::RightBracket) {
+            loop {
+                elements.push(self.element()?);
+                if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                    break
+                }
             }
         }
+        self.consume_expected(TokenType::RightBracket, "Expect ']' after list elements")?;
+        Ok(Exp::ListExp(ListExp{id: self.next_id(), elements, span: Span { line: bracket.line }}))
+    }
 
-        // TODO: this consume_valid_tokens and position checking logic is duplicated a bunch - clean it up
-        // Match literals
-        if self.data.len() <= self.current_position {
-            return Err("No tokens to parse".to_string())
-        }
-        let current = self.data.index(self.current_position);
-        match current.token_type {
-            TokenType::NIL => {
-                advance(self);
-                Ok(Exp::LiteralExp(LiteralExp{value: Literal::STRING("null".to_string())}))
-            }
-            TokenType::TRUE => {
-                advance(self);
-                Ok(Exp::LiteralExp(LiteralExp{value: Literal::STRING("true".to_string())}))
+    fn map(&mut self, brace: &'a Token) -> Result<Exp, String> {
+        let mut entries = vec![];
+        if !self.check(&TokenType::RightBrace) {
+            loop {
+                let key = self.parse_precedence(Precedence::Assignment)?;
+                self.consume_expected(TokenType::COLON, "Expect ':' after map key")?;
+                let value = self.parse_precedence(Precedence::Assignment)?;
+                entries.push((key, value));
+                if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                    break
+                }
             }
-            TokenType::FALSE => {
-                advance(self);
-                Ok(Exp::LiteralExp(LiteralExp{value: Literal::STRING("false".to_string())}))
+        }
+        self.consume_expected(TokenType::RightBrace, "Expect '}' after map entries")?;
+        Ok(Exp::MapExp(MapExp{id: self.next_id(), entries, span: Span { line: brace.line }}))
+    }
+
+    fn match_expression(&mut self, keyword: &'a Token) -> Result<Exp, String> {
+        let subject = self.consume_match_subject()?;
+        let mut arms = vec![];
+        while !self.check(&TokenType::RightBrace) {
+            let (pattern, guard, span) = self.consume_arm_head()?;
+            // Commas separate the arms, so a body can't use the comma operator without parentheses.
+            let body = self.parse_precedence(Precedence::Assignment)?;
+            arms.push(MatchArm { pattern, guard, body, span });
+            if !self.consume_valid_tokens(instances_to_discriminants(&[TokenType::COMMA]).as_mut()) {
+                break
             }
-            TokenType::Literal(ref literal) => {
-                advance(self);
-                Ok(Exp::LiteralExp(LiteralExp{value: literal.clone() }))},
-            TokenType::LeftParen => {
-                if self.consume_valid_tokens(&mut instances_to_discriminants(&[TokenType::LeftParen])) {
-                    let expr = self.expression();
-                    if self.consume_valid_tokens(&mut instances_to_discriminants(&[TokenType::RightParen])) {
-                        return expr.map(|ex| Exp::GroupingExp(GroupingExp{exp: Box::new(ex)}))
-                    }
-                    return Err("Expect ')' after expression.".to_string())
-                }
-                Err("No valid ')' found after '('".to_string()) // TODO: Unlear why this needs to be String not &str
-            },
-            TokenType::RightParen => panic!("Did not expect to reach this branch of match statement"),
-            _ => Err("Didn't expect to get anything other than a literal or paren here".to_string())
         }
+        self.consume_expected(TokenType::RightBrace, "Expect '}' after match arms")?;
+        Ok(Exp::MatchExp(
+            MatchExp{
+                id: self.next_id(),
+                subject: Box::new(subject),
+                arms,
+                span: Span { line: keyword.line }}))
+    }
+
+    fn lambda(&mut self, keyword: &'a Token) -> Result<Exp, String> {
+        let function = self.consume_function(keyword)?;
+        Ok(Exp::LambdaExp(LambdaExp{id: self.next_id(), function}))
+    }
+
+    fn unary(&mut self, operator: &'a Token) -> Result<Exp, String> {
+        let op = unary_op(operator)?;
+        let right = self.parse_precedence(Precedence::Unary)?;
+        Ok(Exp::UnaryExp(
+            UnaryExp{
+                id: self.next_id(),
+                right: Box::new(right),
+                operator: op,
+                span: Span { line: operator.line }}))
+    }
+
+    fn grouping(&mut self, _paren: &'a Token) -> Result<Exp, String> {
+        let expr = self.expression()?;
+        if self.consume_valid_tokens(&mut instances_to_discriminants(&[TokenType::RightParen])) {
+            return Ok(Exp::GroupingExp(GroupingExp{id: self.next_id(), exp: Box::new(expr)}))
+        }
+        Err("Expect ')' after expression.".to_string())
+    }
+
+    fn literal(&mut self, token: &'a Token) -> Result<Exp, String> {
+        let value = match token.token_type {
+            TokenType::NIL => Literal::NIL,
+            TokenType::TRUE => Literal::BOOLEAN(true),
+            TokenType::FALSE => Literal::BOOLEAN(false),
+            TokenType::Literal(ref literal) => literal.clone(),
+            ref other => return Err(format!("Expected a literal but found {:?}, line: {}", other, token.line)),
+        };
+        Ok(Exp::LiteralExp(LiteralExp{id: self.next_id(), value, span: Span { line: token.line } }))
     }
 }
 
@@ -279,20 +1190,18 @@ mod tests {
         ];
         let expected_exp: Exp = Exp::BinaryExp(
             BinaryExp {
-                left: Box::new(Exp::LiteralExp(LiteralExp{ value: Literal::IDENTIFIER("foobar".to_string()) })
+                id: NodeId(2),
+                left: Box::new(Exp::LiteralExp(LiteralExp{ id: NodeId(0), value: Literal::IDENTIFIER("foobar".to_string()), span: Span { line: 0 } })
                 ),
-                operator: Token {
-                    token_type: TokenType::EqualEqual,
-                    lexeme: "==".to_string(),
-                    line: 0
-                },
-                right: Box::new(Exp::LiteralExp(LiteralExp{value: Literal::NUMBER(2)}))});
+                operator: BinaryOp::EqualEqual,
+                span: Span { line: 0 },
+                right: Box::new(Exp::LiteralExp(LiteralExp{ id: NodeId(1), value: Literal::NUMBER(2), span: Span { line: 0 } }))});
         let exp_result = Parser::new(valid_tokens.as_ref()).expression();
         match exp_result {
             Ok(exp) => {
                 assert_eq!(exp, expected_exp)
             },
-            Err(err) => panic!(err)
+            Err(err) => panic!("{}", err)
         }
     }
 
@@ -309,17 +1218,16 @@ mod tests {
         let expected_exp: Exp =
             Exp::GroupingExp(
                 GroupingExp {
+                    id: NodeId(3),
                     exp: Box::new(
                         Exp::BinaryExp(
                             BinaryExp {
-                                left: Box::new(Exp::LiteralExp(LiteralExp{ value: Literal::IDENTIFIER("foobar".to_string()) })
+                                id: NodeId(2),
+                                left: Box::new(Exp::LiteralExp(LiteralExp{ id: NodeId(0), value: Literal::IDENTIFIER("foobar".to_string()), span: Span { line: 0 } })
                                 ),
-                                operator: Token {
-                                    token_type: TokenType::EqualEqual,
-                                    lexeme: "==".to_string(),
-                                    line: 0
-                                },
-                                right: Box::new(Exp::LiteralExp(LiteralExp{value: Literal::NUMBER(2)}))}
+                                operator: BinaryOp::EqualEqual,
+                                span: Span { line: 0 },
+                                right: Box::new(Exp::LiteralExp(LiteralExp{ id: NodeId(1), value: Literal::NUMBER(2), span: Span { line: 0 } }))}
                         )
                     )
                 }
@@ -329,7 +1237,377 @@ mod tests {
             Ok(exp) => {
                 assert_eq!(exp, expected_exp)
             },
-            Err(err) => panic!(err)
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    #[test]
+    fn node_ids_are_unique_within_a_parse()
+    {
+        let tokens = vec![
+            Token{token_type: TokenType::PRINT, lexeme: "p".to_string(), line: 0},
+            Token{token_type: TokenType::Literal(Literal::NUMBER(1)), lexeme: "1".to_string(), line: 0},
+            Token{token_type: TokenType::SEMICOLON, lexeme: ";".to_string(), line: 0},
+            Token{token_type: TokenType::PRINT, lexeme: "p".to_string(), line: 1},
+            Token{token_type: TokenType::Literal(Literal::NUMBER(1)), lexeme: "1".to_string(), line: 1},
+            Token{token_type: TokenType::SEMICOLON, lexeme: ";".to_string(), line: 1},
+        ];
+        let stmts = Parser::new(tokens.as_ref()).parse().expect("valid statements");
+
+        let exp_ids: Vec<NodeId> = stmts.iter()
+            .map(|stmt| match stmt {
+                Stmt::PrintStmt(print) => print.exp.id(),
+                other => panic!("Expected print statement, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(exp_ids, vec![NodeId(0), NodeId(2)]);
+        assert_eq!(stmts[0].id(), NodeId(1));
+        assert_eq!(stmts[1].id(), NodeId(3));
+    }
+
+    #[test]
+    fn parse_logical_operators_with_precedence()
+    {
+        let tokens = vec![
+            Token{token_type: TokenType::Literal(Literal::IDENTIFIER("a".to_string())), lexeme: "a".to_string(), line: 0},
+            Token{token_type: TokenType::OR, lexeme: "o".to_string(), line: 0},
+            Token{token_type: TokenType::Literal(Literal::IDENTIFIER("b".to_string())), lexeme: "b".to_string(), line: 0},
+            Token{token_type: TokenType::AND, lexeme: "a".to_string(), line: 0},
+            Token{token_type: TokenType::BANG, lexeme: "!".to_string(), line: 0},
+            Token{token_type: TokenType::Literal(Literal::IDENTIFIER("c".to_string())), lexeme: "c".to_string(), line: 0},
+        ];
+        let exp = Parser::new(tokens.as_ref()).expression().expect("valid expression");
+
+        match exp {
+            Exp::LogicalExp(LogicalExp { operator: LogicalOp::Or, right, .. }) => match *right {
+                Exp::LogicalExp(LogicalExp { operator: LogicalOp::And, right, .. }) => match *right {
+                    Exp::UnaryExp(UnaryExp { operator: UnaryOp::Bang, .. }) => {},
+                    other => panic!("Expected unary '!', got {:?}", other),
+                },
+                other => panic!("Expected 'and' expression, got {:?}", other),
+            },
+            other => panic!("Expected 'or' expression, got {:?}", other),
+        }
+    }
+
+    fn print_parsed(source: &str) -> String {
+        let mut scanner = ::scanner::Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().expect("valid statements");
+        let mut output = String::new();
+        for stmt in &stmts {
+            ::ast::stmt_printer(&mut output, stmt);
+        }
+        output
+    }
+
+    #[test]
+    fn binary_operators_are_left_associative()
+    {
+        assert_eq!(print_parsed("1 - 2 + 3 - 4;"), "(- (+ (- 1 2) 3) 4);");
+        assert_eq!(print_parsed("8 / 4 * 2;"), "(* (/ 8 4) 2);");
+    }
+
+    #[test]
+    fn precedence_table_orders_operators()
+    {
+        assert_eq!(print_parsed("1 + 2 * -3 == 4 or 5 and 6;"), "(or (== (+ 1 (* 2 (- 3))) 4) (and 5 6));");
+    }
+
+    #[test]
+    fn ranges_bind_between_comparison_and_shift()
+    {
+        assert_eq!(print_parsed("0..n - 1 == 1 << 2..=3;"), "(== (.. 0 (- n 1)) (..= (<< 1 2) 3));");
+    }
+
+    #[test]
+    fn missing_operand_is_an_error()
+    {
+        let mut scanner = ::scanner::Scanner::new("1 + ;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn conditional_is_right_associative_and_binds_looser_than_or()
+    {
+        assert_eq!(print_parsed("a or b ? c : d ? e : f;"), "(?: (or a b) c (?: d e f));");
+        assert_eq!(print_parsed("a ? b, c : d;"), "(?: a (, b c) d);");
+    }
+
+    #[test]
+    fn comma_binds_loosest()
+    {
+        assert_eq!(print_parsed("1, 2 ? 3 : 4, 5;"), "(, (, 1 (?: 2 3 4)) 5);");
+    }
+
+    #[test]
+    fn conditional_without_colon_is_an_error()
+    {
+        let mut scanner = ::scanner::Scanner::new("a ? b;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(),
+                   Err("Expect ':' after then branch of conditional expression, line: 0".to_string()));
+    }
+
+    #[test]
+    fn fun_at_statement_start_is_a_declaration_only_when_named()
+    {
+        assert_eq!(print_parsed("fun add(a, b) { return a + b; }"), "fun add(a b) { return (+ a b); }");
+        assert_eq!(print_parsed("fun (a) { print a; }(1);"), "(call (fun (a) { print a; }) 1);");
+    }
+
+    #[test]
+    fn return_outside_function_is_an_error()
+    {
+        let mut scanner = ::scanner::Scanner::new("return 1;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Can't return from top-level code, line: 0".to_string()));
+    }
+
+    #[test]
+    fn for_loop_desugars_to_while_in_a_block()
+    {
+        assert_eq!(print_parsed("for (var i = 0; i < 3; i = i + 1) print i;"),
+                   "{ var i = 0; while ((< i 3); (= i (+ i 1))) print i; }");
+        assert_eq!(print_parsed("for (;;) break;"), "while (true) break;");
+    }
+
+    #[test]
+    fn assignment_is_right_associative_and_needs_a_variable_target()
+    {
+        assert_eq!(print_parsed("a = b = 1, 2;"), "(, (= a (= b 1)) 2);");
+        let mut scanner = ::scanner::Scanner::new("a + b = 1;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Invalid assignment target, line: 0".to_string()));
+    }
+
+    #[test]
+    fn loop_jumps_outside_loop_are_errors()
+    {
+        let mut scanner = ::scanner::Scanner::new("if (true) break;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Can't use 'break' outside of a loop, line: 0".to_string()));
+
+        let mut scanner = ::scanner::Scanner::new("while (true) { fun f() { continue; } }".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Can't use 'continue' outside of a loop, line: 0".to_string()));
+    }
+
+    #[test]
+    fn list_literals_indexing_and_methods()
+    {
+        assert_eq!(print_parsed("[1, a = 2, [3]][0];"), "(index (list 1 (= a 2) (list 3)) 0);");
+        assert_eq!(print_parsed("xs[i] = ys[0] = 1;"), "(index= xs i (index= ys 0 1));");
+        assert_eq!(print_parsed("xs.slice(0, 1).len();"), "(call (. len (call (. slice xs) 0 1)));");
+        assert_eq!(print_parsed("[];"), "(list);");
+    }
+
+    #[test]
+    fn brace_starts_a_map_only_when_a_key_and_colon_follow()
+    {
+        assert_eq!(print_parsed("var m = {\"a\": 1, 2: {}};"), "var m = (map a 1 2 (map));");
+        assert_eq!(print_parsed("{\"a\": 1}.keys();"), "(call (. keys (map a 1)));");
+        assert_eq!(print_parsed("{ a; }"), "{ a; }");
+        assert_eq!(print_parsed("{}"), "{ }");
+    }
+
+    #[test]
+    fn exponent_is_right_associative_and_binds_tighter_than_unary()
+    {
+        assert_eq!(print_parsed("-2 ** 3 ** 2;"), "(- (** 2 (** 3 2)));");
+        assert_eq!(print_parsed("2 ** -1 * 3;"), "(* (** 2 (- 1)) 3);");
+        assert_eq!(print_parsed("a % b ~/ c + d;"), "(+ (~/ (% a b) c) d);");
+    }
+
+    #[test]
+    fn bitwise_operators_have_c_precedence()
+    {
+        assert_eq!(print_parsed("a | b ^ c & d == e;"), "(| a (^ b (& c (== d e))));");
+        assert_eq!(print_parsed("a << b + c < d >> e;"), "(< (<< a (+ b c)) (>> d e));");
+        assert_eq!(print_parsed("a or b | c and d;"), "(or a (and (| b c) d));");
+        assert_eq!(print_parsed("~a & ~-b;"), "(& (~ a) (~ (- b)));");
+    }
+
+    #[test]
+    fn compound_assignment_and_increments_need_assignable_targets()
+    {
+        assert_eq!(print_parsed("a += b -= 2 * c;"), "(+= a (-= b (* 2 c)));");
+        assert_eq!(print_parsed("xs[i++] %= -x--;"), "(%= (index xs (post++ i)) (- (post-- x)));");
+        assert_eq!(print_parsed("++a[0] + --b;"), "(+ (pre++ (index a 0)) (pre-- b));");
+
+        for source in ["a + b += 1;", "(a)++;", "++a++;", "xs.len--;"].iter() {
+            let mut scanner = ::scanner::Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens();
+            assert_eq!(Parser::new(tokens).parse(), Err("Invalid assignment target, line: 0".to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn coalesce_binds_between_conditional_and_or()
+    {
+        assert_eq!(print_parsed("a ?? b or c ?? d;"), "(?? a (?? (or b c) d));");
+        assert_eq!(print_parsed("a ?? b ? c : d;"), "(?: (?? a b) c d);");
+        assert_eq!(print_parsed("a?.b.c(1)?.d;"), "(?. d (call (. c (?. b a)) 1));");
+    }
+
+    #[test]
+    fn match_statements_and_expressions()
+    {
+        assert_eq!(print_parsed("match (x) { [a, -1] if a > 0 => print a; {\"k\": _} => {} _ => x; }"),
+                   "match (x) { [a, -1] if (> a 0) => print a; {\"k\": _} => { } _ => x; }");
+        assert_eq!(print_parsed("print match (x) { 1 => \"one\", n => n, };"),
+                   "print (match x (1 => one) (n => n));");
+    }
+
+    #[test]
+    fn imports_and_top_level_exports()
+    {
+        assert_eq!(print_parsed("import \"lib/a.lox\" as a; export var x = a.y; export fun f() {}"),
+                   "import \"lib/a.lox\" as a;export var x = (. y a);export fun f() { }");
+
+        for (source, error) in [
+            ("{ export var x = 1; }", "Can only export top-level declarations, line: 0"),
+            ("export x;", "Expect 'var', 'const', 'fun' or 'enum' declaration after 'export', line: 0"),
+            ("import a as b;", "Expect module path string after 'import', line: 0"),
+            ("import \"a.lox\";", "Expect 'as' after module path, line: 0"),
+        ].iter() {
+            let mut scanner = ::scanner::Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens();
+            assert_eq!(Parser::new(tokens).parse(), Err(error.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn try_needs_a_catch_or_finally_clause()
+    {
+        assert_eq!(print_parsed("try { throw 1; } catch (e) { print e; } finally { }"),
+                   "try { throw 1; } catch (e) { print e; } finally { }");
+        assert_eq!(print_parsed("try { } finally { x; }"), "try { } finally { x; }");
+
+        let mut scanner = ::scanner::Scanner::new("try { }".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Expect 'catch' or 'finally' after try block, line: 0".to_string()));
+    }
+
+    #[test]
+    fn const_declarations_are_parsed_like_var()
+    {
+        assert_eq!(print_parsed("const a = 1; export const b = a;"), "const a = 1;export const b = a;");
+        let mut scanner = ::scanner::Scanner::new("const a;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Expected equals after variable name".to_string()));
+    }
+
+    #[test]
+    fn for_in_loops_are_parsed_separately_from_c_style_loops()
+    {
+        assert_eq!(print_parsed("for (x in xs) print x; for (;;) break;"), "for (x in xs) print x;while (true) break;");
+        let mut scanner = ::scanner::Scanner::new("for (x in xs) { } break;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Can't use 'break' outside of a loop, line: 0".to_string()));
+    }
+
+    #[test]
+    fn functions_containing_yield_are_generators()
+    {
+        assert_eq!(print_parsed("fun g() { while (true) { yield 1; } yield; }"), "fun g() { while (true) { yield 1; } yield; }");
+        let mut scanner = ::scanner::Scanner::new("fun g() { yield 1; var f = fun () { return 2; }; } fun h() { fun i() { yield; } }".to_string());
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().expect("valid statements");
+        let generators: Vec<bool> = stmts.iter().map(|stmt| match stmt {
+            Stmt::FunDecl(decl) => decl.function.generator,
+            _ => panic!("expected a function declaration"),
+        }).collect();
+        assert_eq!(generators, vec![true, false]);
+
+        let mut scanner = ::scanner::Scanner::new("yield 1;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Can't yield from top-level code, line: 0".to_string()));
+    }
+
+    #[test]
+    fn type_annotations_are_optional()
+    {
+        assert_eq!(print_parsed("var a: Number = 1; fun f(x: String, y): Bool { return x; } var g = fun (): Any {};"),
+                   "var a: Number = 1;fun f(x: String y): Bool { return x; }var g = (fun (): Any { });");
+        let mut scanner = ::scanner::Scanner::new("var a: Integer = 1;".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Unknown type 'Integer', line: 0".to_string()));
+    }
+
+    #[test]
+    fn declarations_and_assignments_can_destructure()
+    {
+        assert_eq!(print_parsed("var [a, [_, b]] = pair; const {x, \"k\": y} = point; [a, b] = [b, a];"),
+                   "var [a, [_, b]] = pair;const {\"x\": x, \"k\": y} = point;(= [a, b] (list b a));");
+        assert_eq!(print_parsed("match (p) { {x, y} => print x; }"), "match (p) { {\"x\": x, \"y\": y} => print x; }");
+    }
+
+    #[test]
+    fn rest_parameters_and_spread_elements_are_parsed()
+    {
+        assert_eq!(print_parsed("fun log(level, ...args) { } log(...xs, 1); print [...a, b, ...0..2];"),
+                   "fun log(level ...args) { }(call log ...xs 1);print (list ...a b ...(.. 0 2));");
+        let mut scanner = ::scanner::Scanner::new("fun f(...a, b) { }".to_string());
+        let tokens = scanner.scan_tokens();
+        assert_eq!(Parser::new(tokens).parse(), Err("Rest parameter must be the last parameter, line: 0".to_string()));
+    }
+
+    #[test]
+    fn defaults_and_named_arguments_are_validated()
+    {
+        assert_eq!(print_parsed("fun connect(host, port: Number = 80 + 1) { } connect(\"h\", port: 1);"),
+                   "fun connect(host port: Number = (+ 80 1)) { }(call connect h port: 1);");
+        for (source, error) in [
+            ("fun f(a = 1, b) { }", "Parameter 'b' without a default can't follow parameters with defaults, line: 0"),
+            ("fun f(a, b, a) { }", "Duplicate parameter 'a', line: 0"),
+            ("fun f(...a = []) { }", "Rest parameter 'a' can't have a default, line: 0"),
+            ("f(a: 1, a: 2);", "Duplicate named argument 'a', line: 0"),
+            ("f(a: 1, 2);", "Positional arguments must come before named arguments, line: 0"),
+        ].iter() {
+            let mut scanner = ::scanner::Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens();
+            assert_eq!(Parser::new(tokens).parse(), Err(error.to_string()), "{}", source);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn enum_declarations_and_variant_patterns()
+    {
+        assert_eq!(print_parsed("enum Color { Red, Green, Blue, } export enum Unit { Metric }"),
+                   "enum Color { Red, Green, Blue }export enum Unit { Metric }");
+        assert_eq!(print_parsed("match (c) { Color.Red => 1; [Color.Blue, c] => c; }"),
+                   "match (c) { Color.Red => 1; [Color.Blue, c] => c; }");
+
+        for (source, error) in [
+            ("enum Color { Red, Green, Red }", "Duplicate variant 'Red' in enum Color, line: 0"),
+            ("enum Color {}", "Expect variant name in enum, line: 0"),
+            ("enum { Red }", "Expect enum name, line: 0"),
+            ("match (c) { Color. => 1; }", "Expect variant name after '.' in pattern, line: 0"),
+        ].iter() {
+            let mut scanner = ::scanner::Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens();
+            assert_eq!(Parser::new(tokens).parse(), Err(error.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors()
+    {
+        for (source, error) in [
+            ("match (x) { [a, a] => 1; }", "Duplicate binding 'a' in pattern, line: 0"),
+            ("match (x) { a + 1 => 1; }", "Expect '=>' after match pattern, line: 0"),
+            ("var [a, {a}] = x;", "Duplicate binding 'a' in pattern, line: 0"),
+            ("var [a, 1] = x;", "Can only destructure into names, not literals, line: 0"),
+            ("var [a];", "Expected equals after destructuring pattern, line: 0"),
+            ("[a, b + 1] = x;", "Invalid assignment target, line: 0"),
+            ("[a, ...b] = x;", "Invalid assignment target, line: 0"),
+            ("var [Color.Red] = x;", "Can only destructure into names, not literals, line: 0"),
+        ].iter() {
+            let mut scanner = ::scanner::Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens();
+            assert_eq!(Parser::new(tokens).parse(), Err(error.to_string()), "{}", source);
+        }
+    }
+}
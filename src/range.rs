@@ -0,0 +1,100 @@
+use std::fmt;
+
+/// The numbers from `start` towards `end`, `step` apart. Ranges are lazy: their numbers are only
+/// produced as they're iterated over.
+#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    /// Whether `end` is included when a step lands on it.
+    pub inclusive: bool,
+    /// Never zero. A negative step counts down from `start` to `end`.
+    pub step: i64,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, inclusive: bool) -> Range {
+        Range { start, end, inclusive, step: 1 }
+    }
+
+    /// The same range, counting in steps of `step`.
+    pub fn with_step(self, step: i64) -> Result<Range, String> {
+        if step == 0 {
+            return Err("Range step can't be zero".to_string())
+        }
+        Ok(Range { step, ..self })
+    }
+
+    /// Whether `value` hasn't gone past the end of the range, in the direction it counts.
+    fn before_end(&self, value: i64) -> bool {
+        match (self.step > 0, self.inclusive) {
+            (true, false) => value < self.end,
+            (true, true) => value <= self.end,
+            (false, false) => value > self.end,
+            (false, true) => value >= self.end,
+        }
+    }
+
+    pub fn values(&self) -> RangeValues {
+        RangeValues { range: *self, next: Some(self.start) }
+    }
+}
+
+/// The numbers of a range, in order.
+pub struct RangeValues {
+    range: Range,
+    /// `None` once the range is finished, including when the next step would overflow.
+    next: Option<i64>,
+}
+
+impl Iterator for RangeValues {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let value = self.next.filter(|value| self.range.before_end(*value))?;
+        self.next = value.checked_add(self.range.step);
+        Some(value)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = if self.inclusive { "..=" } else { ".." };
+        match self.step {
+            1 => write!(f, "{}{}{}", self.start, operator, self.end),
+            step => write!(f, "({}{}{}).step({})", self.start, operator, self.end, step),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(range: Range) -> Vec<i64> {
+        range.values().collect()
+    }
+
+    #[test]
+    fn ranges_count_towards_their_end_in_steps()
+    {
+        assert_eq!(values(Range::new(0, 4, false)), vec![0, 1, 2, 3]);
+        assert_eq!(values(Range::new(0, 4, true)), vec![0, 1, 2, 3, 4]);
+        assert_eq!(values(Range::new(0, 9, true).with_step(3).unwrap()), vec![0, 3, 6, 9]);
+        assert_eq!(values(Range::new(5, 0, false).with_step(-2).unwrap()), vec![5, 3, 1]);
+        assert_eq!(values(Range::new(3, 3, false)), Vec::<i64>::new());
+        assert_eq!(values(Range::new(3, 0, false)), Vec::<i64>::new());
+        assert_eq!(values(Range::new(i64::MAX - 1, i64::MAX, true)), vec![i64::MAX - 1, i64::MAX]);
+        assert_eq!(Range::new(0, 1, false).with_step(0), Err("Range step can't be zero".to_string()));
+    }
+
+    #[test]
+    fn ranges_print_like_the_code_creating_them()
+    {
+        assert_eq!(Range::new(0, 10, false).to_string(), "0..10");
+        assert_eq!(Range::new(-1, 1, true).to_string(), "-1..=1");
+        assert_eq!(Range::new(0, 10, false).with_step(2).unwrap().to_string(), "(0..10).step(2)");
+    }
+}
@@ -0,0 +1,356 @@
+use ast::{Stmt, Exp, AssignTarget, MatchArm, Pattern, Function, Identifier, Span};
+use std::collections::HashMap;
+
+/// Walks a program before it runs, collecting errors for code which would be sure to fail, and
+/// warnings about code which is valid but probably a mistake. Warnings don't stop the program from
+/// running.
+pub struct Resolver {
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+    /// The names declared so far in each enclosing scope, outermost first, mapped to whether they
+    /// were declared with `const`.
+    scopes: Vec<HashMap<String, bool>>,
+}
+
+impl Default for Resolver {
+    fn default() -> Resolver {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    pub fn resolve(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    /// Resolves `stmts` in a new scope holding `names`.
+    fn resolve_scope(&mut self, names: Vec<String>, stmts: &[Stmt]) {
+        self.in_scope(names, |this| this.resolve(stmts));
+    }
+
+    fn in_scope(&mut self, names: Vec<String>, f: impl FnOnce(&mut Resolver)) {
+        self.scopes.push(names.into_iter().map(|name| (name, false)).collect());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Identifier, constant: bool, span: Span) {
+        let scope = self.scopes.last_mut().expect("the global scope is never popped");
+        if scope.get(&name.0) == Some(&true) {
+            self.errors.push(format!("Can't redeclare constant '{}', line: {}", name.0, span.line));
+        }
+        scope.insert(name.0.clone(), constant);
+    }
+
+    /// Reports assigning to `name` if it's known to be a constant. Names declared in no enclosing
+    /// scope may still be globals declared later, which the `Environment` checks at runtime.
+    fn check_assignable(&mut self, name: &Identifier, span: Span) {
+        let constant = self.scopes.iter().rev().find_map(|scope| scope.get(&name.0));
+        if constant == Some(&true) {
+            self.errors.push(format!("Can't assign to constant '{}', line: {}", name.0, span.line));
+        }
+    }
+
+    fn resolve_function(&mut self, function: &Function) {
+        let params = function.params.iter().map(|param| param.0.clone()).collect();
+        self.in_scope(params, |this| {
+            for default in function.defaults.iter().flatten() {
+                this.resolve_exp(default);
+            }
+            this.resolve(&function.body);
+        });
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::VarDecl(decl) => {
+                if let Some(exp) = &decl.exp {
+                    self.resolve_exp(exp);
+                }
+                for name in decl.pattern.bindings() {
+                    self.declare(name, decl.constant, decl.span);
+                }
+            },
+            Stmt::Statement(stmt) => self.resolve_exp(&stmt.exp),
+            Stmt::PrintStmt(stmt) => self.resolve_exp(&stmt.exp),
+            Stmt::Block(block) => self.resolve_scope(vec![], &block.stmts),
+            Stmt::FunDecl(decl) => {
+                self.declare(&decl.name, false, decl.function.span);
+                self.resolve_function(&decl.function);
+            },
+            Stmt::Return(stmt) => {
+                if let Some(exp) = &stmt.exp {
+                    self.resolve_exp(exp);
+                }
+            },
+            Stmt::Yield(stmt) => {
+                if let Some(exp) = &stmt.exp {
+                    self.resolve_exp(exp);
+                }
+            },
+            Stmt::If(stmt) => {
+                self.resolve_exp(&stmt.condition);
+                self.resolve_stmt(&stmt.then_branch);
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            },
+            Stmt::While(stmt) => {
+                self.resolve_exp(&stmt.condition);
+                self.resolve_stmt(&stmt.body);
+                if let Some(increment) = &stmt.increment {
+                    self.resolve_exp(increment);
+                }
+            },
+            Stmt::ForIn(stmt) => {
+                self.resolve_exp(&stmt.iterable);
+                self.in_scope(vec![stmt.name.0.clone()], |this| this.resolve_stmt(&stmt.body));
+            },
+            Stmt::Break(_) | Stmt::Continue(_) => {},
+            Stmt::Import(stmt) => self.declare(&stmt.name, false, stmt.span),
+            Stmt::Enum(decl) => self.declare(&decl.name, false, decl.span),
+            Stmt::Export(stmt) => self.resolve_stmt(&stmt.decl),
+            Stmt::Throw(stmt) => self.resolve_exp(&stmt.exp),
+            Stmt::Try(stmt) => {
+                self.resolve_scope(vec![], &stmt.body);
+                if let Some(catch) = &stmt.catch {
+                    self.resolve_scope(vec![catch.name.0.clone()], &catch.body);
+                }
+                if let Some(finally) = &stmt.finally {
+                    self.resolve_scope(vec![], finally);
+                }
+            },
+            Stmt::Match(stmt) => {
+                self.resolve_exp(&stmt.subject);
+                self.check_reachable(&stmt.arms);
+                for arm in &stmt.arms {
+                    self.in_scope(pattern_bindings(&arm.pattern), |this| {
+                        if let Some(guard) = &arm.guard {
+                            this.resolve_exp(guard);
+                        }
+                        this.resolve_stmt(&arm.body);
+                    });
+                }
+            },
+        }
+    }
+
+    fn resolve_exp(&mut self, exp: &Exp) {
+        match exp {
+            Exp::BinaryExp(x) => {
+                self.resolve_exp(&x.left);
+                self.resolve_exp(&x.right);
+            },
+            Exp::LogicalExp(x) => {
+                self.resolve_exp(&x.left);
+                self.resolve_exp(&x.right);
+            },
+            Exp::CommaExp(x) => {
+                self.resolve_exp(&x.left);
+                self.resolve_exp(&x.right);
+            },
+            Exp::ConditionalExp(x) => {
+                self.resolve_exp(&x.condition);
+                self.resolve_exp(&x.then_branch);
+                self.resolve_exp(&x.else_branch);
+            },
+            Exp::GroupingExp(x) => self.resolve_exp(&x.exp),
+            Exp::UnaryExp(x) => self.resolve_exp(&x.right),
+            Exp::LiteralExp(_) => {},
+            Exp::CallExp(x) => {
+                self.resolve_exp(&x.callee);
+                for argument in &x.arguments {
+                    self.resolve_exp(argument.exp());
+                }
+                for (_, argument) in &x.named {
+                    self.resolve_exp(argument);
+                }
+            },
+            Exp::LambdaExp(x) => self.resolve_function(&x.function),
+            Exp::AssignExp(x) => {
+                self.resolve_exp(&x.value);
+                self.check_assignable(&x.name, x.span);
+            },
+            Exp::ListExp(x) => {
+                for element in &x.elements {
+                    self.resolve_exp(element.exp());
+                }
+            },
+            Exp::MapExp(x) => {
+                for (key, value) in &x.entries {
+                    self.resolve_exp(key);
+                    self.resolve_exp(value);
+                }
+            },
+            Exp::IndexExp(x) => {
+                self.resolve_exp(&x.object);
+                self.resolve_exp(&x.index);
+            },
+            Exp::SetIndexExp(x) => {
+                self.resolve_exp(&x.object);
+                self.resolve_exp(&x.index);
+                self.resolve_exp(&x.value);
+            },
+            Exp::GetExp(x) => self.resolve_exp(&x.object),
+            Exp::CompoundAssignExp(x) => {
+                self.resolve_target(&x.target, x.span);
+                self.resolve_exp(&x.value);
+            },
+            Exp::IncrementExp(x) => self.resolve_target(&x.target, x.span),
+            Exp::DestructureExp(x) => {
+                self.resolve_exp(&x.value);
+                for name in x.pattern.bindings() {
+                    self.check_assignable(name, x.span);
+                }
+            },
+            Exp::MatchExp(x) => {
+                self.resolve_exp(&x.subject);
+                self.check_reachable(&x.arms);
+                for arm in &x.arms {
+                    self.in_scope(pattern_bindings(&arm.pattern), |this| {
+                        if let Some(guard) = &arm.guard {
+                            this.resolve_exp(guard);
+                        }
+                        this.resolve_exp(&arm.body);
+                    });
+                }
+            },
+        }
+    }
+
+    fn resolve_target(&mut self, target: &AssignTarget, span: Span) {
+        match target {
+            AssignTarget::Variable(name) => self.check_assignable(name, span),
+            AssignTarget::Index { object, index } => {
+                self.resolve_exp(object);
+                self.resolve_exp(index);
+            },
+        }
+    }
+
+    /// Warns about arms which can never run, because an earlier arm without a guard matches
+    /// everything they do.
+    fn check_reachable<T>(&mut self, arms: &[MatchArm<T>]) {
+        for (position, arm) in arms.iter().enumerate() {
+            let unreachable = arms[..position].iter()
+                .any(|earlier| earlier.guard.is_none() && covers(&earlier.pattern, &arm.pattern));
+            if unreachable {
+                self.warnings.push(format!("Unreachable match arm, line: {}", arm.span.line));
+            }
+        }
+    }
+}
+
+/// The names a pattern binds.
+fn pattern_bindings(pattern: &Pattern) -> Vec<String> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Literal(_) | Pattern::Variant(..) => vec![],
+        Pattern::Binding(name) => vec![name.0.clone()],
+        Pattern::List(elements) => elements.iter().flat_map(pattern_bindings).collect(),
+        Pattern::Map(entries) => entries.iter().flat_map(|(_, value)| pattern_bindings(value)).collect(),
+    }
+}
+
+/// Whether every value matching `later` also matches `earlier`.
+fn covers(earlier: &Pattern, later: &Pattern) -> bool {
+    match (earlier, later) {
+        (Pattern::Wildcard, _) | (Pattern::Binding(_), _) => true,
+        (Pattern::Literal(earlier), Pattern::Literal(later)) => earlier == later,
+        (Pattern::Variant(..), Pattern::Variant(..)) => earlier == later,
+        (Pattern::List(earlier), Pattern::List(later)) =>
+            earlier.len() == later.len() && earlier.iter().zip(later).all(|(earlier, later)| covers(earlier, later)),
+        // A map pattern ignores keys it doesn't mention, so `later` must mention at least the same keys.
+        (Pattern::Map(earlier), Pattern::Map(later)) => earlier.iter().all(|(key, earlier)| {
+            later.iter().any(|(later_key, later)| key == later_key && covers(earlier, later))
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::Parser;
+    use scanner::Scanner;
+
+    fn resolved(source: &str) -> Resolver {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().expect("source should parse");
+        let mut resolver = Resolver::new();
+        resolver.resolve(&stmts);
+        resolver
+    }
+
+    fn warnings(source: &str) -> Vec<String> {
+        resolved(source).warnings
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        resolved(source).errors
+    }
+
+    #[test]
+    fn arms_after_a_catch_all_are_unreachable()
+    {
+        assert_eq!(warnings("match (x) {\n 1 => print 1;\n n => print n;\n 2 => print 2;\n _ => print 3;\n}"),
+                   vec!["Unreachable match arm, line: 3".to_string(), "Unreachable match arm, line: 4".to_string()]);
+    }
+
+    #[test]
+    fn arms_covered_by_earlier_patterns_are_unreachable()
+    {
+        assert_eq!(warnings("print match (x) {\n [a, 1] => 1,\n [2, 1] => 2,\n {\"k\": _} => 3,\n {\"k\": 1, \"j\": 2} => 4,\n 1 => 5,\n 1 => 6\n};"),
+                   vec!["Unreachable match arm, line: 2".to_string(),
+                        "Unreachable match arm, line: 4".to_string(),
+                        "Unreachable match arm, line: 6".to_string()]);
+    }
+
+    #[test]
+    fn repeated_variant_arms_are_unreachable()
+    {
+        assert_eq!(warnings("enum Color { Red, Green }\n match (c) {\n Color.Red => {}\n Color.Green => {}\n Color.Red => {}\n}"),
+                   vec!["Unreachable match arm, line: 4".to_string()]);
+    }
+
+    #[test]
+    fn guarded_and_distinct_arms_are_reachable()
+    {
+        assert!(warnings("fun f(x) { return match (x) { n if n > 0 => 1, [a] => 2, [a, b] => 3, {\"j\": 1} => 4, {\"k\": 1} => 5, _ => 6 }; }").is_empty());
+    }
+
+    #[test]
+    fn assigning_to_a_known_constant_is_an_error()
+    {
+        assert_eq!(errors("const a = 1;\n a = 2;\n fun f() { a += 1; }\n { a++; }\n const a = 3;"),
+                   vec!["Can't assign to constant 'a', line: 1".to_string(),
+                        "Can't assign to constant 'a', line: 2".to_string(),
+                        "Can't assign to constant 'a', line: 3".to_string(),
+                        "Can't redeclare constant 'a', line: 4".to_string()]);
+    }
+
+    #[test]
+    fn destructuring_declares_and_assigns_every_name()
+    {
+        assert_eq!(errors("const [a, {b}] = x;\n [c, b] = y;\n var [a] = z;"),
+                   vec!["Can't assign to constant 'b', line: 1".to_string(),
+                        "Can't redeclare constant 'a', line: 2".to_string()]);
+    }
+
+    #[test]
+    fn shadowing_constants_and_assigning_unknown_names_is_allowed()
+    {
+        assert!(errors("const a = 1; { var a = 2; a = 3; } fun f(a) { a = 4; } match (1) { a => a = 5; }
+                        try { } catch (a) { a = 6; } fun g() { b = 7; } var c = 8; c = 9;").is_empty());
+    }
+}
@@ -11,10 +11,11 @@ use itertools::{Itertools, PeekingNext};
 pub enum Literal {
     IDENTIFIER(String),
     STRING(String),
-    NUMBER(i64)
+    NUMBER(i64),
+    BOOLEAN(bool),
+    NIL
 }
 
-// TODO: TokenTypes should be split out into Unary, Binary etc for type safety
 #[allow(dead_code)]
 #[derive(Clone)]
 #[derive(Debug)]
@@ -24,6 +25,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     COMMA,
     DOT,
     MINUS,
@@ -31,6 +34,13 @@ pub enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
+    AMPERSAND,
+    PIPE,
+    CARET,
+    TILDE,
+    QUESTION,
+    COLON,
 
     // One or two character tokens.
     BANG,
@@ -41,6 +51,23 @@ pub enum TokenType {
     GreaterEqual,
     LESS,
     LessEqual,
+    StarStar,
+    TildeSlash,
+    LessLess,
+    GreaterGreater,
+    QuestionQuestion,
+    QuestionDot,
+    DotDot,
+    DotDotEqual,
+    DotDotDot,
+    FatArrow,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    PlusPlus,
+    MinusMinus,
 
     // Literals.
     Literal(Literal),
@@ -62,6 +89,20 @@ pub enum TokenType {
     TRUE,
     VAR,
     WHILE,
+    BREAK,
+    CONTINUE,
+    MATCH,
+    IMPORT,
+    EXPORT,
+    AS,
+    THROW,
+    TRY,
+    CATCH,
+    FINALLY,
+    CONST,
+    YIELD,
+    IN,
+    ENUM,
 
     EOF
 }
@@ -104,7 +145,7 @@ impl Scanner {
     pub fn new(source: String) -> Scanner
     {
         Scanner {
-            source: source,
+            source,
             tokens: Vec::new(),
             line: 0,
             errors: Vec::new()
@@ -124,13 +165,13 @@ impl Scanner {
             remaining_chars = source_iter.clone().count();
         }
 
-        return &self.tokens;
+        &self.tokens
     }
 
     fn scan_token(&mut self, remaining_source: &mut Peekable<Chars>) -> Option<Token>
     {
         // TODO: Refactor this in terms of peek_next
-        let next_char = remaining_source.peek().expect("Have asserted that char is there").clone();
+        let next_char = *remaining_source.peek().expect("Have asserted that char is there");
         let token_match = match next_char {
             '(' => {
                 remaining_source.next();
@@ -148,21 +189,49 @@ impl Scanner {
                 remaining_source.next();
                 Some(TokenType::RightBrace)
             },
+            '[' => {
+                remaining_source.next();
+                Some(TokenType::LeftBracket)
+            },
+            ']' => {
+                remaining_source.next();
+                Some(TokenType::RightBracket)
+            },
             ',' => {
                 remaining_source.next();
                 Some(TokenType::COMMA)
             },
             '.' => {
                 remaining_source.next();
-                Some(TokenType::DOT)
+                if !next_is(remaining_source, '.') {
+                    Some(TokenType::DOT)
+                } else if next_is(remaining_source, '=') {
+                    Some(TokenType::DotDotEqual)
+                } else if next_is(remaining_source, '.') {
+                    Some(TokenType::DotDotDot)
+                } else {
+                    Some(TokenType::DotDot)
+                }
             },
             '-' => {
                 remaining_source.next();
-                Some(TokenType::MINUS)
+                if next_is(remaining_source, '-') {
+                    Some(TokenType::MinusMinus)
+                } else if next_is(remaining_source, '=') {
+                    Some(TokenType::MinusEqual)
+                } else {
+                    Some(TokenType::MINUS)
+                }
             },
             '+' => {
                 remaining_source.next();
-                Some(TokenType::PLUS)
+                if next_is(remaining_source, '+') {
+                    Some(TokenType::PlusPlus)
+                } else if next_is(remaining_source, '=') {
+                    Some(TokenType::PlusEqual)
+                } else {
+                    Some(TokenType::PLUS)
+                }
             },
             ';' => {
                 remaining_source.next();
@@ -170,35 +239,96 @@ impl Scanner {
             },
             '*' => {
                 remaining_source.next();
-                Some(TokenType::STAR)
+                if next_is(remaining_source, '*') {
+                    Some(TokenType::StarStar)
+                } else if next_is(remaining_source, '=') {
+                    Some(TokenType::StarEqual)
+                } else {
+                    Some(TokenType::STAR)
+                }
+            },
+            '%' => {
+                remaining_source.next();
+                if next_is(remaining_source, '=') { Some(TokenType::PercentEqual) } else { Some(TokenType::PERCENT) }
+            },
+            '~' => {
+                remaining_source.next();
+                if next_is(remaining_source, '/') { Some(TokenType::TildeSlash) } else { Some(TokenType::TILDE) }
+            },
+            '&' => {
+                remaining_source.next();
+                Some(TokenType::AMPERSAND)
+            },
+            '|' => {
+                remaining_source.next();
+                Some(TokenType::PIPE)
+            },
+            '^' => {
+                remaining_source.next();
+                Some(TokenType::CARET)
+            },
+            '?' => {
+                remaining_source.next();
+                if next_is(remaining_source, '?') {
+                    Some(TokenType::QuestionQuestion)
+                } else if next_is(remaining_source, '.') {
+                    Some(TokenType::QuestionDot)
+                } else {
+                    Some(TokenType::QUESTION)
+                }
+            },
+            ':' => {
+                remaining_source.next();
+                Some(TokenType::COLON)
             },
             '!' => {
                 remaining_source.next();
-                if remaining_source.next().expect("Have asserted that char is there") == '='
-                { Some(TokenType::BangEqual) } else { Some(TokenType::BANG) }
+                if next_is(remaining_source, '=') { Some(TokenType::BangEqual) } else { Some(TokenType::BANG) }
             },
             '=' => {
                 remaining_source.next();
-                if remaining_source.next().expect("Have asserted that char is there") == '='
-                { Some(TokenType::EqualEqual) } else { Some(TokenType::EQUAL) }
+                if next_is(remaining_source, '=') {
+                    Some(TokenType::EqualEqual)
+                } else if next_is(remaining_source, '>') {
+                    Some(TokenType::FatArrow)
+                } else {
+                    Some(TokenType::EQUAL)
+                }
             },
             '<' => {
                 remaining_source.next();
-                if remaining_source.next().expect("Have asserted that char is there") == '='
-                { Some(TokenType::LessEqual) } else { Some(TokenType::LESS) }
+                if next_is(remaining_source, '=') {
+                    Some(TokenType::LessEqual)
+                } else if next_is(remaining_source, '<') {
+                    Some(TokenType::LessLess)
+                } else {
+                    Some(TokenType::LESS)
+                }
             },
             '>' => {
                 remaining_source.next();
-                if remaining_source.next().expect("Have asserted that char is there") == '='
-                { Some(TokenType::GreaterEqual) } else { Some(TokenType::GREATER) }
+                if next_is(remaining_source, '=') {
+                    Some(TokenType::GreaterEqual)
+                } else if next_is(remaining_source, '>') {
+                    Some(TokenType::GreaterGreater)
+                } else {
+                    Some(TokenType::GREATER)
+                }
             },
             '/' => {
                 remaining_source.next();
-                if remaining_source.next().expect("Have asserted that char is there") == '/'
+                if next_is(remaining_source, '/')
                 {
-                    remaining_source.skip_while(|x| *x != '\n').next();
+                    // Leave the newline itself to be counted as one.
+                    while remaining_source.peek().is_some_and(|x| *x != '\n') {
+                        remaining_source.next();
+                    }
                     None
-                } else { Some(TokenType::SLASH) }
+                } else if next_is(remaining_source, '=') {
+                    Some(TokenType::SlashEqual)
+                } else {
+                    Some(TokenType::SLASH)
+                }
             }
             ' ' => {
                 remaining_source.next();
@@ -223,7 +353,7 @@ impl Scanner {
                 remaining_source.next();
                 self.scan_string(remaining_source)
             },
-            character @ '0'..='9' => {
+            '0'..='9' => {
                 self.scan_number(remaining_source)
             },
             _ => {
@@ -244,7 +374,7 @@ impl Scanner {
                 remaining_source.take_while(|x| *x != '"');
             string = String::from_iter(string_iter);
         }
-        if string.len() == 0 {
+        if string.is_empty() {
             self.errors.push(format!("Unterminated string starting on line {}", self.line));
             return None;
         }
@@ -256,35 +386,39 @@ impl Scanner {
 
     fn scan_number(&mut self, remaining_source: &mut Peekable<Chars>) -> Option<TokenType>
     {
-        {
-            let string_iter =
-                remaining_source.peeking_take_while(|x| x.is_digit(10) || x.eq(&'.'));
-            let string: String = string_iter.collect();
-            if string.ends_with('.') {
-                self.errors.push(format!("Number not permitted to end with '.' on line {}", self.line));
-                return None;
-            }
-            match string.parse::<i64>() {
-                Ok(result) => return Some(TokenType::Literal(Literal::NUMBER(result))),
-                Err(_) => {
-                    self.errors.push(format!("Unable to parse number on line {}", self.line));
-                    return None
-                }
+        let mut string: String = remaining_source.peeking_take_while(|x| x.is_ascii_digit()).collect();
+        // Two dots after a number start a range rather than continuing the number.
+        let mut lookahead = remaining_source.clone();
+        if lookahead.next() == Some('.') && lookahead.peek() != Some(&'.') {
+            string.extend(remaining_source.peeking_take_while(|x| x.is_ascii_digit() || x.eq(&'.')));
+        }
+        if string.ends_with('.') {
+            self.errors.push(format!("Number not permitted to end with '.' on line {}", self.line));
+            return None;
+        }
+        match string.parse::<i64>() {
+            Ok(result) => Some(TokenType::Literal(Literal::NUMBER(result))),
+            Err(_) => {
+                self.errors.push(format!("Unable to parse number on line {}", self.line));
+                None
             }
         }
-
-        self.errors.push(format!("Unknown error parsing number on line {}", self.line));
-        None
     }
 
     fn scan_keyword_or_identifier<I>(&mut self, remaining_source: &mut I) -> Option<TokenType>
         where I: PeekingNext<Item=char>
     {
         let string_iter =
-            remaining_source.peeking_take_while(|x| x.is_alphanumeric());
+            remaining_source.peeking_take_while(|x| x.is_alphanumeric() || *x == '_');
         let string: String = string_iter.collect();
 
         match string.as_str() {
+            // Not the start of any token, so skip it rather than scanning it again forever.
+            "" => {
+                let unexpected = remaining_source.next().expect("Have asserted that char is there");
+                self.errors.push(format!("Unexpected character '{}' on line {}", unexpected, self.line));
+                None
+            },
             "and" => Some(TokenType::AND),
             "class" => Some(TokenType::CLASS),
             "else" => Some(TokenType::ELSE),
@@ -301,11 +435,30 @@ impl Scanner {
             "true" => Some(TokenType::TRUE),
             "var" => Some(TokenType::VAR),
             "while" => Some(TokenType::WHILE),
+            "break" => Some(TokenType::BREAK),
+            "continue" => Some(TokenType::CONTINUE),
+            "match" => Some(TokenType::MATCH),
+            "import" => Some(TokenType::IMPORT),
+            "export" => Some(TokenType::EXPORT),
+            "as" => Some(TokenType::AS),
+            "throw" => Some(TokenType::THROW),
+            "try" => Some(TokenType::TRY),
+            "catch" => Some(TokenType::CATCH),
+            "finally" => Some(TokenType::FINALLY),
+            "const" => Some(TokenType::CONST),
+            "yield" => Some(TokenType::YIELD),
+            "in" => Some(TokenType::IN),
+            "enum" => Some(TokenType::ENUM),
             identifier => Some(TokenType::Literal(Literal::IDENTIFIER(identifier.to_string())))
         }
     }
 }
 
+/// Consumes the next char if it's `expected`, for tokens which may continue past their first char.
+fn next_is(remaining_source: &mut Peekable<Chars>, expected: char) -> bool {
+    remaining_source.next_if_eq(&expected).is_some()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -356,4 +509,62 @@ mod tests {
         ];
         assert_eq!(tokens, &expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn operators_only_consume_the_chars_they_use()
+    {
+        let mut scanner = Scanner::new("!a<b>=2**3~/4%5".to_string());
+        let token_types: Vec<TokenType> = scanner.scan_tokens().iter().map(|token| token.token_type.clone()).collect();
+
+        assert_eq!(token_types, vec![
+            TokenType::BANG,
+            TokenType::Literal(Literal::IDENTIFIER("a".to_string())),
+            TokenType::LESS,
+            TokenType::Literal(Literal::IDENTIFIER("b".to_string())),
+            TokenType::GreaterEqual,
+            TokenType::Literal(Literal::NUMBER(2)),
+            TokenType::StarStar,
+            TokenType::Literal(Literal::NUMBER(3)),
+            TokenType::TildeSlash,
+            TokenType::Literal(Literal::NUMBER(4)),
+            TokenType::PERCENT,
+            TokenType::Literal(Literal::NUMBER(5)),
+        ]);
+    }
+
+    #[test]
+    fn two_dots_after_a_number_start_a_range()
+    {
+        let mut scanner = Scanner::new("0..10 1..=n a.b ...xs".to_string());
+        let token_types: Vec<TokenType> = scanner.scan_tokens().iter().map(|token| token.token_type.clone()).collect();
+
+        assert_eq!(token_types, vec![
+            TokenType::Literal(Literal::NUMBER(0)),
+            TokenType::DotDot,
+            TokenType::Literal(Literal::NUMBER(10)),
+            TokenType::Literal(Literal::NUMBER(1)),
+            TokenType::DotDotEqual,
+            TokenType::Literal(Literal::IDENTIFIER("n".to_string())),
+            TokenType::Literal(Literal::IDENTIFIER("a".to_string())),
+            TokenType::DOT,
+            TokenType::Literal(Literal::IDENTIFIER("b".to_string())),
+            TokenType::DotDotDot,
+            TokenType::Literal(Literal::IDENTIFIER("xs".to_string())),
+        ]);
+        let mut scanner = Scanner::new("1.".to_string());
+        scanner.scan_tokens();
+        assert_eq!(scanner.errors, vec!["Number not permitted to end with '.' on line 0".to_string()]);
+    }
+
+    #[test]
+    fn comments_end_at_the_newline_which_is_still_counted()
+    {
+        let mut scanner = Scanner::new("a // first\nb // second\n\nc".to_string());
+        let lines: Vec<usize> = scanner.scan_tokens().iter()
+            .filter(|token| token.token_type != TokenType::EOF)
+            .map(|token| token.line)
+            .collect();
+
+        assert_eq!(lines, vec![0, 1, 3]);
+    }
+}
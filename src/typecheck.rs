@@ -0,0 +1,719 @@
+use ast::{NodeMap, Stmt, Exp, Element, Function, TypeAnnotation, BinaryOp, UnaryOp, LogicalOp, AssignTarget, Identifier, Pattern, Span};
+use scanner::Literal;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// What's known statically about the values an expression can produce. Nothing is known about
+/// unannotated code, which gets `Any` and so is never reported.
+#[derive(Eq, PartialEq)]
+#[derive(Clone)]
+#[derive(Debug)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    Number,
+    String,
+    List,
+    Map,
+    /// A function, with its signature when the checker has seen where it was declared.
+    Function(Option<Rc<Signature>>),
+}
+
+#[derive(Eq, PartialEq)]
+#[derive(Debug)]
+pub struct Signature {
+    pub names: Vec<String>,
+    pub params: Vec<Type>,
+    /// How many of the parameters have no default, and so must be passed.
+    pub required: usize,
+    /// Whether the last of `params` is a rest parameter, which any number of arguments can fill.
+    pub rest: bool,
+    pub result: Type,
+}
+
+impl Type {
+    fn from_annotation(annotation: &Option<TypeAnnotation>) -> Type {
+        match annotation {
+            None | Some(TypeAnnotation::Any) => Type::Any,
+            Some(TypeAnnotation::Nil) => Type::Nil,
+            Some(TypeAnnotation::Bool) => Type::Bool,
+            Some(TypeAnnotation::Number) => Type::Number,
+            Some(TypeAnnotation::String) => Type::String,
+            Some(TypeAnnotation::List) => Type::List,
+            Some(TypeAnnotation::Map) => Type::Map,
+            Some(TypeAnnotation::Function) => Type::Function(None),
+        }
+    }
+
+    /// Whether a value of type `actual` can be used where this type is expected.
+    fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Function(_), Type::Function(_)) => true,
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    /// The type of a value which could have come from either branch.
+    fn join(self, other: Type) -> Type {
+        if self == other { self } else { Type::Any }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Function(_) => write!(f, "Function"),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// A variable's `declared` type limits what can be assigned to it, whereas its `known` type is what
+/// reading it produces. They only differ for functions, whose signature is known from their
+/// declaration even though the variable holding them can be reassigned to anything.
+struct Binding {
+    declared: Type,
+    known: Type,
+}
+
+/// Checks annotated code before it runs, inferring the types of expressions from literals,
+/// annotations and `const` initializers. Values that don't match an annotation are errors, but
+/// operations which would fail at runtime are only warnings, as they may never run or be caught.
+pub struct TypeChecker {
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+    /// The type inferred for each expression checked, so later passes can look it up by node.
+    pub types: NodeMap<Type>,
+    /// The variables declared so far in each enclosing scope, outermost first.
+    scopes: Vec<HashMap<String, Binding>>,
+    /// The return type of each function being checked, innermost last.
+    return_types: Vec<Type>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> TypeChecker {
+        TypeChecker::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        TypeChecker {
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            types: NodeMap::new(),
+            scopes: vec![HashMap::new()],
+            return_types: Vec::new(),
+        }
+    }
+
+    pub fn check(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_scope(&mut self, names: Vec<(String, Type)>, stmts: &[Stmt]) {
+        self.in_scope(names, |this| this.check(stmts));
+    }
+
+    fn in_scope<R>(&mut self, names: Vec<(String, Type)>, f: impl FnOnce(&mut TypeChecker) -> R) -> R {
+        let scope = names.into_iter()
+            .map(|(name, declared)| (name, Binding { known: declared.clone(), declared }))
+            .collect();
+        self.scopes.push(scope);
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn declare(&mut self, name: &Identifier, declared: Type, known: Type) {
+        self.scopes.last_mut().expect("the global scope is never popped")
+            .insert(name.0.clone(), Binding { declared, known });
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::VarDecl(decl) => {
+                let actual = match &decl.exp {
+                    Some(exp) => self.check_exp(exp),
+                    None => Type::Nil,
+                };
+                let name = match &decl.pattern {
+                    Pattern::Binding(name) => name,
+                    pattern => {
+                        self.check_destructurable(pattern, &actual, decl.span);
+                        for name in pattern.bindings() {
+                            self.declare(name, Type::Any, Type::Any);
+                        }
+                        return
+                    },
+                };
+                // An unannotated `var` can be reassigned to anything, but a `const` will always
+                // hold its initial value.
+                let declared = match (&decl.annotation, decl.constant) {
+                    (None, true) => actual.clone(),
+                    (annotation, _) => Type::from_annotation(annotation),
+                };
+                if !declared.accepts(&actual) {
+                    self.errors.push(format!("Expected {} for '{}' but got {}, line: {}",
+                                             declared, name.0, actual, decl.span.line));
+                }
+                let known = match (&declared, &actual) {
+                    (Type::Function(None), Type::Function(_)) => actual,
+                    _ => declared.clone(),
+                };
+                self.declare(name, declared, known);
+            },
+            Stmt::Statement(stmt) => { self.check_exp(&stmt.exp); },
+            Stmt::PrintStmt(stmt) => { self.check_exp(&stmt.exp); },
+            Stmt::Block(block) => self.check_scope(vec![], &block.stmts),
+            Stmt::FunDecl(decl) => {
+                // Declared before the body is checked, so recursive calls are checked too.
+                self.declare(&decl.name, Type::Any, Type::Function(signature(&decl.function)));
+                self.check_function(&decl.function);
+            },
+            Stmt::Return(stmt) => {
+                let actual = match &stmt.exp {
+                    Some(exp) => self.check_exp(exp),
+                    None => Type::Nil,
+                };
+                let expected = self.return_types.last().cloned().unwrap_or(Type::Any);
+                if !expected.accepts(&actual) {
+                    self.errors.push(format!("Expected a {} return value but got {}, line: {}",
+                                             expected, actual, stmt.span.line));
+                }
+            },
+            Stmt::Yield(stmt) => {
+                if let Some(exp) = &stmt.exp {
+                    self.check_exp(exp);
+                }
+            },
+            Stmt::If(stmt) => {
+                self.check_exp(&stmt.condition);
+                self.check_stmt(&stmt.then_branch);
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.check_stmt(else_branch);
+                }
+            },
+            Stmt::While(stmt) => {
+                self.check_exp(&stmt.condition);
+                self.check_stmt(&stmt.body);
+                if let Some(increment) = &stmt.increment {
+                    self.check_exp(increment);
+                }
+            },
+            Stmt::ForIn(stmt) => {
+                let iterable = self.check_exp(&stmt.iterable);
+                self.check_iterable(iterable, stmt.span);
+                self.in_scope(vec![(stmt.name.0.clone(), Type::Any)], |this| this.check_stmt(&stmt.body));
+            },
+            Stmt::Break(_) | Stmt::Continue(_) => {},
+            Stmt::Match(stmt) => {
+                self.check_exp(&stmt.subject);
+                for arm in &stmt.arms {
+                    self.in_scope(pattern_bindings(&arm.pattern), |this| {
+                        if let Some(guard) = &arm.guard {
+                            this.check_exp(guard);
+                        }
+                        this.check_stmt(&arm.body);
+                    });
+                }
+            },
+            Stmt::Import(stmt) => self.declare(&stmt.name, Type::Any, Type::Any),
+            Stmt::Enum(decl) => self.declare(&decl.name, Type::Any, Type::Any),
+            Stmt::Export(stmt) => self.check_stmt(&stmt.decl),
+            Stmt::Throw(stmt) => { self.check_exp(&stmt.exp); },
+            Stmt::Try(stmt) => {
+                self.check_scope(vec![], &stmt.body);
+                if let Some(catch) = &stmt.catch {
+                    self.check_scope(vec![(catch.name.0.clone(), Type::Any)], &catch.body);
+                }
+                if let Some(finally) = &stmt.finally {
+                    self.check_scope(vec![], finally);
+                }
+            },
+        }
+    }
+
+    fn check_function(&mut self, function: &Function) {
+        let mut params: Vec<(String, Type)> = function.params.iter().zip(&function.param_types)
+            .map(|(param, annotation)| (param.0.clone(), Type::from_annotation(annotation)))
+            .collect();
+        if function.rest {
+            if let Some((_, rest)) = params.last_mut() {
+                *rest = Type::List;
+            }
+        }
+        self.return_types.push(Type::from_annotation(&function.return_type));
+        self.in_scope(params, |this| {
+            for ((param, annotation), default) in function.params.iter().zip(&function.param_types).zip(&function.defaults) {
+                if let Some(default) = default {
+                    let declared = Type::from_annotation(annotation);
+                    let actual = this.check_exp(default);
+                    if !declared.accepts(&actual) {
+                        this.errors.push(format!("Expected {} for '{}' but got {}, line: {}",
+                                                 declared, param.0, actual, function.span.line));
+                    }
+                }
+            }
+            this.check(&function.body);
+        });
+        self.return_types.pop();
+    }
+
+    fn check_exp(&mut self, exp: &Exp) -> Type {
+        let inferred = self.infer_exp(exp);
+        self.types.insert(exp.id(), inferred.clone());
+        inferred
+    }
+
+    fn infer_exp(&mut self, exp: &Exp) -> Type {
+        match exp {
+            Exp::LiteralExp(x) => match &x.value {
+                Literal::NUMBER(_) => Type::Number,
+                Literal::STRING(_) => Type::String,
+                Literal::BOOLEAN(_) => Type::Bool,
+                Literal::NIL => Type::Nil,
+                Literal::IDENTIFIER(name) => self.lookup(name).map_or(Type::Any, |binding| binding.known.clone()),
+            },
+            Exp::GroupingExp(x) => self.check_exp(&x.exp),
+            Exp::UnaryExp(x) => {
+                let right = self.check_exp(&x.right);
+                match x.operator {
+                    UnaryOp::Bang => Type::Bool,
+                    UnaryOp::Minus | UnaryOp::Tilde => {
+                        if !Type::Number.accepts(&right) {
+                            self.warnings.push(format!("Operator {} needs a Number operand, not {}, line: {}",
+                                                     x.operator, right, x.span.line));
+                        }
+                        Type::Number
+                    },
+                }
+            },
+            Exp::BinaryExp(x) => {
+                let left = self.check_exp(&x.left);
+                let right = self.check_exp(&x.right);
+                self.binary_type(x.operator, left, right, x.span)
+            },
+            Exp::LogicalExp(x) => {
+                let left = self.check_exp(&x.left);
+                let right = self.check_exp(&x.right);
+                match x.operator {
+                    LogicalOp::And | LogicalOp::Or => left.join(right),
+                    LogicalOp::QuestionQuestion if left == Type::Nil => right,
+                    LogicalOp::QuestionQuestion if left == Type::Any => Type::Any,
+                    LogicalOp::QuestionQuestion => left,
+                }
+            },
+            Exp::ConditionalExp(x) => {
+                self.check_exp(&x.condition);
+                let then_type = self.check_exp(&x.then_branch);
+                let else_type = self.check_exp(&x.else_branch);
+                then_type.join(else_type)
+            },
+            Exp::CommaExp(x) => {
+                self.check_exp(&x.left);
+                self.check_exp(&x.right)
+            },
+            Exp::CallExp(x) => {
+                let callee = self.check_exp(&x.callee);
+                let (arguments, spread) = self.check_elements(&x.arguments, x.span);
+                let named = x.named.iter().map(|(name, argument)| (name, self.check_exp(argument))).collect();
+                self.call_type(callee, arguments, named, spread, x.span)
+            },
+            Exp::LambdaExp(x) => {
+                self.check_function(&x.function);
+                Type::Function(signature(&x.function))
+            },
+            Exp::AssignExp(x) => {
+                let value = self.check_exp(&x.value);
+                self.check_assign(&x.name, &value, x.span);
+                value
+            },
+            Exp::ListExp(x) => {
+                self.check_elements(&x.elements, x.span);
+                Type::List
+            },
+            Exp::MapExp(x) => {
+                for (key, value) in &x.entries {
+                    self.check_exp(key);
+                    self.check_exp(value);
+                }
+                Type::Map
+            },
+            Exp::IndexExp(x) => {
+                let object = self.check_exp(&x.object);
+                self.check_exp(&x.index);
+                self.check_indexable(&object, x.span);
+                Type::Any
+            },
+            Exp::SetIndexExp(x) => {
+                let object = self.check_exp(&x.object);
+                self.check_exp(&x.index);
+                self.check_indexable(&object, x.span);
+                self.check_exp(&x.value)
+            },
+            Exp::GetExp(x) => {
+                self.check_exp(&x.object);
+                Type::Any
+            },
+            Exp::CompoundAssignExp(x) => {
+                let current = self.check_target(&x.target, x.span);
+                let value = self.check_exp(&x.value);
+                let updated = self.binary_type(x.operator, current, value, x.span);
+                if let AssignTarget::Variable(name) = &x.target {
+                    self.check_assign(name, &updated, x.span);
+                }
+                updated
+            },
+            Exp::IncrementExp(x) => {
+                let current = self.check_target(&x.target, x.span);
+                self.binary_type(x.operator, current, Type::Number, x.span)
+            },
+            Exp::DestructureExp(x) => {
+                let value = self.check_exp(&x.value);
+                self.check_destructurable(&x.pattern, &value, x.span);
+                for name in x.pattern.bindings() {
+                    self.check_assign(name, &Type::Any, x.span);
+                }
+                value
+            },
+            Exp::MatchExp(x) => {
+                self.check_exp(&x.subject);
+                let mut result: Option<Type> = None;
+                for arm in &x.arms {
+                    let body = self.in_scope(pattern_bindings(&arm.pattern), |this| {
+                        if let Some(guard) = &arm.guard {
+                            this.check_exp(guard);
+                        }
+                        this.check_exp(&arm.body)
+                    });
+                    result = Some(match result {
+                        None => body,
+                        Some(result) => result.join(body),
+                    });
+                }
+                result.unwrap_or(Type::Any)
+            },
+        }
+    }
+
+    fn binary_type(&mut self, operator: BinaryOp, left: Type, right: Type, span: Span) -> Type {
+        match operator {
+            BinaryOp::EqualEqual | BinaryOp::BangEqual => Type::Bool,
+            // `+` adds numbers or concatenates strings, so either operand can decide which it is.
+            BinaryOp::Plus => match (left, right) {
+                (Type::Any, Type::Any) => Type::Any,
+                (Type::Number, Type::Number) | (Type::Number, Type::Any) | (Type::Any, Type::Number) => Type::Number,
+                (Type::String, Type::String) | (Type::String, Type::Any) | (Type::Any, Type::String) => Type::String,
+                (left, right) => {
+                    self.warnings.push(format!("Operator + needs two numbers or two strings, not {} and {}, line: {}",
+                                             left, right, span.line));
+                    Type::Any
+                },
+            },
+            _ => {
+                for operand in [&left, &right].iter() {
+                    if !Type::Number.accepts(operand) {
+                        self.warnings.push(format!("Operator {} needs Number operands, not {}, line: {}",
+                                                 operator, operand, span.line));
+                        break
+                    }
+                }
+                match operator {
+                    BinaryOp::Greater | BinaryOp::GreaterEqual | BinaryOp::Less | BinaryOp::LessEqual => Type::Bool,
+                    BinaryOp::DotDot | BinaryOp::DotDotEqual => Type::Any,
+                    _ => Type::Number,
+                }
+            },
+        }
+    }
+
+    /// The type of calling `callee`. When some arguments were spread, `arguments` only holds the
+    /// ones before the first spread, and there could be any number after them.
+    fn call_type(&mut self, callee: Type, arguments: Vec<Type>, named: Vec<(&Identifier, Type)>, spread: bool, span: Span)
+                 -> Type {
+        let signature = match callee {
+            Type::Function(Some(signature)) => signature,
+            Type::Function(None) | Type::Any => return Type::Any,
+            other => {
+                self.warnings.push(format!("Can only call functions, not {}, line: {}", other, span.line));
+                return Type::Any
+            },
+        };
+        let positional = signature.params.len() - signature.rest as usize;
+        if !signature.rest && arguments.len() > positional {
+            let expected = if signature.required == positional { "" } else { "at most " };
+            self.errors.push(format!("Expected {}{} arguments but got {}, line: {}", expected, positional, arguments.len(), span.line));
+        }
+        // Any arguments beyond the positional ones are collected by the rest parameter.
+        let expected = signature.params[..positional].iter()
+            .chain(signature.params[positional..].iter().cycle());
+        for (position, (expected, actual)) in expected.zip(&arguments).enumerate() {
+            if !expected.accepts(actual) {
+                self.errors.push(format!("Expected {} for argument {} but got {}, line: {}",
+                                         expected, position + 1, actual, span.line));
+            }
+        }
+        let mut given: Vec<bool> = (0..positional).map(|position| position < arguments.len()).collect();
+        for (name, actual) in named {
+            match signature.names[..positional].iter().position(|param| *param == name.0) {
+                None => self.errors.push(format!("Unknown parameter '{}', line: {}", name.0, span.line)),
+                Some(position) if given[position] =>
+                    self.errors.push(format!("Got more than one argument for parameter '{}', line: {}", name.0, span.line)),
+                Some(position) => {
+                    given[position] = true;
+                    if !signature.params[position].accepts(&actual) {
+                        self.errors.push(format!("Expected {} for argument '{}' but got {}, line: {}",
+                                                 signature.params[position], name.0, actual, span.line));
+                    }
+                },
+            }
+        }
+        // Spread arguments could fill any of the parameters.
+        if let Some(missing) = given[..signature.required].iter().position(|given| !given).filter(|_| !spread) {
+            self.errors.push(format!("Missing argument for parameter '{}', line: {}", signature.names[missing], span.line));
+        }
+        signature.result.clone()
+    }
+
+    fn check_assign(&mut self, name: &Identifier, value: &Type, span: Span) {
+        let declared = match self.lookup(&name.0) {
+            Some(binding) => binding.declared.clone(),
+            None => return,
+        };
+        if !declared.accepts(value) {
+            self.errors.push(format!("Expected {} for '{}' but got {}, line: {}", declared, name.0, value, span.line));
+        }
+        // Whatever was known about the old value no longer holds.
+        let binding = self.scopes.iter_mut().rev()
+            .find_map(|scope| scope.get_mut(&name.0))
+            .expect("the binding was just found");
+        binding.known = declared;
+    }
+
+    /// Checks that a value of type `value` can be taken apart by a list or map pattern.
+    fn check_destructurable(&mut self, pattern: &Pattern, value: &Type, span: Span) {
+        let (expected, shape) = match pattern {
+            Pattern::List(_) => (Type::List, "list"),
+            Pattern::Map(_) => (Type::Map, "map"),
+            _ => return,
+        };
+        if !expected.accepts(value) {
+            self.warnings.push(format!("Can't destructure {} as a {}, line: {}", value, shape, span.line));
+        }
+    }
+
+    fn check_iterable(&mut self, iterable: Type, span: Span) {
+        match iterable {
+            Type::Any | Type::List | Type::Map | Type::String => {},
+            other => self.warnings.push(format!("Can only iterate over lists, maps, strings and iterators, not {}, line: {}",
+                                              other, span.line)),
+        }
+    }
+
+    /// Checks the elements of a list literal or the arguments to a call, producing the types of
+    /// those before the first spread, whose positions are known.
+    fn check_elements(&mut self, elements: &[Element], span: Span) -> (Vec<Type>, bool) {
+        let mut types = vec![];
+        let mut spread = false;
+        for element in elements {
+            let element_type = self.check_exp(element.exp());
+            match element {
+                Element::Single(_) if !spread => types.push(element_type),
+                Element::Single(_) => {},
+                Element::Spread(_) => {
+                    self.check_iterable(element_type, span);
+                    spread = true;
+                },
+            }
+        }
+        (types, spread)
+    }
+
+    /// The type of an assignment target's current value.
+    fn check_target(&mut self, target: &AssignTarget, span: Span) -> Type {
+        match target {
+            AssignTarget::Variable(name) => self.lookup(&name.0).map_or(Type::Any, |binding| binding.known.clone()),
+            AssignTarget::Index { object, index } => {
+                let object = self.check_exp(object);
+                self.check_exp(index);
+                self.check_indexable(&object, span);
+                Type::Any
+            },
+        }
+    }
+
+    fn check_indexable(&mut self, object: &Type, span: Span) {
+        match object {
+            Type::Any | Type::List | Type::Map => {},
+            other => self.warnings.push(format!("Can only index lists and maps, not {}, line: {}", other, span.line)),
+        }
+    }
+}
+
+/// The signature calls to `function` are checked against, if any of it is annotated. Calling a
+/// generator produces the generator, so its return type only applies to its `return` statements.
+fn signature(function: &Function) -> Option<Rc<Signature>> {
+    if function.return_type.is_none() && function.param_types.iter().all(Option::is_none) {
+        return None
+    }
+    let result = if function.generator { Type::Any } else { Type::from_annotation(&function.return_type) };
+    Some(Rc::new(Signature {
+        names: function.params.iter().map(|param| param.0.clone()).collect(),
+        params: function.param_types.iter().map(Type::from_annotation).collect(),
+        required: function.required(),
+        rest: function.rest,
+        result,
+    }))
+}
+
+/// The names a pattern binds, which could be bound to anything.
+fn pattern_bindings(pattern: &Pattern) -> Vec<(String, Type)> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Literal(_) | Pattern::Variant(..) => vec![],
+        Pattern::Binding(name) => vec![(name.0.clone(), Type::Any)],
+        Pattern::List(elements) => elements.iter().flat_map(pattern_bindings).collect(),
+        Pattern::Map(entries) => entries.iter().flat_map(|(_, value)| pattern_bindings(value)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::Parser;
+    use scanner::Scanner;
+
+    fn checked(source: &str) -> TypeChecker {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().expect("source should parse");
+        let mut checker = TypeChecker::new();
+        checker.check(&stmts);
+        checker
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        checked(source).errors
+    }
+
+    fn warnings(source: &str) -> Vec<String> {
+        checked(source).warnings
+    }
+
+    #[test]
+    fn unannotated_code_is_dynamic()
+    {
+        assert!(errors("var a = 1; a = \"one\"; print a + 1; fun f(x) { return x * 2; } f(\"s\"); f(1, 2, 3);").is_empty());
+    }
+
+    #[test]
+    fn annotated_declarations_and_assignments_are_checked()
+    {
+        assert_eq!(errors("var a: Number = \"one\";\n var b: String = \"b\";\n b = 2;\n b += 1;\n var c: Any = nil; c = 1;"),
+                   vec!["Expected Number for 'a' but got String, line: 0".to_string(),
+                        "Expected String for 'b' but got Number, line: 2".to_string()]);
+    }
+
+    #[test]
+    fn operations_on_inferred_types_are_warnings()
+    {
+        assert!(errors("print 1 + true; var b: String = \"b\"; b += 1; print -\"s\"; [1](); for (x in 1) {}").is_empty());
+        assert_eq!(warnings("var b: String = \"b\";\n b += 1;"),
+                   vec!["Operator + needs two numbers or two strings, not String and Number, line: 1".to_string()]);
+        assert_eq!(warnings("const n = 1;\n const s = \"s\";\n print n + s;\n print -s;\n print s < 2;\n print (n + 1)[0];\n s();"),
+                   vec!["Operator + needs two numbers or two strings, not Number and String, line: 2".to_string(),
+                        "Operator - needs a Number operand, not String, line: 3".to_string(),
+                        "Operator < needs Number operands, not String, line: 4".to_string(),
+                        "Can only index lists and maps, not Number, line: 5".to_string(),
+                        "Can only call functions, not String, line: 6".to_string()]);
+    }
+
+    #[test]
+    fn calls_and_returns_are_checked_against_signatures()
+    {
+        assert_eq!(errors("fun f(a: String, b): Bool {\n return a;\n }\n f(1, 2);\n f(\"a\");\n var ok: Bool = f(\"a\", nil);\n var bad: Number = f(\"a\", nil);\n fun g(): Number { return; }"),
+                   vec!["Expected a Bool return value but got String, line: 1".to_string(),
+                        "Expected String for argument 1 but got Number, line: 3".to_string(),
+                        "Missing argument for parameter 'b', line: 4".to_string(),
+                        "Expected Number for 'bad' but got Bool, line: 6".to_string(),
+                        "Expected a Number return value but got Nil, line: 7".to_string()]);
+    }
+
+    #[test]
+    fn only_iterable_types_are_looped_over()
+    {
+        assert_eq!(warnings("for (x in [1]) print -x;\n for (c in \"ab\") print c;\n for (x in 1) print x;\n for (x in true) {}"),
+                   vec!["Can only iterate over lists, maps, strings and iterators, not Number, line: 2".to_string(),
+                        "Can only iterate over lists, maps, strings and iterators, not Bool, line: 3".to_string()]);
+    }
+
+    #[test]
+    fn reassigned_functions_lose_their_signature()
+    {
+        assert!(errors("fun f(a: Number) {} f = fun (a: String) {}; f(\"s\"); var g: Function = fun (a: Number) {};").is_empty());
+        assert_eq!(errors("var g: Function = fun (a: Number) {}; g(\"s\");"),
+                   vec!["Expected Number for argument 1 but got String, line: 0".to_string()]);
+    }
+
+    #[test]
+    fn only_lists_and_maps_are_destructured()
+    {
+        assert_eq!(warnings("var [a, b] = [1, 2];\n var {c} = 1;\n var x: Number = 1;\n [x] = [\"s\"];\n print -x;\n [a] = \"s\";"),
+                   vec!["Can't destructure Number as a map, line: 1".to_string(),
+                        "Can't destructure String as a list, line: 5".to_string()]);
+    }
+
+    #[test]
+    fn rest_parameters_and_spread_arguments_change_arity_checks()
+    {
+        assert_eq!(errors("fun sum(first: Number, ...rest: Number): Number { print rest[0]; return first; }\n sum(1, 2, 3);\n sum();\n sum(1, \"two\");\n sum(...[1]);\n sum(...1);"),
+                   vec!["Missing argument for parameter 'first', line: 2".to_string(),
+                        "Expected Number for argument 2 but got String, line: 3".to_string()]);
+        assert_eq!(warnings("fun sum(...rest: Number) {}\n sum(...1);"),
+                   vec!["Can only iterate over lists, maps, strings and iterators, not Number, line: 1".to_string()]);
+        assert_eq!(errors("fun pair(a: Number, b) {}\n pair(1, ...[2]);\n pair(1, 2, ...[]);\n pair(1, 2, 3, ...[4]);"),
+                   vec!["Expected 2 arguments but got 3, line: 3".to_string()]);
+    }
+
+    #[test]
+    fn named_arguments_and_defaults_are_checked()
+    {
+        assert_eq!(errors("fun connect(host: String, port: Number = \"80\") {}\n connect(port: 1, host: \"h\");\n connect(\"h\", port: \"p\");\n connect(port: 1);\n connect(\"h\", 1, 2);\n connect(\"h\", timeout: 1);"),
+                   vec!["Expected Number for 'port' but got String, line: 0".to_string(),
+                        "Expected Number for argument 'port' but got String, line: 2".to_string(),
+                        "Missing argument for parameter 'host', line: 3".to_string(),
+                        "Expected at most 2 arguments but got 3, line: 4".to_string(),
+                        "Unknown parameter 'timeout', line: 5".to_string()]);
+    }
+
+    #[test]
+    fn inferred_types_are_recorded_by_node()
+    {
+        let mut scanner = Scanner::new("const n = 1; print n + 2; print \"s\";".to_string());
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().expect("source should parse");
+        let mut checker = TypeChecker::new();
+        checker.check(&stmts);
+
+        let (sum, string) = match (&stmts[1], &stmts[2]) {
+            (Stmt::PrintStmt(sum), Stmt::PrintStmt(string)) => (&sum.exp, &string.exp),
+            other => panic!("Expected print statements, got {:?}", other),
+        };
+        let left = match sum {
+            Exp::BinaryExp(binary) => binary.left.id(),
+            other => panic!("Expected a binary expression, got {:?}", other),
+        };
+        assert_eq!(checker.types.get(sum.id()), Some(&Type::Number));
+        assert_eq!(checker.types.get(left), Some(&Type::Number));
+        assert_eq!(checker.types.get(string.id()), Some(&Type::String));
+    }
+}
@@ -0,0 +1,987 @@
+extern crate loxrust;
+
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+use loxrust::scanner::Scanner;
+use loxrust::parser::Parser;
+use loxrust::interpreter::Interpreter;
+use loxrust::lower::Lowerer;
+use loxrust::analysis::analyze;
+
+#[derive(Clone)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `source` and returns everything it printed, followed by the error if execution failed.
+/// Imports are resolved relative to the working directory and then `search_path`.
+fn run(source: &str, lower: bool, search_path: &[&str]) -> String {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens();
+    let stmts = Parser::new(tokens).parse().expect("source should parse");
+
+    let output = SharedOutput(Rc::new(RefCell::new(Vec::new())));
+    let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+    for directory in search_path {
+        interpreter.add_search_path(PathBuf::from(directory));
+    }
+    let result = if lower {
+        Lowerer::new().lower(&stmts).and_then(|core| interpreter.interpret_ir(&core))
+    } else {
+        interpreter.interpret(&stmts)
+    };
+
+    let mut printed = String::from_utf8(output.0.borrow().clone()).expect("output should be utf8");
+    if let Err(err) = result {
+        printed.push_str(format!("error: {}\n", err).as_str());
+    }
+    printed
+}
+
+fn assert_output(source: &str, expected: &str) {
+    assert_output_with_search_path(source, &[], expected);
+}
+
+/// Like `assert_output`, but first runs the static passes the way the command line does, printing
+/// their warnings and only running the source if they found no errors.
+fn assert_analyzed_output(source: &str, expected: &str) {
+    let mut scanner = Scanner::new(source.to_string());
+    let stmts = Parser::new(scanner.scan_tokens()).parse().expect("source should parse");
+    let analysis = analyze(&stmts);
+    let mut printed: String = analysis.warnings.iter().map(|warning| format!("Warning: {}\n", warning)).collect();
+    if !analysis.errors.is_empty() {
+        printed.extend(analysis.errors.iter().map(|error| format!("{}\n", error)));
+        assert_eq!(printed, expected, "analyzing the AST");
+        return
+    }
+    for lower in [false, true] {
+        assert_eq!(format!("{}{}", printed, run(source, lower, &[])), expected, "lower: {}", lower);
+    }
+}
+
+fn assert_output_with_search_path(source: &str, search_path: &[&str], expected: &str) {
+    assert_eq!(run(source, false, search_path), expected, "interpreting the AST");
+    assert_eq!(run(source, true, search_path), expected, "interpreting the lowered IR");
+}
+
+#[test]
+fn arithmetic_and_comparison() {
+    assert_output(
+        "var a = 3; print a * 4 - 2; print (a + 1) / 2; print a >= 3; print \"foo\" + \"bar\";",
+        "10\n2\ntrue\nfoobar\n");
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    assert_output(
+        "var a = 1; print a or undefined; print 0 and 2; print a == 2 and undefined;",
+        "1\n2\nfalse\n");
+}
+
+#[test]
+fn runtime_errors_match() {
+    assert_output(
+        "print 1; print -\"foo\"; print 2;",
+        "1\nerror: Minus can't be used with this value: String, line: 0\n");
+}
+
+#[test]
+fn conditional_only_evaluates_selected_branch() {
+    assert_output(
+        "print false ? undefined : 2; print true ? 1 : undefined; print nil ? 1 : 2 == 2 ? 3 : 4;",
+        "2\n1\n3\n");
+}
+
+#[test]
+fn comma_produces_right_operand() {
+    assert_output(
+        "print (1, 2); print 1 == 1, \"last\";",
+        "2\nlast\n");
+}
+
+#[test]
+fn functions_and_recursion() {
+    assert_output(
+        "fun fact(n) { return n <= 1 ? 1 : n * fact(n - 1); } print fact(5); print fact;",
+        "120\n<fn fact>\n");
+}
+
+#[test]
+fn functions_close_over_their_scope() {
+    assert_output(
+        "fun makeAdder(n) { fun add(x) { return x + n; } return add; }
+         var addTwo = makeAdder(2);
+         print addTwo(3);
+         print makeAdder(10)(5);",
+        "5\n15\n");
+}
+
+#[test]
+fn lambdas_as_callbacks_and_closures() {
+    assert_output(
+        "fun apply(f, x) { return f(x); }
+         print apply(fun (a) { return a * 2; }, 21);
+         fun makeAdder(n) { return fun (x) { return x + n; }; }
+         var addTwo = makeAdder(2);
+         print addTwo(3);
+         fun () { print \"called\"; }();
+         print fun () {};",
+        "42\n5\ncalled\n<fn>\n");
+}
+
+#[test]
+fn blocks_scope_declarations() {
+    assert_output(
+        "var a = \"outer\"; { var a = \"inner\"; print a; } print a;",
+        "inner\nouter\n");
+}
+
+#[test]
+fn calls_check_arity() {
+    assert_output(
+        "fun f(a, b) {} f(1);",
+        "error: Missing argument for parameter 'b', line: 0\n");
+    assert_output("fun f(a, b) {} f(1, 2, 3);", "error: Expected 2 arguments but got 3, line: 0\n");
+}
+
+#[test]
+fn assignment_rebinds_the_innermost_variable() {
+    assert_output(
+        "var a = 1; { var a = 2; a = 3; print a; } print a; var b = 0; print b = a = 4; print a;",
+        "3\n1\n4\n4\n");
+}
+
+#[test]
+fn assigning_undefined_variable_is_an_error() {
+    assert_output(
+        "undefined = 1;",
+        "error: Undefined variable: undefined, line: 0\n");
+}
+
+#[test]
+fn if_else_and_assignment() {
+    assert_output(
+        "var a = 1; if (a == 1) a = 2; else a = 3; print a; if (a == 1) print a; else { var b = 0; print b = a = 4; } print a;",
+        "2\n4\n4\n");
+}
+
+#[test]
+fn while_and_for_loops() {
+    assert_output(
+        "var i = 0;
+         while (i < 3) { print i; i = i + 1; }
+         for (var j = 10; j > 7; j = j - 1) print j;
+         fun f() { while (true) { return \"returned\"; } }
+         print f();",
+        "0\n1\n2\n10\n9\n8\nreturned\n");
+}
+
+#[test]
+fn break_and_continue_in_while_loops() {
+    assert_output(
+        "var i = 0;
+         while (true) {
+             i = i + 1;
+             if (i == 2) continue;
+             if (i > 4) break;
+             print i;
+         }
+         print i;",
+        "1\n3\n4\n5\n");
+}
+
+#[test]
+fn continue_in_for_loop_still_runs_increment() {
+    assert_output(
+        "for (var i = 0; i < 5; i = i + 1) {
+             if (i == 1) continue;
+             if (i == 3) break;
+             print i;
+         }
+         var n = 0;
+         for (;;) { n = n + 1; if (n == 3) break; }
+         print n;",
+        "0\n2\n3\n");
+}
+
+#[test]
+fn break_only_leaves_innermost_loop() {
+    assert_output(
+        "for (var i = 0; i < 2; i = i + 1) {
+             for (var j = 0; j < 5; j = j + 1) { if (j == 1) break; print j; }
+             print i;
+         }
+         fun f() { while (true) { return \"returned\"; } }
+         print f();",
+        "0\n0\n0\n1\nreturned\n");
+}
+
+#[test]
+fn list_literals_and_indexing() {
+    assert_output(
+        "var xs = [1, \"two\", [3]]; print xs; print xs[1]; print xs[2][0];
+         xs[0] = xs[0] + 10; print xs[0];
+         var ys = xs; ys[1] = nil; print xs;
+         print [1, [2]] == [1, [2]]; print [1] == [2]; print [];",
+        "[1, \"two\", [3]]\ntwo\n3\n11\n[11, nil, [3]]\ntrue\nfalse\n[]\n");
+}
+
+#[test]
+fn list_indexes_are_bounds_checked() {
+    assert_output("var xs = [1, 2]; print xs[2];", "error: Index 2 out of bounds for list of length 2, line: 0\n");
+    assert_output("var xs = [1, 2]; xs[-1] = 0;", "error: Index -1 out of bounds for list of length 2, line: 0\n");
+    assert_output("print [1][\"0\"];", "error: List index must be a number, not String, line: 0\n");
+    assert_output("var n = 1; print n[0];", "error: Can only index lists and maps, not Number, line: 0\n");
+}
+
+#[test]
+fn list_native_methods() {
+    assert_output(
+        "var xs = [];
+         xs.push(1); xs.push(2); xs.push(3);
+         print xs.len();
+         print xs.pop(); print xs;
+         xs.insert(0, 0); xs.insert(3, 9); print xs;
+         print xs.remove(1); print xs;
+         print xs.slice(1, 3); print xs.slice(0, 0);
+         var push = xs.push; push(4); print xs;
+         print push;",
+        "3\n3\n[1, 2]\n[0, 1, 2, 9]\n1\n[0, 2, 9]\n[2, 9]\n[]\n[0, 2, 9, 4]\n<native fn push>\n");
+}
+
+#[test]
+fn list_native_method_errors() {
+    assert_output("[].pop();", "error: Can't pop from an empty list, line: 0\n");
+    assert_output("[1].insert(2, 0);", "error: Index 2 out of bounds for list of length 1, line: 0\n");
+    assert_output("[1].remove(1);", "error: Index 1 out of bounds for list of length 1, line: 0\n");
+    assert_output("[1, 2].slice(2, 1);", "error: Slice start 2 is after its end 1, line: 0\n");
+    assert_output("[].push();", "error: Expected 1 arguments but got 0, line: 0\n");
+    assert_output("[].size();", "error: Undefined property 'size' on List, line: 0\n");
+}
+
+#[test]
+fn map_literals_and_indexing() {
+    assert_output(
+        "var m = {\"b\": 1, 2: \"two\", true: [3]};
+         print m; print m[\"b\"]; print m[1 + 1]; print m[true];
+         m[\"a\"] = 4; m[\"b\"] = 5; print m;
+         var alias = m; alias[false] = nil; print m[false];
+         print {\"x\": 1, \"y\": 2} == {\"y\": 2, \"x\": 1}; print {1: 1} == {\"1\": 1}; print {};
+         { print \"block\"; }",
+        "{\"b\": 1, 2: \"two\", true: [3]}\n1\ntwo\n[3]\n{\"b\": 5, 2: \"two\", true: [3], \"a\": 4}\nnil\ntrue\nfalse\n{}\nblock\n");
+}
+
+#[test]
+fn self_referential_lists_and_maps_equal_themselves() {
+    assert_output(
+        "var m = {}; m[\"self\"] = m; print m == m;
+         var xs = []; xs.push(xs); print xs == xs;",
+        "true\ntrue\n");
+}
+
+#[test]
+fn self_referential_lists_and_maps_print_a_placeholder() {
+    assert_output(
+        "var a = [1]; a.push(a); print a;
+         var m = {\"n\": 1}; m[\"self\"] = m; print m;
+         var outer = [m, [m]]; print outer;
+         var b = [2]; a.push(b); b.push(a); print b;",
+        "[1, [...]]\n{\"n\": 1, \"self\": {...}}\n[{\"n\": 1, \"self\": {...}}, [{\"n\": 1, \"self\": {...}}]]\n\
+         [2, [1, [...], [...]]]\n");
+}
+
+#[test]
+fn distinct_cyclic_lists_and_maps_compare_by_content() {
+    assert_output(
+        "var a = [1]; a.push(a); var b = [1]; b.push(b); print a == b;
+         var c = [2]; c.push(c); print a == c;
+         var m = {\"n\": 1}; m[\"self\"] = m; var n = {\"n\": 1}; n[\"self\"] = n; print m == n;
+         var o = {\"n\": 2}; o[\"self\"] = o; print m != o;",
+        "true\nfalse\ntrue\ntrue\n");
+}
+
+#[test]
+fn map_keys_are_checked() {
+    assert_output("print {\"a\": 1}[\"b\"];", "error: Key \"b\" not found in map, line: 0\n");
+    assert_output("var m = {}; m[[1]] = 1;", "error: Map keys must be strings, numbers or booleans, not List, line: 0\n");
+    assert_output("print {nil: 1};", "error: Map keys must be strings, numbers or booleans, not Nil, line: 0\n");
+}
+
+#[test]
+fn map_native_methods() {
+    assert_output(
+        "var m = {\"a\": 1, \"b\": 2, \"c\": 3};
+         print m.keys(); print m.values();
+         print m.has(\"b\"); print m.delete(\"b\"); print m.delete(\"b\"); print m.has(\"b\");
+         m[\"b\"] = 4; print m.keys(); print m.len();",
+        "[\"a\", \"b\", \"c\"]\n[1, 2, 3]\ntrue\ntrue\nfalse\nfalse\n[\"a\", \"c\", \"b\"]\n3\n");
+}
+
+#[test]
+fn modulo_takes_the_sign_of_the_divisor() {
+    assert_output(
+        "print 7 % 3; print -7 % 3; print 7 % -3; print -7 % -3; print 6 % 3;",
+        "1\n2\n-2\n-1\n0\n");
+}
+
+#[test]
+fn integer_division_rounds_down_where_slash_truncates() {
+    assert_output(
+        "print 7 ~/ 2; print -7 ~/ 2; print -7 / 2; print 7 ~/ -2; print -6 ~/ 2;
+         var a = -7; var b = 3; print a ~/ b * b + a % b == a;",
+        "3\n-4\n-3\n-4\n-3\ntrue\n");
+}
+
+#[test]
+fn exponent() {
+    assert_output(
+        "print 2 ** 10; print -2 ** 2; print (-2) ** 3; print 2 ** 3 ** 2; print 5 ** 0;",
+        "1024\n-4\n-8\n512\n1\n");
+}
+
+#[test]
+fn arithmetic_errors_instead_of_panicking() {
+    assert_output("print 1 / 0;", "error: Division by zero, line: 0\n");
+    assert_output("print 1 ~/ 0;", "error: Division by zero, line: 0\n");
+    assert_output("print 1 % 0;", "error: Modulo by zero, line: 0\n");
+    assert_output("print 2 ** -1;", "error: Exponent can't be negative: -1, line: 0\n");
+    assert_output("print 2 ** 64;", "error: Integer overflow with operator **, line: 0\n");
+}
+
+#[test]
+fn integer_overflow_is_an_error() {
+    let min = "(-9223372036854775807 - 1)";
+    assert_output("print 9223372036854775807 + 1;", "error: Integer overflow with operator +, line: 0\n");
+    assert_output(&format!("print {} - 1;", min), "error: Integer overflow with operator -, line: 0\n");
+    assert_output("print 4611686018427387904 * 2;", "error: Integer overflow with operator *, line: 0\n");
+    assert_output(&format!("print -{};", min), "error: Integer overflow with operator -, line: 0\n");
+    assert_output(&format!("print {} / -1;", min), "error: Integer overflow with operator /, line: 0\n");
+    assert_output(&format!("print {} ~/ -1;", min), "error: Integer overflow with operator ~/, line: 0\n");
+    assert_output(&format!("print {} % -1; print {} + 9223372036854775807;", min, min), "0\n-1\n");
+}
+
+#[test]
+fn bitwise_and_shift_operators() {
+    assert_output(
+        "print 12 & 10; print 12 | 10; print 12 ^ 10; print ~0; print ~5;
+         print 1 << 4; print -16 >> 2; print 255 >> 4 & 3; print (6 & 3) == 2;",
+        "8\n14\n6\n-1\n-6\n16\n-4\n3\ntrue\n");
+}
+
+#[test]
+fn bitwise_operators_need_numbers() {
+    assert_output("print 1 & true;", "error: Both sides of value must be the same type: Number, Bool, line: 0\n");
+    assert_output("print \"a\" | \"b\";", "error: Non-number values not supported with operator |, line: 0\n");
+    assert_output("print ~nil;", "error: Tilde can't be used with this value: Nil, line: 0\n");
+    assert_output("print 1 << 64;", "error: Shift amount must be between 0 and 63, not 64, line: 0\n");
+    assert_output("print 1 >> -1;", "error: Shift amount must be between 0 and 63, not -1, line: 0\n");
+}
+
+#[test]
+fn compound_assignment() {
+    assert_output(
+        "var a = 10; a += 5; print a; a -= 3; print a; a *= 2; print a; a /= 5; print a; a %= 3; print a;
+         print a += 1; var s = \"a\"; s += \"b\"; print s;
+         var xs = [1, 2]; xs[1] *= 10; print xs; var m = {\"k\": 1}; m[\"k\"] += 1; print m;",
+        "15\n12\n24\n4\n1\n2\nab\n[1, 20]\n{\"k\": 2}\n");
+}
+
+#[test]
+fn increment_and_decrement() {
+    assert_output(
+        "var i = 0; print i++; print i; print ++i; print i--; print --i;
+         var xs = [5]; print xs[0]++; print ++xs[0]; print xs;
+         for (var j = 0; j < 3; j++) print j;",
+        "0\n1\n2\n2\n0\n5\n7\n[7]\n0\n1\n2\n");
+}
+
+#[test]
+fn update_targets_are_evaluated_once() {
+    assert_output(
+        "var calls = 0;
+         var xs = [10, 20];
+         fun at(i) { calls++; return i; }
+         xs[at(1)] += 1; xs[at(0)]++; --xs[at(0)];
+         print xs; print calls;",
+        "[10, 21]\n3\n");
+}
+
+#[test]
+fn update_errors() {
+    assert_output("undefined += 1;", "error: Undefined variable: undefined, line: 0\n");
+    assert_output("var b = true; b++;", "error: Both sides of value must be the same type: Bool, Number, line: 0\n");
+    assert_output("var xs = []; xs[0] -= 1;", "error: Index 0 out of bounds for list of length 0, line: 0\n");
+    assert_output("var a = 1;\nundefined += 1;", "error: Undefined variable: undefined, line: 1\n");
+    assert_output("var a = 1;\nundefined = 1;", "error: Undefined variable: undefined, line: 1\n");
+}
+
+#[test]
+fn updates_check_for_overflow() {
+    let (max, min) = ("9223372036854775807", "(-9223372036854775807 - 1)");
+    assert_output(&format!("var i = {}; i++;", max), "error: Integer overflow with operator +, line: 0\n");
+    assert_output(&format!("var i = {}; ++i;", max), "error: Integer overflow with operator +, line: 0\n");
+    assert_output(&format!("var i = {}; i--;", min), "error: Integer overflow with operator -, line: 0\n");
+    assert_output(&format!("var xs = [{}]; xs[0] += 1;", max), "error: Integer overflow with operator +, line: 0\n");
+    assert_output(&format!("var i = {}; i -= 1;", min), "error: Integer overflow with operator -, line: 0\n");
+    assert_output(&format!("var i = {}; i *= 2;", max), "error: Integer overflow with operator *, line: 0\n");
+    assert_output(&format!("var i = {}; i /= -1;", min), "error: Integer overflow with operator /, line: 0\n");
+    assert_output(&format!("var i = {}; try {{ i++; }} catch (e) {{}} print i == {};", max, max), "true\n");
+}
+
+#[test]
+fn coalesce_only_evaluates_right_operand_for_nil() {
+    assert_output(
+        "print nil ?? 1; print false ?? undefined; print 0 ?? undefined; print nil ?? nil ?? \"last\";
+         var m = {\"a\": nil}; print m[\"a\"] ?? \"default\";",
+        "1\nfalse\n0\nlast\ndefault\n");
+}
+
+#[test]
+fn optional_chaining_short_circuits_the_whole_chain() {
+    assert_output(
+        "var xs = [1, 2]; var none = nil;
+         print xs?.len(); print none?.len(); print none?.len().foo[0];
+         print none?.len ?? \"missing\";
+         var calls = 0; fun f() { calls++; return 1; }
+         none?.push(f()); print calls;
+         print (none?.len)();",
+        "2\nnil\nnil\nmissing\n0\nerror: Can only call functions, not Nil, line: 5\n");
+}
+
+#[test]
+fn optional_chaining_still_reports_missing_properties() {
+    assert_output("print [1]?.size;", "error: Undefined property 'size' on List, line: 0\n");
+}
+
+#[test]
+fn match_statements_pick_the_first_matching_arm() {
+    assert_output(
+        "fun describe(x) {
+             match (x) {
+                 0 => print \"zero\";
+                 \"s\" => print \"string\";
+                 [a, 2] if a > 0 => print a;
+                 [_, _] => print \"pair\";
+                 {\"k\": v} => print v;
+                 nil => print \"nil\";
+                 other => print [other];
+             }
+         }
+         describe(0); describe(\"s\"); describe([1, 2]); describe([-1, 2]); describe({\"k\": 3, \"j\": 4});
+         describe(nil); describe(true);",
+        "zero\nstring\n1\npair\n3\nnil\n[true]\n");
+}
+
+#[test]
+fn match_expressions_produce_the_arm_value() {
+    assert_output(
+        "fun sign(n) { return match (n) { 0 => \"zero\", n if n < 0 => \"negative\", _ => \"positive\" }; }
+         print sign(-5); print sign(0); print sign(5);
+         print match ({\"x\": 1, \"y\": 2}) { {\"x\": x, \"y\": y} => x + y, _ => 0 };",
+        "negative\nzero\npositive\n3\n");
+}
+
+#[test]
+fn match_bindings_are_scoped_to_their_arm() {
+    assert_output(
+        "var a = \"outer\"; match (1) { a => print a; } print a;
+         match (2) { b if b > 5 => print b; _ => print b; }",
+        "1\nouter\nerror: Undefined variable: b, line: 1\n");
+}
+
+#[test]
+fn match_arms_can_break_and_return() {
+    assert_output(
+        "fun first_even(xs) {
+             for (var i = 0; i < xs.len(); i++) {
+                 match (xs[i] % 2) { 0 => return xs[i]; _ => continue; }
+             }
+         }
+         print first_even([1, 3, 4, 6]);
+         while (true) { match (1) { 1 => break; } }
+         print \"done\";",
+        "4\ndone\n");
+}
+
+#[test]
+fn match_without_a_matching_arm_is_an_error() {
+    assert_output("print match (3) { 1 => 1, 2 => 2 };", "error: No match arm matches 3, line: 0\n");
+}
+
+#[test]
+fn modules_run_once_and_share_their_exports() {
+    assert_output(
+        "import \"tests/modules/counter.lox\" as counter;
+         import \"tests/modules/bumper.lox\" as bumper;
+         import \"tests/modules/counter.lox\" as again;
+         print counter.increment(); print bumper.bump(); print again.count;
+         print counter == again; print counter;",
+        "running counter\n1\n2\n2\ntrue\n<module \"tests/modules/counter.lox\">\n");
+}
+
+#[test]
+fn modules_only_expose_their_exports() {
+    assert_output(
+        "import \"tests/modules/counter.lox\" as counter; print counter.hidden;",
+        "running counter\nerror: Module \"tests/modules/counter.lox\" doesn't export 'hidden', line: 0\n");
+}
+
+#[test]
+fn modules_are_found_on_the_search_path() {
+    assert_output_with_search_path(
+        "import \"greeting.lox\" as greeting; print greeting.greet(\"world\");",
+        &["tests/modules/lib"],
+        "hello world\n");
+    assert_output("import \"greeting.lox\" as greeting;", "error: Can't find module \"greeting.lox\", line: 0\n");
+}
+
+#[test]
+fn import_errors() {
+    assert_output(
+        "import \"tests/modules/cycle_a.lox\" as a;",
+        "error: Import cycle: \"tests/modules/cycle_a.lox\" -> \"cycle_b.lox\" -> \"cycle_a.lox\", line: 1\n");
+    assert_output(
+        "import \"tests/modules/broken.lox\" as broken;",
+        "error: In module \"tests/modules/broken.lox\": Expect 'var', 'const', 'fun' or 'enum' declaration after 'export', line: 0\n");
+    assert_output(
+        "import \"tests/modules/mistyped.lox\" as mistyped;",
+        "error: In module \"tests/modules/mistyped.lox\": Expected Number for 'x' but got String, line: 0\n");
+}
+
+#[test]
+fn static_checks_only_stop_programs_breaking_annotations() {
+    assert_analyzed_output(
+        "try { 1 + true; } catch (e) { print e.message; }",
+        "Warning: Operator + needs two numbers or two strings, not Number and Bool, line: 0\n\
+         Both sides of value must be the same type: Number, Bool\n");
+    assert_analyzed_output(
+        "var n: Number = \"one\"; print n;",
+        "Expected Number for 'n' but got String, line: 0\n");
+}
+
+#[test]
+fn thrown_values_are_caught_by_the_nearest_try() {
+    assert_output(
+        "fun fail(value) { throw value; }
+         try { fail({\"code\": 1}); print \"unreachable\"; } catch (e) { print e[\"code\"]; }
+         try {
+             try { fail(\"inner\"); } catch (e) { print e; throw e + \" again\"; }
+         } catch (e) { print e; }
+         throw [1, 2];",
+        "1\ninner\ninner again\nerror: Uncaught exception: [1, 2], line: 5\n");
+}
+
+#[test]
+fn runtime_errors_are_caught_as_error_objects() {
+    assert_output(
+        "fun divide(a, b) { return a / b; }
+         fun outer() { return divide(1, 0); }
+         try { outer(); } catch (e) { print e.message; print e.line; print e.stack; print e; }
+         try { undefined; } catch (e) { print e.message; print e.line; }
+         try { [1][3]; } catch (e) { print e.message; print e.stack; }
+         try { [].pop(); } catch (e) { print e.stack; }",
+        "Division by zero\n0\n[\"divide called on line 1\", \"outer called on line 2\"]\nError: Division by zero\n\
+         Undefined variable: undefined\n3\n\
+         Index 3 out of bounds for list of length 1\n[]\n\
+         [\"pop called on line 5\"]\n");
+}
+
+#[test]
+fn finally_runs_however_the_try_finishes() {
+    assert_output(
+        "fun returns() { try { return \"returned\"; } finally { print \"finally after return\"; } }
+         print returns();
+         while (true) { try { break; } finally { print \"finally after break\"; } }
+         try { try { throw 1; } finally { print \"finally after throw\"; } } catch (e) { print e; }
+         try { print \"no error\"; } catch (e) { print \"unreachable\"; } finally { print \"finally\"; }
+         try { throw 2; } catch (e) { throw 3; } finally { print \"finally after catch throws\"; }",
+        "finally after return\nreturned\nfinally after break\nfinally after throw\n1\nno error\nfinally\n\
+         finally after catch throws\nerror: Uncaught exception: 3, line: 5\n");
+}
+
+#[test]
+fn jumps_out_of_finally_replace_the_outcome() {
+    assert_output(
+        "fun f() { try { throw \"lost\"; } finally { return \"finally wins\"; } }
+         print f();
+         fun g() { try { return 1; } finally { return 2; } }
+         print g();",
+        "finally wins\n2\n");
+}
+
+#[test]
+fn constants_cant_be_assigned_at_runtime() {
+    assert_output(
+        "fun set() { limit = 20; }
+         const limit = 10; print limit;
+         { var limit = 1; limit = 2; print limit; }
+         try { set(); } catch (e) { print e.message; }
+         limit += 1;",
+        "10\n2\nCan't assign to constant 'limit'\nerror: Can't assign to constant 'limit', line: 4\n");
+}
+
+#[test]
+fn constants_cant_be_redeclared_by_later_repl_lines() {
+    for lower in [false, true].iter() {
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        let mut run_line = |source: &str| {
+            let mut scanner = Scanner::new(source.to_string());
+            let stmts = Parser::new(scanner.scan_tokens()).parse().expect("source should parse");
+            if *lower {
+                Lowerer::new().lower(&stmts).and_then(|core| interpreter.interpret_ir(&core))
+            } else {
+                interpreter.interpret(&stmts)
+            }
+        };
+        assert_eq!(run_line("const answer = 42;"), Ok(()));
+        assert_eq!(run_line("var answer = 0;"), Err("Can't redeclare constant 'answer', line: 0".to_string()));
+        assert_eq!(run_line("fun answer() {}"), Err("Can't redeclare constant 'answer', line: 0".to_string()));
+    }
+}
+
+#[test]
+fn type_annotations_dont_change_how_code_runs() {
+    assert_output(
+        "fun greet(name: String, times: Number): String {
+             var greeting: String = name;
+             for (var i: Number = 0; i < times; i++) greeting = \"hi \" + greeting;
+             return greeting;
+         }
+         const twice: Function = fun (f: Function, x: Any): Any { return f(f(x)); };
+         print greet(\"lox\", 2);
+         print twice(fun (n) { return n * 3; }, 2);",
+        "hi hi lox\n18\n");
+}
+
+#[test]
+fn generators_run_lazily_until_each_yield() {
+    assert_output(
+        "fun count(n) {
+             var i = 0;
+             while (i < n) { print [\"producing\", i]; yield i; i++; }
+         }
+         var g = count(2);
+         print g;
+         print \"created\";
+         print g.next();
+         print g.next();
+         print g.next();
+         print g.next();
+         var other = count(1);
+         print other.next()[\"value\"];",
+        "<generator count>\ncreated\n[\"producing\", 0]\n{\"value\": 0, \"done\": false}\n[\"producing\", 1]\n\
+         {\"value\": 1, \"done\": false}\n{\"value\": nil, \"done\": true}\n{\"value\": nil, \"done\": true}\n\
+         [\"producing\", 0]\n0\n");
+}
+
+#[test]
+fn generators_resume_inside_nested_loops_and_end_at_return() {
+    assert_output(
+        "fun pairs(n) {
+             for (var i = 0; i < n; i++) {
+                 for (var j = 0; j < n; j++) {
+                     if (j > i) break;
+                     if (i == 2) return \"ignored\";
+                     yield [i, j];
+                 }
+             }
+             yield \"unreachable\";
+         }
+         var g = pairs(5);
+         var step = g.next();
+         while (!step[\"done\"]) { print step[\"value\"]; step = g.next(); }
+         var naturals = fun () { var n = 0; while (true) { yield n; n++; } };
+         var numbers = naturals();
+         for (var taken = 0; taken < 3; taken++) print numbers.next()[\"value\"];",
+        "[0, 0]\n[1, 0]\n[1, 1]\n0\n1\n2\n");
+}
+
+#[test]
+fn generators_resume_inside_try_and_match_statements() {
+    assert_output(
+        "fun guarded() {
+             try { yield 1; throw \"boom\"; } catch (e) { yield \"caught \" + e; } finally { yield \"cleanup\"; }
+             match ([1, 2]) { [a, b] => { yield a + b; } _ => {} }
+             try { yield 4; } finally { print \"finally\"; }
+         }
+         var g = guarded();
+         for (var step = g.next(); !step[\"done\"]; step = g.next()) print step[\"value\"];",
+        "1\ncaught boom\ncleanup\n3\n4\nfinally\n");
+}
+
+#[test]
+fn generators_finish_when_they_fail() {
+    assert_output(
+        "fun failing() { yield 1; throw \"broken\"; }
+         var g = failing();
+         g.next();
+         try { g.next(); } catch (e) { print e; }
+         print g.next();
+         fun reentrant() { yield self.next(); }
+         var self = reentrant();
+         try { self.next(); } catch (e) { print e.message; print e.stack; }
+         print self.next()[\"done\"];",
+        "broken\n{\"value\": nil, \"done\": true}\nCan't resume a generator while it's running\n\
+         [\"reentrant called on line 7\"]\ntrue\n");
+}
+
+#[test]
+fn for_in_loops_iterate_over_lists_maps_and_strings() {
+    assert_output(
+        "for (x in [1, 2, 3, 4, 5]) { if (x == 2) continue; if (x == 4) break; print x; }
+         for (key in {\"a\": 1, \"b\": 2}) print key;
+         for (c in \"hi\") print c;
+         var xs = [1];
+         for (x in xs) if (x < 3) xs.push(x + 1);
+         print xs;
+         var it = [7, 8].iterator();
+         print it;
+         print it.next();
+         for (x in it) print x;
+         print it.next();",
+        "1\n3\na\nb\nh\ni\n[1, 2, 3]\n<list iterator>\n{\"value\": 7, \"done\": false}\n8\n\
+         {\"value\": nil, \"done\": true}\n");
+}
+
+#[test]
+fn for_in_loops_bind_a_fresh_variable_each_iteration() {
+    assert_output(
+        "var printers = [];
+         for (x in [1, 2, 3]) printers.push(fun () { print x; });
+         for (printer in printers) printer();
+         for (x in [1]) { x = 5; print x; }
+         for (x in 5) print x;",
+        "1\n2\n3\n5\nerror: Can't iterate over Number, line: 4\n");
+}
+
+#[test]
+fn for_in_loops_drive_generators() {
+    assert_output(
+        "fun flatten(lists) {
+             for (list in lists) for (x in list) yield x;
+         }
+         for (x in flatten([[1, 2], [], [3]])) print x;
+         fun evens() { var n = 0; while (true) { yield n; n += 2; } }
+         for (n in evens()) { if (n > 4) break; print n; }",
+        "1\n2\n3\n0\n2\n4\n");
+}
+
+#[test]
+fn ranges_are_lazy_and_can_be_stepped() {
+    assert_output(
+        "var r = 0..3;
+         print r;
+         print 1..=3;
+         print (0..10).step(3);
+         print r == 0..3;
+         for (i in r) print i;
+         for (i in (10..=0).step(-5)) print i;
+         var big = 0..1000000000000;
+         for (i in big) { if (i == 2) break; print i; }
+         var n = 3;
+         for (i in 0..n - 1) print i;",
+        "0..3\n1..=3\n(0..10).step(3)\ntrue\n0\n1\n2\n10\n5\n0\n0\n1\n0\n1\n");
+}
+
+#[test]
+fn ranges_slice_lists() {
+    assert_output(
+        "var xs = [\"a\", \"b\", \"c\", \"d\"];
+         print xs[1..3];
+         print xs[0..=3];
+         print xs[2..2];
+         print xs[(3..=0).step(-2)];
+         print xs[2..5];",
+        "[\"b\", \"c\"]\n[\"a\", \"b\", \"c\", \"d\"]\n[]\n[\"d\", \"b\"]\n\
+         error: Index 4 out of bounds for list of length 4, line: 5\n");
+}
+
+#[test]
+fn range_bounds_and_steps_are_validated() {
+    assert_output("print 0..\"a\";", "error: Range bounds must be numbers, not Number and String, line: 0\n");
+    assert_output("print (0..1).step(0);", "error: Range step can't be zero, line: 0\n");
+    assert_output("print (0..1).step(nil);", "error: Range step must be a number, not Nil, line: 0\n");
+}
+
+#[test]
+fn declarations_destructure_lists_and_maps() {
+    assert_output(
+        "var [a, b] = [1, 2];
+         print a + b;
+         var {x, y} = {\"y\": 4, \"x\": 3, \"z\": 5};
+         print [x, y];
+         const [first, [_, {\"k\": inner}]] = [1, [2, {\"k\": 3}]];
+         print inner;
+         fun f() { var [head, tail] = [\"h\", \"t\"]; return tail; }
+         print f();",
+        "3\n[3, 4]\n3\nt\n");
+}
+
+#[test]
+fn assignments_destructure_into_existing_variables() {
+    assert_output(
+        "var a = 1;
+         var b = 2;
+         print [a, b] = [b, a];
+         print [a, b];
+         var m = {};
+         {\"k\": m} = {\"k\": 5};
+         print m;",
+        "[2, 1]\n[2, 1]\n5\n");
+}
+
+#[test]
+fn destructuring_values_of_the_wrong_shape_is_an_error() {
+    assert_output("var [a, b] = [1];", "error: Expected 2 elements to destructure but got 1, line: 0\n");
+    assert_output("var [a] = {};", "error: Can't destructure {} as a list, line: 0\n");
+    assert_output("var {a} = [1];", "error: Can't destructure [1] as a map, line: 0\n");
+    assert_output("var {a} = {\"b\": 1};", "error: Key \"a\" not found in map, line: 0\n");
+    assert_output("var a = 1;\n var b = 2;\n [a, b] = [3];\n print a;", "error: Expected 2 elements to destructure but got 1, line: 2\n");
+}
+
+#[test]
+fn rest_parameters_collect_extra_arguments() {
+    assert_output(
+        "fun log(level, ...args) { print [level, args]; }
+         log(\"info\");
+         log(\"warn\", 1, 2);
+         var f = fun (...all) { return all; };
+         print f();
+         log();",
+        "[\"info\", []]\n[\"warn\", [1, 2]]\n[]\nerror: Missing argument for parameter 'level', line: 5\n");
+}
+
+#[test]
+fn spread_expands_iterables_into_lists_and_arguments() {
+    assert_output(
+        "var a = [1, 2];
+         var b = [3];
+         print [...a, ...b, 4];
+         print [0, ...1..3, ...\"ab\"];
+         fun g() { yield 5; yield 6; }
+         fun add(x, y) { return x + y; }
+         print add(...g());
+         print add(1, ...b);
+         fun log(level, ...args) { print args; }
+         log(...a, ...a);",
+        "[1, 2, 3, 4]\n[0, 1, 2, \"a\", \"b\"]\n11\n4\n[2, 1, 2]\n");
+}
+
+#[test]
+fn spread_arguments_are_checked_after_spreading() {
+    assert_output("fun add(x, y) { return x + y; }\n add(...[1, 2, 3]);", "error: Expected 2 arguments but got 3, line: 1\n");
+    assert_output("fun f(...xs) { return xs; }\n print f(...0..255)[254];\n f(...0..256);",
+                  "254\nerror: Can't have more than 255 arguments but got 256, line: 2\n");
+    assert_output("print [...nil];", "error: Can't iterate over Nil, line: 0\n");
+}
+
+#[test]
+fn defaults_are_evaluated_at_call_time_in_the_callee_scope() {
+    assert_output(
+        "fun connect(host, port = 8080) { print [host, port]; }
+         connect(\"a\");
+         connect(\"b\", 1);
+         var calls = 0;
+         fun count() { calls = calls + 1; return calls; }
+         fun f(a, b = count(), c = a + b) { return [a, b, c]; }
+         print f(10);
+         print f(10);
+         fun fresh(xs = []) { xs.push(1); return xs; }
+         print fresh();
+         print fresh();
+         fun g(first, second = first, ...rest) { yield [first, second, rest]; }
+         print [...g(1)];
+         print [...g(1, 2, 3)];",
+        "[\"a\", 8080]\n[\"b\", 1]\n[10, 1, 11]\n[10, 2, 12]\n[1]\n[1]\n[[1, 1, []]]\n[[1, 2, [3]]]\n");
+}
+
+#[test]
+fn named_arguments_fill_parameters_by_name() {
+    assert_output(
+        "fun connect(host, port = 8080, secure = false) { print [host, port, secure]; }
+         connect(port: 9000, host: \"x\");
+         connect(\"y\", secure: true);
+         var f = fun (a, b) { return a - b; };
+         print f(b: 1, a: 3);",
+        "[\"x\", 9000, false]\n[\"y\", 8080, true]\n2\n");
+}
+
+#[test]
+fn arity_errors_name_the_parameter() {
+    assert_output("fun connect(host, port = 1) {}\n connect(port: 2);", "error: Missing argument for parameter 'host', line: 1\n");
+    assert_output("fun connect(host, port = 1) {}\n connect(1, 2, 3);", "error: Expected at most 2 arguments but got 3, line: 1\n");
+    assert_output("fun connect(host, port = 1) {}\n connect(1, timeout: 2);", "error: Unknown parameter 'timeout', line: 1\n");
+    assert_output("fun connect(host, port = 1) {}\n connect(1, host: 2);",
+                  "error: Got more than one argument for parameter 'host', line: 1\n");
+    assert_output("[].push(value: 1);", "error: Can't pass named arguments to push, line: 0\n");
+}
+
+#[test]
+fn enum_variants_are_distinct_singletons() {
+    assert_output(
+        "enum Color { Red, Green, Blue }
+         print Color.Red;
+         print Color;
+         print [Color.Green, {\"c\": Color.Blue}];
+         var red = Color.Red;
+         print red == Color.Red;
+         print Color.Red == Color.Green;
+         print Color.Red == \"Color.Red\";
+         fun shade() { enum Color { Red } return Color.Red; }
+         print shade() == Color.Red;
+         print shade() == shade();",
+        "Color.Red\n<enum Color>\n[Color.Green, {\"c\": Color.Blue}]\ntrue\nfalse\nfalse\nfalse\nfalse\n");
+    assert_output("enum Color { Red }\n print Color.Purple;", "error: Enum Color has no variant 'Purple', line: 1\n");
+}
+
+#[test]
+fn enums_iterate_over_their_variants_in_order() {
+    assert_output(
+        "enum Color { Red, Green, Blue }
+         for (c in Color) print c;
+         print [...Color];",
+        "Color.Red\nColor.Green\nColor.Blue\n[Color.Red, Color.Green, Color.Blue]\n");
+}
+
+#[test]
+fn variants_work_as_match_patterns() {
+    assert_output(
+        "enum Color { Red, Green, Blue }
+         fun name(c) {
+             return match (c) {
+                 Color.Red => \"red\",
+                 [Color.Green, n] => \"green \" + n,
+                 _ => \"other\",
+             };
+         }
+         print name(Color.Red);
+         print name([Color.Green, \"light\"]);
+         print name(Color.Blue);
+         print name(\"Color.Red\");
+         match (Color.Blue) { Color.Green => print 1; Color.Blue => print 2; }",
+        "red\ngreen light\nother\nother\n2\n");
+    assert_output("match (1) { Shade.Red => print 1; }", "error: Undefined variable: Shade, line: 0\n");
+}
+
+#[test]
+fn enums_can_be_exported() {
+    assert_output(
+        "import \"tests/modules/colors.lox\" as colors;
+         import \"tests/modules/colors.lox\" as again;
+         const Color = colors.Color;
+         print Color.Green;
+         print again.Color.Green == Color.Green;
+         match (Color.Green) { Color.Red => print \"red\"; Color.Green => print \"green\"; }",
+        "Color.Green\ntrue\ngreen\n");
+}